@@ -0,0 +1,68 @@
+//! Author pages, listing the posts of each author, e.g. authors/jane-doe/index.html.
+//! They are rendered with the author.html layout, if the site has one.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::AuthorConfig;
+use crate::post::Post;
+use crate::render::Error;
+use crate::url;
+use crate::util;
+
+/// The directory the author pages are written to
+pub const AUTHORS_DIR: &str = "authors";
+/// The layout for author pages
+pub const TEMPLATE: &str = "author.html";
+
+/// An author, as the author templates get it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Author {
+    pub slug: String,
+    /// The name from the config, or the author as written in the frontmatter of their first post
+    pub name: String,
+    pub bio: Option<String>,
+    pub avatar: Option<String>,
+    /// The url of the author's page
+    pub url: PathBuf,
+}
+
+/// The posts of each author, keyed by the slug of the author, in the order of posts.
+/// Authors that only differ in case or punctuation, like "Jane Doe" and "jane-doe", are the same author.
+/// Their details come from authors, the [authors] config, by slug.
+pub fn posts_by_author<'a>(
+    posts: &'a [Post],
+    authors: &BTreeMap<String, AuthorConfig>,
+    html_extension: &str,
+) -> Result<BTreeMap<String, (Author, Vec<&'a Post>)>, Error> {
+    let mut by_author: BTreeMap<String, (Author, Vec<&Post>)> = BTreeMap::new();
+    for post in posts {
+        for name in &post.authors {
+            let slug = util::slugify(name)?;
+            let (_, authored) = by_author.entry(slug.clone()).or_insert_with(|| {
+                let config = authors.get(&slug).cloned().unwrap_or_default();
+                let author = Author {
+                    url: url::url_from_output_path(&output_path(&slug, html_extension)),
+                    name: config.name.unwrap_or(name.clone()),
+                    bio: config.bio,
+                    avatar: config.avatar,
+                    slug,
+                };
+                (author, vec![])
+            });
+            if !authored.iter().any(|p| p.input_path == post.input_path) {
+                authored.push(post);
+            }
+        }
+    }
+    Ok(by_author)
+}
+
+/// Where the page of an author is written, relative to the output directory, e.g. authors/jane-doe/index.html
+pub fn output_path(slug: &str, html_extension: &str) -> PathBuf {
+    url::with_html_extension(
+        &Path::new(AUTHORS_DIR).join(slug).join("index.html"),
+        html_extension,
+    )
+}
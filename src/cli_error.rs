@@ -0,0 +1,117 @@
+//! The errors that stop a kalamos command, reported as text or, with `--errors json`, as JSON for scripts.
+//! Each kind of error exits with its own code, so scripts can tell them apart without parsing the message.
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{config, deploy, render};
+
+/// What kind of error stopped a command. This is the stable error_type of the JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorType {
+    /// The arguments were wrong, e.g. --drafts-to is the output directory
+    Usage,
+    /// config.toml couldn't be read or parsed
+    Config,
+    /// The site couldn't be rendered, e.g. a template or frontmatter error
+    Render,
+    /// The development server failed
+    Serve,
+    /// Watching the input directory failed
+    Watch,
+    /// Uploading to S3 or invalidating CloudFront failed
+    Deploy,
+    /// Reading or writing a file failed outside of a render, e.g. in `kalamos new`
+    Io,
+}
+
+impl ErrorType {
+    /// The exit code of the process. 1 is left for panics and failed checks, like `kalamos doctor`'s.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Usage => 2,
+            Self::Config => 3,
+            Self::Render => 4,
+            Self::Serve => 5,
+            Self::Watch => 6,
+            Self::Deploy => 7,
+            Self::Io => 8,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Usage => "usage",
+            Self::Config => "config",
+            Self::Render => "render",
+            Self::Serve => "serve",
+            Self::Watch => "watch",
+            Self::Deploy => "deploy",
+            Self::Io => "io",
+        }
+    }
+}
+
+/// An error that stops a command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandError {
+    pub error_type: ErrorType,
+    pub message: String,
+}
+
+impl CommandError {
+    pub fn new(error_type: ErrorType, message: impl fmt::Display) -> Self {
+        Self {
+            error_type,
+            message: message.to_string(),
+        }
+    }
+
+    /// The error as a single line of JSON, e.g. {"error_type":"render","message":"..."}
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("an error should serialize")
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error: {}", self.error_type.as_str(), self.message)
+    }
+}
+
+impl From<config::ConfigError> for CommandError {
+    fn from(e: config::ConfigError) -> Self {
+        let message = match e {
+            config::ConfigError::IoError(e) => e.to_string(),
+            config::ConfigError::TomlError(e) => e.to_string(),
+            config::ConfigError::DuplicateRoute(a, b) => {
+                format!("[serve] routes {:?} and {:?} are the same route", a, b)
+            }
+        };
+        Self::new(
+            ErrorType::Config,
+            format!("config.toml: {}", message.trim_end()),
+        )
+    }
+}
+
+/// A render that failed because of the config is a config error
+impl From<render::Error> for CommandError {
+    fn from(e: render::Error) -> Self {
+        match e {
+            render::Error::Config(e) => e.into(),
+            e => Self::new(ErrorType::Render, e),
+        }
+    }
+}
+
+/// A deploy that failed while generating the site is a render error
+impl From<deploy::Error> for CommandError {
+    fn from(e: deploy::Error) -> Self {
+        match e {
+            deploy::Error::RenderError(e) => e.into(),
+            e => Self::new(ErrorType::Deploy, e),
+        }
+    }
+}
@@ -1,18 +1,149 @@
-use std::{fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
 
 use serde::{Deserialize, Serialize};
 
+/// The directory that themes are in, relative to the root of the site
+pub const THEMES_DIR: &str = "themes";
+
 /// The configuration for the site.
-/// Currently, only the deploy section is used.
 /// An example config.toml would look like this:
 /// ```toml
+/// base_url = "https://your.domain.com" // The url the site is served from, used for absolute urls like the sitemap in robots.txt
+/// languages = ["en", "es"] // Directories in posts/ and pages/ with these names hold the content in that language.
+/// theme = "mytheme" // A theme in themes/mytheme, providing layouts/ and static/. The site's own files override the theme's.
+///
 /// [deploy]
 /// strategy = "s3_and_cloudfront" // The deploy strategy to use. Currently, only s3_and_cloudfront is supported.
 /// bucket = "your.domain.com" // This is the name of the bucket in s3 and also the domain name that you want to use for your site.
+/// max_invalidation_paths = 25 // Invalidate everything in CloudFront, instead of just the changed paths, when more paths than this changed.
+/// connect_timeout_secs = 10 // Give up connecting to AWS after this many seconds.
+/// attempt_timeout_secs = 120 // Give up on an attempt at a request to AWS, like uploading a file, after this many seconds.
+/// max_attempts = 3 // Try each request to AWS this many times before failing the deploy.
+/// profile = "client-a" // The AWS profile to deploy with. Defaults to AWS_PROFILE or the default credentials.
+///
+/// [related_posts]
+/// enabled = true // Set this to false to skip computing related posts on very large sites.
+/// limit = 5 // The maximum number of related posts for each post.
+///
+/// [build]
+/// html_extension = "html" // The extension of the files written for posts and pages, e.g. "htm", or "" for none.
+/// line_endings = "lf" // The line endings of rendered posts and pages: "lf" or "crlf".
+///
+/// [pages]
+/// flat_root = false // Set this to true to read pages from the root of the site instead of the pages directory.
+///
+/// [serve]
+/// routes = { "feed" = "atom.xml" } // Extensionless routes served by `kalamos serve`, mapped to files in the output directory.
+/// index_files = ["index.html", "index.htm"] // The files to serve for a directory, tried in order.
+/// error_pages = { "404" = "40x.html", "500" = "50x.html" } // The page to serve for an error status, relative to the output directory.
+/// autoindex = false // Set this to true to serve a list of the files in directories without an index file.
+///
+/// [[variants]] // Each post is also rendered once for each variant, e.g. an AMP version.
+/// name = "amp"
+/// template = "amp" // The template for the variant, without the .html.
+/// path_prefix = "amp" // The variant of /2024/12/slug.html is written to /amp/2024/12/slug.html.
+///
+/// [feeds]
+/// tags = true // Also render the feed page for each tag, with only the posts with that tag, e.g. to tags/rust/atom.xml.
+/// page = "atom.xml" // The output path of the feed page, e.g. pages/atom.xml. Defaults to atom.xml.
+/// extra_pages = ["rss.xml", "feed.json"] // Other feed pages, which get the same limit as page.
+/// limit = 20 // The feed pages get only the most recent posts, this many of them. 0 puts every post in the feeds.
+///
+/// [robots]
+/// disallow = ["/drafts/"] // Paths that crawlers should not visit. A robots.txt is generated if this section or base_url is set.
+///
+/// [authors.jane-doe] // Details of the author with the slug jane-doe, for their page at /authors/jane-doe/, if there is an author.html layout.
+/// name = "Jane Doe" // Defaults to the author as written in the frontmatter.
+/// bio = "Jane writes about Rust."
+/// avatar = "/images/jane.png"
+///
+/// [sitemap]
+/// exclude = ["tags/**", "archive/*.html"] // Globs of output paths to leave out of the sitemap list, on top of 404.html.
+///
+/// [favicon]
+/// source = "icon.png" // A square PNG icon, relative to the root of the site. It is resized to 16, 32, 180, 192 and 512 pixels, listed in a site.webmanifest.
+/// name = "My Site" // The name of the site in the manifest.
+/// short_name = "Site" // The short name in the manifest. Defaults to name.
+/// theme_color = "#ffffff"
+/// background_color = "#ffffff"
+///
+/// [markdown]
+/// smart_punctuation = false // Set this to true to convert quotes to curly quotes, -- and --- to dashes and ... to ellipses.
+/// math = false // Set this to true to wrap $inline$ and $$display$$ math in elements that KaTeX's auto-render can find.
+/// excerpt_separator = "<!--excerpt-->" // The text that ends the excerpt of a post, instead of <!--more-->.
+///
+/// [highlight]
+/// classes = false // Set this to true to highlight code with css classes instead of inline styles. The styles are written to highlight.css.
+/// class_prefix = "hl-" // The prefix of the css classes used for highlighting.
+///
+/// [templates]
+/// post = "post" // The template for posts that don't set one in their frontmatter.
+/// page = "default" // The template for pages that don't set one in their frontmatter.
+/// base = "base" // Put the body of markdown pages that don't set a template in the content block of base.html, instead of using page.
+/// dir = "themes/mytheme/templates" // The directory the templates are loaded from. Defaults to "layouts".
+/// extensions = ["html", "tera"] // The extensions of the template files. Defaults to ["html"].
+/// date_format = "%B %-d, %Y" // The strftime format of date_formatted for posts, e.g. December 1, 2024. Defaults to "%Y-%m-%d".
+///
+/// [copy]
+/// map = { "assets" = "static" } // Copy static/assets/ to /static/ instead of /assets/.
+///
+/// [mime]
+/// overrides = { "webmanifest" = "application/manifest+json" } // MIME types to use for file extensions, used by serve and deploy.
+///
+/// [context] // Values for every template, under config, e.g. {{ config.analytics_id }}. They can't be overridden by a file's frontmatter.
+/// analytics_id = "G-12345"
+/// show_banner = true
+///
+/// [schema.post] // Checks on the frontmatter of every post, on top of the built-in fields. [schema.page] does the same for pages.
+/// required = ["author"] // Fields every post must set, possibly through _defaults.toml or a sidecar.
+/// types = { author = "string", rating = "integer" } // The type a field must have when it's set: string, integer, float, boolean, datetime, array or table.
 /// ```
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// The name of a directory in themes/ with the layouts and static files to use when the site doesn't have its own
+    pub theme: Option<String>,
     pub deploy: Option<DeployConfig>,
+    #[serde(default)]
+    pub related_posts: RelatedPostsConfig,
+    #[serde(default)]
+    pub mime: MimeConfig,
+    #[serde(default)]
+    pub pages: PagesConfig,
+    #[serde(default)]
+    pub serve: ServeConfig,
+    #[serde(default)]
+    pub feeds: FeedsConfig,
+    pub robots: Option<RobotsConfig>,
+    #[serde(default)]
+    pub sitemap: SitemapConfig,
+    /// The details of the authors of posts, by the slug of the author
+    #[serde(default)]
+    pub authors: BTreeMap<String, AuthorConfig>,
+    pub favicon: Option<FaviconConfig>,
+    #[serde(default)]
+    pub variants: Vec<VariantConfig>,
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    #[serde(default)]
+    pub highlight: HighlightConfig,
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    #[serde(default)]
+    pub copy: CopyConfig,
+    #[serde(default)]
+    pub schema: SchemaConfig,
+    #[serde(default)]
+    pub build: BuildConfig,
+    /// Global values added to the context of every template, under config
+    #[serde(default)]
+    pub context: toml::Table,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -25,12 +156,440 @@ pub enum DeployStrategy {
 pub struct DeployConfig {
     pub strategy: DeployStrategy,
     pub bucket: String,
+    #[serde(default = "DeployConfig::default_max_invalidation_paths")]
+    pub max_invalidation_paths: usize,
+    /// How long to wait to connect to AWS, in seconds
+    #[serde(default = "DeployConfig::default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// How long each attempt at a request to AWS can take, in seconds, e.g. uploading a file
+    #[serde(default = "DeployConfig::default_attempt_timeout_secs")]
+    pub attempt_timeout_secs: u64,
+    /// How many times to try each request to AWS before giving up
+    #[serde(default = "DeployConfig::default_max_attempts")]
+    pub max_attempts: u32,
+    /// The named AWS profile to deploy with, instead of the default credentials
+    pub profile: Option<String>,
+}
+
+impl DeployConfig {
+    fn default_max_invalidation_paths() -> usize {
+        25
+    }
+
+    fn default_connect_timeout_secs() -> u64 {
+        10
+    }
+
+    fn default_attempt_timeout_secs() -> u64 {
+        120
+    }
+
+    fn default_max_attempts() -> u32 {
+        3
+    }
+}
+
+/// Related posts are the posts that share the most tags with a post.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelatedPostsConfig {
+    pub enabled: bool,
+    pub limit: usize,
+}
+
+impl Default for RelatedPostsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            limit: 5,
+        }
+    }
+}
+
+/// Configuration for the files the build writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildConfig {
+    /// The extension of the html files written for posts and pages, without the leading ., e.g. htm.
+    /// An empty extension writes files without one, for hosts that serve them as html.
+    pub html_extension: String,
+    /// The line endings that rendered posts and pages are normalized to
+    pub line_endings: LineEndings,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            html_extension: "html".to_string(),
+            line_endings: LineEndings::default(),
+        }
+    }
+}
+
+/// The line endings of rendered files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// \n
+    #[default]
+    Lf,
+    /// \r\n
+    Crlf,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PagesConfig {
+    /// Read pages from the root of the site instead of the pages directory.
+    /// The directories that kalamos uses for other things, like posts and layouts, are skipped.
+    pub flat_root: bool,
+}
+
+/// Configuration for the development server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// A map of request path to the file in the output directory to serve for it, e.g. "feed" = "atom.xml".
+    /// Use this to match the rewrite rules of your production server.
+    pub routes: HashMap<String, String>,
+    /// The file names to try, in order, when a request is for a directory, like nginx's index directive
+    pub index_files: Vec<String>,
+    /// A map of HTTP status code to the page in the output directory to serve with it, e.g. "500" = "50x.html".
+    /// Without one, 404s are served with 404.html, if it exists.
+    pub error_pages: HashMap<String, String>,
+    /// List the files in directories that don't have an index file, like nginx's autoindex
+    pub autoindex: bool,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            index_files: vec!["index.html".to_string()],
+            error_pages: HashMap::new(),
+            autoindex: false,
+        }
+    }
+}
+
+impl ServeConfig {
+    /// The serve config for a site written with html_extension, so that its index files and 404 page,
+    /// e.g. index.htm and 404.htm, or index and 404, are found. They are tried after the configured ones.
+    pub fn with_html_extension(&self, html_extension: &str) -> Self {
+        let with_extension = |stem: &str| match html_extension {
+            "" => stem.to_string(),
+            extension => format!("{}.{}", stem, extension),
+        };
+        let mut config = self.clone();
+        let index_file = with_extension("index");
+        if !config.index_files.contains(&index_file) {
+            config.index_files.push(index_file);
+        }
+        if html_extension != "html" {
+            config
+                .error_pages
+                .entry("404".to_string())
+                .or_insert_with(|| with_extension("404"));
+        }
+        config
+    }
+
+    /// Routes are matched with leading and trailing slashes trimmed, so two routes that only differ in them
+    /// would make the file served for a request depend on the order of the routes map. Reject them instead.
+    fn check_routes(&self) -> Result<(), ConfigError> {
+        let mut routes = self.routes.keys().collect::<Vec<_>>();
+        routes.sort();
+        let mut seen: HashMap<&str, &String> = HashMap::new();
+        for route in routes {
+            if let Some(other) = seen.insert(route.trim_matches('/'), route) {
+                return Err(ConfigError::DuplicateRoute(
+                    other.to_string(),
+                    route.to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Another version of every post, rendered with a different template to a different path
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VariantConfig {
+    pub name: String,
+    /// The template to render the posts with, without the .html
+    pub template: String,
+    /// The directory the variant is written to, in front of the post's own output path
+    pub path_prefix: String,
+}
+
+impl VariantConfig {
+    /// Where the variant of a post with the given output path is written, relative to the output directory
+    pub fn output_path(&self, output_path: &Path) -> std::path::PathBuf {
+        Path::new(self.path_prefix.trim_matches('/')).join(output_path)
+    }
+}
+
+/// Configuration for the feeds of some of the posts, like the feed for each tag
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedsConfig {
+    /// Render a feed for each tag. This is off by default, as a site with many tags would get many feeds.
+    pub tags: bool,
+    /// The output path of the page that is the site's feed. It is rendered again for each tag.
+    pub page: String,
+    /// The output paths of other feed pages, e.g. rss.xml and feed.json. They aren't rendered for each tag.
+    pub extra_pages: Vec<String>,
+    /// How many of the most recent posts the feed pages get as posts. 0 means all of them.
+    pub limit: usize,
+}
+
+impl FeedsConfig {
+    /// True if the page written to output_path is one of the feeds
+    pub fn is_feed(&self, output_path: &Path) -> bool {
+        output_path == Path::new(&self.page)
+            || self
+                .extra_pages
+                .iter()
+                .any(|page| output_path == Path::new(page))
+    }
+}
+
+impl Default for FeedsConfig {
+    fn default() -> Self {
+        Self {
+            tags: false,
+            page: "atom.xml".to_string(),
+            extra_pages: vec![],
+            limit: 20,
+        }
+    }
+}
+
+/// Configuration for the generated robots.txt
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RobotsConfig {
+    /// Paths that all crawlers are asked not to visit
+    pub disallow: Vec<String>,
+}
+
+/// The details of an author, shown on their author page
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthorConfig {
+    pub name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar: Option<String>,
+}
+
+/// Configuration for the list of pages that templates get for the sitemap. See sitemap::entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SitemapConfig {
+    /// Globs of output paths to leave out, e.g. tags/**
+    pub exclude: Vec<String>,
+}
+
+/// Configuration for the favicon and web app manifest. They are only generated if there is a [favicon] section
+/// and the source icon exists.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaviconConfig {
+    /// The icon, relative to the root of the site
+    pub source: String,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub theme_color: String,
+    pub background_color: String,
+}
+
+impl Default for FaviconConfig {
+    fn default() -> Self {
+        Self {
+            source: "icon.png".to_string(),
+            name: String::new(),
+            short_name: None,
+            theme_color: "#ffffff".to_string(),
+            background_color: "#ffffff".to_string(),
+        }
+    }
+}
+
+/// Configuration for converting markdown to html
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    /// Use typographic punctuation in prose: curly quotes, en and em dashes and ellipses.
+    /// Code is left alone.
+    pub smart_punctuation: bool,
+    /// Recognize $inline$ and $$display$$ math, and wrap it for KaTeX or MathJax to render in the browser.
+    /// The math itself is left alone by the rest of the markdown processing.
+    pub math: bool,
+    /// The text that ends the excerpt, instead of <!--more-->. It is matched exactly, and removed from the body.
+    pub excerpt_separator: Option<String>,
+}
+
+/// Configuration for the syntax highlighting of code blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// Use css classes instead of inline styles, and write a stylesheet for them to highlight.css
+    pub classes: bool,
+    /// The prefix of the css classes, e.g. hl-code and hl-keyword
+    pub class_prefix: String,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            classes: false,
+            class_prefix: "hl-".to_string(),
+        }
+    }
+}
+
+/// Where the templates are loaded from, and the default templates for each type of content,
+/// used when the frontmatter doesn't set a template.
+/// If they aren't set, Post::DEFAULT_TEMPLATE and Page::DEFAULT_TEMPLATE are used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplatesConfig {
+    pub post: Option<String>,
+    pub page: Option<String>,
+    /// A layout to put the body of markdown pages in, in its content block, for pages that don't set a template.
+    /// This takes precedence over page, so the layout doesn't need a template that extends it for each page.
+    pub base: Option<String>,
+    /// The directory holding the templates, relative to the root of the site
+    pub dir: String,
+    /// The extensions of the files in dir that are templates
+    pub extensions: Vec<String>,
+    /// The strftime format of date_formatted in the context of posts, e.g. "%B %-d, %Y" for December 1, 2024.
+    /// Defaults to %Y-%m-%d.
+    pub date_format: Option<String>,
+}
+
+/// The frontmatter schemas for posts and pages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchemaConfig {
+    pub post: FrontmatterSchema,
+    pub page: FrontmatterSchema,
+}
+
+/// Fields that a kind of content must have in its frontmatter, and the types of its fields.
+/// This is for the site's own fields, like author. The built-in fields, like title and date, are always checked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrontmatterSchema {
+    pub required: Vec<String>,
+    /// The type of each field, checked when the field is set
+    pub types: BTreeMap<String, FieldType>,
+}
+
+/// The type of a frontmatter field, as TOML sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Datetime,
+    Array,
+    Table,
+}
+
+impl FieldType {
+    fn of(value: &toml::Value) -> Self {
+        match value {
+            toml::Value::String(_) => Self::String,
+            toml::Value::Integer(_) => Self::Integer,
+            toml::Value::Float(_) => Self::Float,
+            toml::Value::Boolean(_) => Self::Boolean,
+            toml::Value::Datetime(_) => Self::Datetime,
+            toml::Value::Array(_) => Self::Array,
+            toml::Value::Table(_) => Self::Table,
+        }
+    }
+}
+
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Boolean => "boolean",
+            Self::Datetime => "datetime",
+            Self::Array => "array",
+            Self::Table => "table",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FrontmatterSchema {
+    /// Describe each way the frontmatter breaks the schema, naming the field, e.g. "`rating` should be of type integer, not string".
+    /// Missing fields come first, in the order they are required, and then the fields with the wrong type, by name.
+    pub fn violations(&self, frontmatter: &toml::Table) -> Vec<String> {
+        let missing = self
+            .required
+            .iter()
+            .filter(|field| !frontmatter.contains_key(field.as_str()))
+            .map(|field| format!("`{}` is required", field));
+        let mistyped = self.types.iter().filter_map(|(field, expected)| {
+            let actual = FieldType::of(frontmatter.get(field)?);
+            (actual != *expected)
+                .then(|| format!("`{}` should be of type {}, not {}", field, expected, actual))
+        });
+        missing.chain(mistyped).collect()
+    }
+}
+
+impl Config {
+    /// The directory of the theme, if the config sets one
+    pub fn theme_dir(&self, root_dir: &Path) -> Option<std::path::PathBuf> {
+        self.theme
+            .as_ref()
+            .map(|theme| root_dir.join(THEMES_DIR).join(theme))
+    }
+}
+
+impl Default for TemplatesConfig {
+    fn default() -> Self {
+        Self {
+            post: None,
+            page: None,
+            base: None,
+            dir: "layouts".to_string(),
+            extensions: vec!["html".to_string()],
+            date_format: None,
+        }
+    }
+}
+
+/// Configuration for copying the static directory to the output directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CopyConfig {
+    /// A map of directory in static/ to the directory in the output directory to copy it to
+    pub map: HashMap<String, String>,
+}
+
+/// MIME types to use instead of the ones guessed from a file's extension
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MimeConfig {
+    /// A map of file extension (without the leading .) to MIME type
+    pub overrides: HashMap<String, String>,
 }
 
 #[derive(Debug)]
 pub enum ConfigError {
     IoError(std::io::Error),
     TomlError(toml::de::Error),
+    /// Two [serve] routes that are the same once leading and trailing slashes are trimmed, e.g. "/docs" and "docs/"
+    DuplicateRoute(String, String),
 }
 
 impl Config {
@@ -41,6 +600,7 @@ impl Config {
         }
         let config_str = fs::read_to_string(config_path).map_err(ConfigError::IoError)?;
         let config: Config = toml::from_str(&config_str).map_err(ConfigError::TomlError)?;
+        config.serve.check_routes()?;
         Ok(Some(config))
     }
 }
@@ -0,0 +1,125 @@
+//! A post or a page, for code that treats all of the rendered content of a site the same way,
+//! e.g. to build a search index or navigation.
+use std::path::Path;
+use tera::{Context, Tera};
+
+use crate::page::Page;
+use crate::post::Post;
+use crate::render::{Error, Render};
+
+/// Posts and pages are boxed, as both are large and Post is more than twice the size of Page
+#[derive(Debug)]
+pub enum Content {
+    Post(Box<Post>),
+    Page(Box<Page>),
+}
+
+impl Content {
+    /// "post" or "page"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Content::Post(_) => "post",
+            Content::Page(_) => "page",
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            Content::Post(post) => &post.title,
+            Content::Page(page) => &page.title,
+        }
+    }
+
+    /// The url of the rendered file, with a leading /
+    pub fn url(&self) -> &Path {
+        match self {
+            Content::Post(post) => &post.url,
+            Content::Page(page) => &page.url,
+        }
+    }
+
+    /// The source file, relative to the root of the site
+    pub fn input_path(&self) -> &Path {
+        match self {
+            Content::Post(post) => &post.input_path,
+            Content::Page(page) => &page.input_path,
+        }
+    }
+
+    /// The rendered file, relative to the output directory
+    pub fn output_path(&self) -> &Path {
+        match self {
+            Content::Post(post) => &post.output_path,
+            Content::Page(page) => &page.output_path,
+        }
+    }
+
+    /// The rendered html of the content
+    pub fn content(&self) -> &str {
+        match self {
+            Content::Post(post) => &post.content,
+            Content::Page(page) => &page.content,
+        }
+    }
+
+    pub fn draft(&self) -> bool {
+        match self {
+            Content::Post(post) => post.draft,
+            Content::Page(page) => page.draft,
+        }
+    }
+
+    pub fn lang(&self) -> Option<&str> {
+        match self {
+            Content::Post(post) => post.lang.as_deref(),
+            Content::Page(page) => page.lang.as_deref(),
+        }
+    }
+
+    /// The context the content's template is rendered with
+    pub fn to_context(&self) -> Context {
+        match self {
+            Content::Post(post) => post.to_context(),
+            Content::Page(page) => page.to_context(),
+        }
+    }
+
+    /// Render the content, without writing it
+    pub fn render_to_string(
+        &self,
+        templates: &Tera,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<String, Error> {
+        match self {
+            Content::Post(post) => post.render_to_string(templates, posts, site_context),
+            Content::Page(page) => page.render_to_string(templates, posts, site_context),
+        }
+    }
+
+    /// Render the content and write it to the output directory
+    pub fn render(
+        &self,
+        templates: &Tera,
+        output_dir: &Path,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<(), Error> {
+        match self {
+            Content::Post(post) => post.render(templates, output_dir, posts, site_context),
+            Content::Page(page) => page.render(templates, output_dir, posts, site_context),
+        }
+    }
+}
+
+impl From<Post> for Content {
+    fn from(post: Post) -> Self {
+        Content::Post(Box::new(post))
+    }
+}
+
+impl From<Page> for Content {
+    fn from(page: Page) -> Self {
+        Content::Page(Box::new(page))
+    }
+}
@@ -0,0 +1,131 @@
+//! The context that posts and pages are rendered with.
+//! Every key that a template can use for a post or a page comes from ContentContext,
+//! so the keys are the same for both kinds of content, apart from the post-only keys in PostContext.
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
+use std::path::Path;
+use tera::Context;
+
+use crate::lang::Translation;
+use crate::page::Page;
+use crate::post::{DateStruct, Post, PostSummary};
+
+/// The keys available to the template of every post and page
+#[derive(Debug, Serialize)]
+pub struct ContentContext<'a> {
+    pub title: &'a str,
+    /// The rendered file, relative to the output directory
+    pub path: &'a Path,
+    /// The url of the rendered file, with a leading /
+    pub url: &'a Path,
+    /// The rendered html of the content
+    pub body: &'a str,
+    /// The html up to the first <!--more-->, or all of it if there isn't one
+    pub excerpt: &'a str,
+    pub slug: &'a str,
+    pub noindex: bool,
+    pub draft: bool,
+    pub is_post: bool,
+    pub is_page: bool,
+    /// True for the page rendered to index.html at the root of the site, or of a language
+    pub is_home: bool,
+    pub lang: Option<&'a str>,
+    /// The same content in the other languages of the site
+    pub translations: &'a [Translation],
+    /// Only posts have these keys
+    #[serde(flatten)]
+    pub post: Option<PostContext<'a>>,
+}
+
+/// The keys that are only available to the templates of posts
+#[derive(Debug, Serialize)]
+pub struct PostContext<'a> {
+    pub date: NaiveDate,
+    pub datetime: DateTime<FixedOffset>,
+    /// The date in the format YYYY-MM-DD
+    pub date_str: &'a str,
+    pub date_struct: &'a DateStruct,
+    /// The datetime in the format of the date_format config, e.g. December 1, 2024
+    pub date_formatted: &'a str,
+    pub tags: &'a [String],
+    pub authors: &'a [String],
+    /// The slug as a valid html id, e.g. for <article id="{{ anchor }}">
+    pub anchor: &'a str,
+    /// True if the excerpt is shorter than the body, for a "Continue reading" link
+    pub has_more: bool,
+    /// The posts that share the most tags with this post
+    pub related: &'a [PostSummary],
+    pub series: Option<&'a str>,
+    /// The position of the post in its series, starting at 1
+    pub series_index: Option<usize>,
+    pub series_total: Option<usize>,
+    pub series_prev: Option<&'a PostSummary>,
+    pub series_next: Option<&'a PostSummary>,
+    pub featured: bool,
+}
+
+impl<'a> From<&'a Post> for ContentContext<'a> {
+    fn from(post: &'a Post) -> Self {
+        let position = post.series_position.as_ref();
+        Self {
+            title: &post.title,
+            path: &post.output_path,
+            url: &post.url,
+            body: &post.content,
+            excerpt: &post.excerpt,
+            slug: &post.slug,
+            noindex: post.noindex,
+            draft: post.draft,
+            is_post: true,
+            is_page: false,
+            is_home: false,
+            lang: post.lang.as_deref(),
+            translations: &post.translations,
+            post: Some(PostContext {
+                date: post.date,
+                datetime: post.datetime,
+                date_str: &post.date_str,
+                date_struct: &post.date_struct,
+                date_formatted: &post.date_formatted,
+                tags: &post.tags,
+                authors: &post.authors,
+                anchor: &post.anchor,
+                has_more: post.has_more,
+                related: &post.related,
+                series: post.series.as_deref(),
+                series_index: position.map(|p| p.index),
+                series_total: position.map(|p| p.total),
+                series_prev: position.and_then(|p| p.prev.as_ref()),
+                series_next: position.and_then(|p| p.next.as_ref()),
+                featured: post.featured,
+            }),
+        }
+    }
+}
+
+impl<'a> From<&'a Page> for ContentContext<'a> {
+    fn from(page: &'a Page) -> Self {
+        Self {
+            title: &page.title,
+            path: &page.output_path,
+            url: &page.url,
+            body: &page.content,
+            excerpt: &page.excerpt,
+            slug: &page.slug,
+            noindex: page.noindex,
+            draft: page.draft,
+            is_post: false,
+            is_page: true,
+            is_home: page.is_home(),
+            lang: page.lang.as_deref(),
+            translations: &page.translations,
+            post: None,
+        }
+    }
+}
+
+impl ContentContext<'_> {
+    pub fn into_context(self) -> Context {
+        Context::from_serialize(self).expect("a ContentContext should serialize to a Context")
+    }
+}
@@ -0,0 +1,68 @@
+//! Load site-wide data files.
+//!
+//! Every `.toml`, `.json`, `.yaml` or `.yml` file in the `data` directory is parsed and stored
+//! in a table keyed by the file name without its extension, so `data/team.toml` is available
+//! in every template as `data.team`.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tera::{Map, Value};
+
+use crate::render::Error;
+
+pub const READ_DIRECTORY: &str = "data";
+pub const VALID_EXTENSIONS: [&str; 4] = ["toml", "json", "yaml", "yml"];
+
+/// Read all of the data files in root_dir/data.
+/// If there is no data directory, an empty table is returned.
+/// Two files with the same name but different extensions, e.g. nav.json and nav.yaml, are an error,
+/// since only one of them could be data.nav.
+pub fn load_data(root_dir: &Path) -> Result<Value, Error> {
+    let data_path = root_dir.join(READ_DIRECTORY);
+    let mut data = Map::new();
+    let mut key_paths: HashMap<String, PathBuf> = HashMap::new();
+    if !data_path.is_dir() {
+        return Ok(Value::Object(data));
+    }
+    let mut paths = fs::read_dir(&data_path)
+        .map_err(Error::ReadFile)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        let extension = path
+            .extension()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default();
+        if !VALID_EXTENSIONS.contains(&extension) {
+            continue;
+        }
+        let key = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or(Error::Path(path.clone(), "file_stem".to_string()))?
+            .to_string();
+        if let Some(other) = key_paths.insert(key.clone(), path.clone()) {
+            return Err(Error::DuplicateData(other, path, key));
+        }
+        let content = fs::read_to_string(&path).map_err(Error::ReadFile)?;
+        let value =
+            parse_data(extension, &content).map_err(|e| Error::ParseData(path.clone(), e))?;
+        data.insert(key, value);
+    }
+    Ok(Value::Object(data))
+}
+
+fn parse_data(extension: &str, content: &str) -> Result<Value, String> {
+    match extension {
+        "toml" => {
+            let value: toml::Value = toml::from_str(content).map_err(|e| e.to_string())?;
+            tera::to_value(value).map_err(|e| e.to_string())
+        }
+        "json" => serde_json::from_str(content).map_err(|e| e.to_string()),
+        _ => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+    }
+}
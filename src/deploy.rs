@@ -1,14 +1,30 @@
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    future::Future,
+    io::IsTerminal,
+    path::Path,
+    time::Duration,
+};
 
-use aws_sdk_cloudfront::types::{InvalidationBatch, Paths};
-use aws_sdk_s3::{primitives::ByteStream, types::ObjectCannedAcl};
-use chrono::Utc;
-use log::info;
+use aws_config::{retry::RetryConfig, timeout::TimeoutConfig};
+use aws_sdk_cloudfront::{
+    error::{DisplayErrorContext, ProvideErrorMetadata},
+    types::{InvalidationBatch, Paths},
+};
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{Delete, ObjectCannedAcl, ObjectIdentifier},
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, log, warn, Level};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use walkdir::WalkDir;
 
-use crate::{config, render};
+use crate::{config, render, util};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DeployStrategy {
@@ -28,6 +44,28 @@ impl From<config::DeployStrategy> for DeployStrategy {
 pub struct DeployConfig {
     pub strategy: DeployStrategy,
     pub bucket: String,
+    /// If more paths than this changed, the whole CloudFront cache is invalidated instead of each path
+    pub max_invalidation_paths: usize,
+    pub connect_timeout: Duration,
+    /// The longest each attempt at a request can take
+    pub attempt_timeout: Duration,
+    pub max_attempts: u32,
+    /// The named AWS profile to use. Without one, the default credential chain is used.
+    pub profile: Option<String>,
+}
+
+impl DeployConfig {
+    /// The timeouts for the AWS clients, so that a flaky connection fails the deploy instead of hanging it
+    pub fn timeout_config(&self) -> TimeoutConfig {
+        TimeoutConfig::builder()
+            .connect_timeout(self.connect_timeout)
+            .operation_attempt_timeout(self.attempt_timeout)
+            .build()
+    }
+
+    pub fn retry_config(&self) -> RetryConfig {
+        RetryConfig::standard().with_max_attempts(self.max_attempts)
+    }
 }
 
 impl From<config::DeployConfig> for DeployConfig {
@@ -35,10 +73,22 @@ impl From<config::DeployConfig> for DeployConfig {
         Self {
             strategy: config.strategy.into(),
             bucket: config.bucket,
+            max_invalidation_paths: config.max_invalidation_paths,
+            connect_timeout: Duration::from_secs(config.connect_timeout_secs),
+            attempt_timeout: Duration::from_secs(config.attempt_timeout_secs),
+            max_attempts: config.max_attempts,
+            profile: config.profile,
         }
     }
 }
 
+/// How many times to try to create a CloudFront invalidation before giving up
+const INVALIDATION_ATTEMPTS: u32 = 5;
+/// How long to wait before retrying a throttled invalidation. This doubles after each attempt.
+const INVALIDATION_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// The most keys S3 will delete in one DeleteObjects request
+pub const DELETE_BATCH_SIZE: usize = 1000;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("render error: {0}")]
@@ -57,6 +107,8 @@ pub enum Error {
     NoDistributionList,
     #[error("cloudfront error: {0:?}")]
     CloudfrontError(AwsError),
+    #[error("write deploy state error: {0:?}")]
+    WriteDeployState(std::io::Error),
 }
 
 #[derive(Debug)]
@@ -71,13 +123,134 @@ impl AwsError {
     }
 }
 
+/// The state of the last successful deploy, saved in the input directory.
+/// It is used to skip uploading files that haven't changed since the last deploy.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployState {
+    /// The bucket that was deployed to
+    pub bucket: String,
+    /// A map of S3 key to the hash of the file's contents and the headers it was uploaded with. See hash_object.
+    pub files: BTreeMap<String, String>,
+}
+
+impl DeployState {
+    pub const FILE_NAME: &str = ".kalamos-deploy-state.json";
+
+    /// Load the deploy state for bucket from input_dir.
+    /// If the state file is missing, unreadable, or for a different bucket, an empty state is returned
+    /// so that every file is uploaded.
+    pub fn load(input_dir: &Path, bucket: &str) -> Self {
+        let empty = Self {
+            bucket: bucket.to_string(),
+            files: BTreeMap::new(),
+        };
+        let Ok(content) = fs::read_to_string(input_dir.join(Self::FILE_NAME)) else {
+            return empty;
+        };
+        match serde_json::from_str::<Self>(&content) {
+            Ok(state) if state.bucket == bucket => state,
+            Ok(_) => empty,
+            Err(e) => {
+                warn!("ignoring invalid deploy state file: {}", e);
+                empty
+            }
+        }
+    }
+
+    pub fn save(&self, input_dir: &Path) -> Result<(), Error> {
+        let content = serde_json::to_string_pretty(self).expect("deploy state should serialize");
+        fs::write(input_dir.join(Self::FILE_NAME), content).map_err(Error::WriteDeployState)
+    }
+
+    /// Returns true if the file at key had the same hash at the last deploy
+    pub fn is_unchanged(&self, key: &str, hash: &str) -> bool {
+        self.files.get(key).is_some_and(|h| h == hash)
+    }
+}
+
+/// The hex encoded SHA-256 hash of a file's content and the Content-Type it is uploaded with,
+/// so that a file is uploaded again when either changes, e.g. after a [mime] override is added
+pub fn hash_object(content: &[u8], content_type: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    hasher.update(format!("\ncontent-type: {}", content_type));
+    format!("{:x}", hasher.finalize())
+}
+
+/// What to do with a local file when deploying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadAction {
+    /// The file is unchanged since the last deploy
+    Skip,
+    /// The file is already on S3, but wasn't part of the last deploy, e.g. because that deploy failed
+    /// after uploading it, so it isn't uploaded again but its path is still invalidated
+    Invalidate,
+    /// Upload the file and invalidate its path
+    Upload,
+}
+
+/// Decide what to do with the file at key, with the hash from hash_object. on_s3 is None if the bucket
+/// doesn't have the key, and otherwise the ETag it has for it.
+/// A file whose ETag matches is only skipped when the last deploy doesn't know the key. When it does know it,
+/// with a different hash, the headers may have changed, which the ETag doesn't cover, so it is uploaded again.
+pub fn upload_action(
+    previous_state: &DeployState,
+    key: &str,
+    hash: &str,
+    on_s3: Option<Option<&str>>,
+    content: &[u8],
+) -> UploadAction {
+    let Some(e_tag) = on_s3 else {
+        return UploadAction::Upload;
+    };
+    if previous_state.is_unchanged(key, hash) {
+        UploadAction::Skip
+    } else if !previous_state.files.contains_key(key) && matches_e_tag(e_tag, content) {
+        UploadAction::Invalidate
+    } else {
+        UploadAction::Upload
+    }
+}
+
+/// Returns true if content is what S3 has for a file with the ETag e_tag, so the file doesn't need uploading,
+/// even without a deploy state, e.g. on a fresh checkout.
+/// Only files uploaded in one part have an ETag that is the MD5 of their content; the others never match.
+pub fn matches_e_tag(e_tag: Option<&str>, content: &[u8]) -> bool {
+    e_tag.is_some_and(|e_tag| e_tag.trim_matches('"') == util::etag(content))
+}
+
+/// Options that change how a deploy runs
+#[derive(Debug, Clone, Default)]
+pub struct DeployOptions {
+    /// Don't generate the site before deploying it
+    pub skip_generate: bool,
+    /// Upload every file, even if it hasn't changed since the last deploy
+    pub force: bool,
+    /// Log per-file and debugging output at the debug level, leaving only the summaries at info
+    pub quiet: bool,
+    /// The named AWS profile to use, overriding the profile in the deploy config
+    pub profile: Option<String>,
+}
+
+impl DeployOptions {
+    /// The level to log details at, like each uploaded file
+    fn detail_level(&self) -> Level {
+        if self.quiet {
+            Level::Debug
+        } else {
+            Level::Info
+        }
+    }
+}
+
 pub async fn deploy(
     input_dir: &Path,
     output_dir: &Path,
     deploy_config: &Option<DeployConfig>,
-    skip_generate: bool,
+    mime_config: &config::MimeConfig,
+    options: &DeployOptions,
 ) -> Result<(), Error> {
-    if !skip_generate {
+    if !options.skip_generate {
         info!("rendering site");
         render::render_dir(input_dir, output_dir).map_err(Error::RenderError)?;
         info!("rendering site complete\n");
@@ -85,56 +258,191 @@ pub async fn deploy(
     if let Some(deploy_config) = deploy_config {
         match deploy_config.strategy {
             DeployStrategy::S3AndCloudfront => {
-                deploy_to_s3_and_cloudfront(input_dir, output_dir, &deploy_config.bucket).await?;
+                deploy_to_s3_and_cloudfront(
+                    input_dir,
+                    output_dir,
+                    deploy_config,
+                    mime_config,
+                    options,
+                )
+                .await?;
             }
         }
     }
     Ok(())
 }
 
+/// Upload the site to S3 and invalidate the CloudFront cache.
+/// Files that haven't changed since the last deploy are not uploaded again unless options.force is true.
+/// Only the changed paths are invalidated, unless there are more than max_invalidation_paths of them.
 pub async fn deploy_to_s3_and_cloudfront(
     input_dir: &Path,
     output_dir: &Path,
-    bucket: &str,
+    deploy_config: &DeployConfig,
+    mime_config: &config::MimeConfig,
+    options: &DeployOptions,
 ) -> Result<(), Error> {
+    let detail = options.detail_level();
     info!("Deploying to S3 and Cloudfront");
-    info!("Input directory: {:?}", input_dir);
-    info!("Output directory: {:?}", output_dir);
+    log!(detail, "Input directory: {:?}", input_dir);
+    log!(detail, "Output directory: {:?}", output_dir);
+    let bucket = deploy_config.bucket.as_str();
     info!("Bucket name: {:?}", bucket);
 
     info!("rendering site");
     render::render_dir(input_dir, output_dir).map_err(Error::RenderError)?;
     info!("rendering site complete\n");
-    let config = aws_config::from_env().load().await;
+    let mut loader = aws_config::from_env()
+        .timeout_config(deploy_config.timeout_config())
+        .retry_config(deploy_config.retry_config());
+    if let Some(profile) = options.profile.as_ref().or(deploy_config.profile.as_ref()) {
+        info!("AWS profile: {:?}", profile);
+        loader = loader.profile_name(profile);
+    }
+    let config = loader.load().await;
     let s3_client = aws_sdk_s3::Client::new(&config);
     let response = s3_client
         .head_bucket()
         .bucket(bucket)
         .send()
         .await
-        .map_err(|e| Error::S3Error(AwsError::new(e.to_string())))?;
+        .map_err(|e| Error::S3Error(AwsError::new(DisplayErrorContext(&e).to_string())))?;
     let region = response.bucket_region().ok_or(Error::NoRegion)?;
-    info!("S3 region: {:?}", region);
+    log!(detail, "S3 region: {:?}", region);
 
     // Upload the files to the bucket
-    upload_site_to_s3(output_dir, bucket, s3_client).await?;
+    let previous_state = if options.force {
+        DeployState::default()
+    } else {
+        DeployState::load(input_dir, bucket)
+    };
+    let (state, changed) = upload_site_to_s3(
+        output_dir,
+        bucket,
+        s3_client,
+        &previous_state,
+        mime_config,
+        options,
+    )
+    .await?;
     // Get the distribution for the bucket and invalidate the cache
+    let paths = invalidation_paths(&changed, deploy_config.max_invalidation_paths);
+    if paths.is_empty() {
+        info!("no files changed, skipping the cloudfront invalidation");
+        return state.save(input_dir);
+    }
     let cloudfront_client = aws_sdk_cloudfront::Client::new(&config);
-    invalidate_cloudfront_cache(bucket, region, &cloudfront_client).await?;
-    Ok(())
+    let reference = caller_reference(&changed, &previous_state, &state);
+    let invalidation = invalidate_cloudfront_cache(
+        bucket,
+        region,
+        &cloudfront_client,
+        &paths,
+        &reference,
+        detail,
+    );
+    save_after_invalidation(input_dir, &state, invalidation).await
+}
+
+/// Save the deploy state once the invalidation succeeds.
+/// If it fails, the state of the last successful deploy is kept, so the next deploy counts the same files
+/// as changed and invalidates them again, even though they are already uploaded. See upload_action.
+pub async fn save_after_invalidation(
+    input_dir: &Path,
+    state: &DeployState,
+    invalidation: impl Future<Output = Result<(), Error>>,
+) -> Result<(), Error> {
+    invalidation.await?;
+    state.save(input_dir)
+}
+
+/// The CloudFront paths to invalidate for the S3 keys that were uploaded or deleted.
+/// index.html files are also invalidated as their directory, since that is how they are usually requested.
+/// If there are more than max_paths paths, everything is invalidated with /* instead.
+pub fn invalidation_paths(changed: &BTreeSet<String>, max_paths: usize) -> Vec<String> {
+    let mut paths = BTreeSet::new();
+    for key in changed {
+        paths.insert(format!("/{}", key));
+        if let Some(dir) = key.strip_suffix("index.html") {
+            if dir.is_empty() || dir.ends_with('/') {
+                paths.insert(format!("/{}", dir));
+            }
+        }
+    }
+    if paths.len() > max_paths {
+        return vec!["/*".to_string()];
+    }
+    paths.into_iter().collect()
 }
 
+/// The caller reference of the CloudFront invalidation for a deploy: a hash of each changed key with its hash
+/// before and after the deploy. A retried deploy of the same content has the same reference, so CloudFront
+/// doesn't create a duplicate invalidation, while any other deploy, even one back to earlier content, has a new one.
+/// A deploy is retried when its invalidation failed, since the state before it is kept. See save_after_invalidation.
+pub fn caller_reference(
+    changed: &BTreeSet<String>,
+    previous_state: &DeployState,
+    state: &DeployState,
+) -> String {
+    let mut hasher = Sha256::new();
+    for key in changed {
+        let previous = previous_state.files.get(key).map_or("", |h| h.as_str());
+        let current = state.files.get(key).map_or("", |h| h.as_str());
+        hasher.update(format!("{}\t{}\t{}\n", key, previous, current));
+    }
+    format!("kalamos-{:x}", hasher.finalize())
+}
+
+/// Upload the files in site_dir to the bucket, skipping files that are unchanged since previous_state,
+/// and remove any files from the bucket that are no longer in site_dir.
+/// Returns the new deploy state and the keys that were uploaded or deleted.
 async fn upload_site_to_s3(
     site_dir: &Path,
     bucket_name: &str,
     s3_client: aws_sdk_s3::Client,
-) -> Result<(), Error> {
+    previous_state: &DeployState,
+    mime_config: &config::MimeConfig,
+    options: &DeployOptions,
+) -> Result<(DeployState, BTreeSet<String>), Error> {
     info!("uploading site to s3");
+    // Get the files that are already on S3, with their ETags. This is used to make sure that skipped files
+    // still exist remotely, to skip files that are already up to date, and to find files that should be removed.
+    let files_on_s3_paginator = s3_client
+        .list_objects_v2()
+        .bucket(bucket_name)
+        .into_paginator()
+        .send();
+    let files_on_s3_iter = files_on_s3_paginator
+        .collect::<Result<Vec<_>, _>>()
+        .await
+        .map_err(|e| Error::S3Error(AwsError::new(DisplayErrorContext(&e).to_string())))?;
+    let files_on_s3 = files_on_s3_iter
+        .into_iter()
+        .flat_map(|e| {
+            e.contents()
+                .iter()
+                .filter_map(|obj| {
+                    obj.key()
+                        .map(|k| (k.to_string(), obj.e_tag().map(|e| e.to_string())))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<HashMap<_, _>>();
+
     let files = WalkDir::new(site_dir)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_file());
-    let mut uploaded_files = HashSet::new();
+        .filter(|e| e.path().is_file())
+        .collect::<Vec<_>>();
+    let progress = UploadProgress::new(files.len(), options);
+    let mut bytes_uploaded = 0;
+    let mut files_uploaded = 0;
+    let mut state = DeployState {
+        bucket: bucket_name.to_string(),
+        files: BTreeMap::new(),
+    };
+    let mut local_files = HashSet::new();
+    let mut changed = BTreeSet::new();
     for file in files {
         let file_path = file.path();
         let file_content = fs::read(file_path).map_err(Error::ReadFile)?;
@@ -143,13 +451,31 @@ async fn upload_site_to_s3(
             .map_err(Error::StripPrefixError)?
             .to_str()
             .ok_or(Error::GenerateKey(file_path.to_path_buf()))?;
-        let mime_type = mime_guess::from_path(key).first_or_text_plain();
-        info!(
+        let mime_type = util::mime_type(Path::new(key), mime_config);
+        let hash = hash_object(&file_content, mime_type.essence_str());
+        local_files.insert(key.to_string());
+        state.files.insert(key.to_string(), hash.clone());
+        let on_s3 = files_on_s3.get(key).map(|e_tag| e_tag.as_deref());
+        match upload_action(previous_state, key, &hash, on_s3, &file_content) {
+            UploadAction::Skip => {
+                progress.log(&format!("Skipping unchanged file: {}", key));
+                progress.inc();
+                continue;
+            }
+            UploadAction::Invalidate => {
+                progress.log(&format!("Skipping file that is already uploaded: {}", key));
+                changed.insert(key.to_string());
+                progress.inc();
+                continue;
+            }
+            UploadAction::Upload => {}
+        }
+        progress.log(&format!(
             "Uploading to: {}, mime_type: {}",
             key,
             mime_type.essence_str()
-        );
-        uploaded_files.insert(key.to_string());
+        ));
+        bytes_uploaded += file_content.len();
         s3_client
             .put_object()
             .bucket(bucket_name)
@@ -159,53 +485,162 @@ async fn upload_site_to_s3(
             .content_type(mime_type.essence_str())
             .send()
             .await
-            .map_err(|e| Error::S3Error(AwsError::new(e.to_string())))?;
+            .map_err(|e| Error::S3Error(AwsError::new(DisplayErrorContext(&e).to_string())))?;
+        files_uploaded += 1;
+        changed.insert(key.to_string());
+        progress.inc();
     }
+    progress.finish();
 
     // Now remove files that should no longer exist in S3
     // These are files that were previously uploaded but are no longer in the local directory
-    let files_on_s3_paginator = s3_client
-        .list_objects_v2()
-        .bucket(bucket_name)
-        .into_paginator()
-        .send();
-    let files_on_s3_iter = files_on_s3_paginator
-        .collect::<Result<Vec<_>, _>>()
-        .await
-        .map_err(|e| Error::S3Error(AwsError::new(e.to_string())))?;
-    let files_on_s3 = files_on_s3_iter
-        .into_iter()
-        .flat_map(|e| {
-            e.contents()
-                .iter()
-                .filter_map(|obj| obj.key().map(|k| k.to_string()))
-                .collect::<Vec<_>>()
-        })
-        .collect::<HashSet<_>>();
-    let files_to_remove = files_on_s3.difference(&uploaded_files);
-    info!("files to remove: {:?}", files_to_remove);
-    for key in files_to_remove {
-        s3_client
-            .delete_object()
+    let files_to_remove = files_on_s3
+        .keys()
+        .filter(|key| !local_files.contains(*key))
+        .collect::<Vec<_>>();
+    log!(
+        options.detail_level(),
+        "files to remove: {:?}",
+        files_to_remove
+    );
+    for batch in files_to_remove.chunks(DELETE_BATCH_SIZE) {
+        let objects = batch
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(*key).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::S3Error(AwsError::new(e.to_string())))?;
+        let delete = Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true)
+            .build()
+            .map_err(|e| Error::S3Error(AwsError::new(e.to_string())))?;
+        let output = s3_client
+            .delete_objects()
             .bucket(bucket_name)
-            .key(key)
+            .delete(delete)
             .send()
             .await
-            .map_err(|e| Error::S3Error(AwsError::new(e.to_string())))?;
+            .map_err(|e| Error::S3Error(AwsError::new(DisplayErrorContext(&e).to_string())))?;
+        // A DeleteObjects request succeeds even if some of its keys couldn't be deleted
+        if let Some(message) = delete_errors_message(output.errors()) {
+            return Err(Error::S3Error(AwsError::new(message)));
+        }
+        changed.extend(batch.iter().map(|key| key.to_string()));
+    }
+    // Files deleted by an earlier deploy whose invalidation failed are gone from S3, but still need invalidating
+    changed.extend(
+        previous_state
+            .files
+            .keys()
+            .filter(|key| !local_files.contains(*key))
+            .cloned(),
+    );
+    info!(
+        "upload to s3 complete: uploaded {} of {} files ({} bytes), deleted {} files\n",
+        files_uploaded,
+        local_files.len(),
+        bytes_uploaded,
+        files_to_remove.len()
+    );
+    Ok((state, changed))
+}
+
+/// Describe the keys that a DeleteObjects request failed to delete, e.g.
+/// "failed to delete 1 file: old.html (AccessDenied: Access Denied)". Returns None if there are no errors.
+pub fn delete_errors_message(errors: &[aws_sdk_s3::types::Error]) -> Option<String> {
+    if errors.is_empty() {
+        return None;
+    }
+    let keys = errors
+        .iter()
+        .map(|e| {
+            format!(
+                "{} ({}: {})",
+                e.key().unwrap_or_default(),
+                e.code().unwrap_or("unknown"),
+                e.message().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>();
+    Some(format!(
+        "failed to delete {} file{}: {}",
+        errors.len(),
+        if errors.len() == 1 { "" } else { "s" },
+        keys.join(", ")
+    ))
+}
+
+/// Reports how many files have been processed during an upload.
+/// When attached to a terminal this is a progress bar. Otherwise, a log line is printed
+/// every 10% of the way through. A quiet deploy has no progress bar, and logs the messages
+/// for each file at the debug level.
+struct UploadProgress {
+    bar: Option<ProgressBar>,
+    total: usize,
+    count: Cell<usize>,
+    level: Level,
+}
+
+impl UploadProgress {
+    fn new(total: usize, options: &DeployOptions) -> Self {
+        let show_bar = !options.quiet && std::io::stderr().is_terminal();
+        let bar = show_bar.then(|| {
+            let bar = ProgressBar::new(total as u64);
+            bar.set_style(
+                ProgressStyle::with_template("{bar:40} uploaded {pos}/{len} [{elapsed}]")
+                    .expect("progress bar template should be valid"),
+            );
+            bar
+        });
+        Self {
+            bar,
+            total,
+            count: Cell::new(0),
+            level: options.detail_level(),
+        }
+    }
+
+    /// Log a message without breaking up the progress bar
+    fn log(&self, message: &str) {
+        match &self.bar {
+            Some(bar) => bar.suspend(|| log!(self.level, "{}", message)),
+            None => log!(self.level, "{}", message),
+        }
+    }
+
+    fn inc(&self) {
+        let count = self.count.get() + 1;
+        self.count.set(count);
+        match &self.bar {
+            Some(bar) => bar.inc(1),
+            None => {
+                let step = (self.total / 10).max(1);
+                if count.is_multiple_of(step) || count == self.total {
+                    log!(self.level, "uploaded {}/{}", count, self.total);
+                }
+            }
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
     }
-    info!("upload to s3 complete\n");
-    Ok(())
 }
 
 async fn invalidate_cloudfront_cache(
     bucket_name: &str,
     region: &str,
     cloudfront_client: &aws_sdk_cloudfront::Client,
+    paths: &[String],
+    caller_reference: &str,
+    detail: Level,
 ) -> Result<(), Error> {
     info!("invalidating cloudfront cache");
     let response = cloudfront_client.list_distributions().send().await;
     let distributions = response
-        .map_err(|e| Error::CloudfrontError(AwsError::new(e.to_string())))?
+        .map_err(|e| Error::CloudfrontError(AwsError::new(DisplayErrorContext(&e).to_string())))?
         .distribution_list
         .ok_or(Error::NoDistributionList)?
         .items
@@ -220,26 +655,83 @@ async fn invalidate_cloudfront_cache(
         None => false,
     });
     let distribution_id = distribution.ok_or(Error::NoDistributionList)?.clone().id;
-    info!("cloudfront distribution ID: {:?}", distribution_id);
+    log!(detail, "cloudfront distribution ID: {:?}", distribution_id);
+    log!(detail, "invalidating paths: {:?}", paths);
     let invalidation_paths = Paths::builder()
-        .items("/*")
-        .quantity(1)
+        .set_items(Some(paths.to_vec()))
+        .quantity(paths.len() as i32)
         .build()
-        .expect("invalidation paths");
-    let now = Utc::now();
-    let timestamp = format!("{}", now.timestamp_millis());
+        .map_err(|e| {
+            Error::CloudfrontError(
+                AwsError::new(DisplayErrorContext(&e).to_string())
+                    .add_message("building invalidation paths"),
+            )
+        })?;
     let invalidation_batch = InvalidationBatch::builder()
         .paths(invalidation_paths)
-        .caller_reference(timestamp)
+        .caller_reference(caller_reference)
         .build()
-        .expect("invalidation batch");
-    cloudfront_client
-        .create_invalidation()
-        .distribution_id(distribution_id)
-        .invalidation_batch(invalidation_batch)
-        .send()
-        .await
-        .map_err(|e| Error::CloudfrontError(AwsError::new(e.to_string())))?;
-    info!("cloudfront cache busted!");
+        .map_err(|e| {
+            Error::CloudfrontError(
+                AwsError::new(DisplayErrorContext(&e).to_string())
+                    .add_message("building invalidation batch"),
+            )
+        })?;
+
+    let mut attempt = 1;
+    let response = loop {
+        let result = cloudfront_client
+            .create_invalidation()
+            .distribution_id(&distribution_id)
+            .invalidation_batch(invalidation_batch.clone())
+            .send()
+            .await;
+        match result {
+            Ok(response) => break response,
+            Err(e)
+                if attempt < INVALIDATION_ATTEMPTS && is_retryable_cloudfront_error(e.code()) =>
+            {
+                let delay = INVALIDATION_RETRY_DELAY * 2u32.pow(attempt - 1);
+                warn!(
+                    "cloudfront invalidation attempt {} of {} failed with {:?}, retrying in {:?}",
+                    attempt,
+                    INVALIDATION_ATTEMPTS,
+                    e.code().unwrap_or_default(),
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(Error::CloudfrontError(
+                    AwsError::new(DisplayErrorContext(&e).to_string())
+                        .add_message(format!("creating invalidation after {} attempts", attempt)),
+                ))
+            }
+        }
+    };
+    let invalidation = response
+        .invalidation
+        .ok_or(Error::CloudfrontError(AwsError::new(
+            "the create invalidation response did not include the invalidation",
+        )))?;
+    info!(
+        "cloudfront cache busted! invalidation {} is {}",
+        invalidation.id, invalidation.status
+    );
     Ok(())
 }
+
+/// Whether a failed CloudFront request is worth retrying, based on its error code.
+/// Throttled requests and requests rejected because too many invalidations are in progress succeed later.
+pub fn is_retryable_cloudfront_error(code: Option<&str>) -> bool {
+    matches!(
+        code,
+        Some(
+            "Throttling"
+                | "ThrottlingException"
+                | "RequestLimitExceeded"
+                | "TooManyInvalidationsInProgress"
+        )
+    )
+}
@@ -0,0 +1,183 @@
+//! Check that a project and its environment are set up to build and deploy the site, for `kalamos doctor`.
+//! The checks only read files and credentials; nothing is written.
+use std::fmt;
+use std::path::Path;
+
+use aws_sdk_s3::config::ProvideCredentials;
+
+use crate::config::{Config, DeployStrategy};
+use crate::page::Page;
+use crate::post::Post;
+use crate::render;
+
+/// How bad a failed check is. Critical problems stop the site from building or deploying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Critical,
+}
+
+/// The result of one check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: &str, severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Critical => "error",
+        };
+        write!(f, "[{}] {}: {}", status, self.name, self.message)
+    }
+}
+
+/// Check the project in input_dir: its directories, its config and its templates
+pub fn check_project(input_dir: &Path) -> Vec<Check> {
+    let mut checks = vec![];
+    if !input_dir.is_dir() {
+        checks.push(Check::new(
+            "input directory",
+            Severity::Critical,
+            format!("{:?} is not a directory", input_dir),
+        ));
+        return checks;
+    }
+    checks.push(Check::new(
+        "input directory",
+        Severity::Ok,
+        format!("{:?}", input_dir),
+    ));
+
+    let config = match Config::load(input_dir) {
+        Ok(Some(config)) => {
+            checks.push(Check::new("config.toml", Severity::Ok, "valid"));
+            config
+        }
+        Ok(None) => {
+            checks.push(Check::new(
+                "config.toml",
+                Severity::Warning,
+                "not found, using the defaults",
+            ));
+            Config::default()
+        }
+        Err(e) => {
+            checks.push(Check::new(
+                "config.toml",
+                Severity::Critical,
+                format!("{:?}", e),
+            ));
+            return checks;
+        }
+    };
+
+    for dir in [Post::READ_DIRECTORY, Page::READ_DIRECTORY] {
+        let check = if input_dir.join(dir).is_dir() {
+            Check::new(dir, Severity::Ok, "found")
+        } else {
+            Check::new(
+                dir,
+                Severity::Warning,
+                "not found, there is nothing to render in it",
+            )
+        };
+        checks.push(check);
+    }
+
+    let theme_dir = config.theme_dir(input_dir);
+    if let Some(theme_dir) = &theme_dir {
+        let check = if theme_dir.is_dir() {
+            Check::new("theme", Severity::Ok, format!("{:?}", theme_dir))
+        } else {
+            Check::new(
+                "theme",
+                Severity::Critical,
+                format!("{:?} not found", theme_dir),
+            )
+        };
+        checks.push(check);
+    }
+
+    // A theme provides its own layouts, so the site doesn't need any
+    let templates_dir = input_dir.join(&config.templates.dir);
+    if !templates_dir.is_dir() && theme_dir.is_none() {
+        checks.push(Check::new(
+            &config.templates.dir,
+            Severity::Critical,
+            "not found, posts and pages need templates to render with",
+        ));
+    } else {
+        let templates = render::load_templates_with_theme(
+            input_dir,
+            &config.templates,
+            theme_dir.as_deref().filter(|d| d.is_dir()),
+        );
+        checks.push(match templates {
+            Ok(templates) => Check::new(
+                "templates",
+                Severity::Ok,
+                format!("{} loaded", templates.get_template_names().count()),
+            ),
+            Err(e) => Check::new("templates", Severity::Critical, e.to_string()),
+        });
+    }
+
+    checks.push(match &config.deploy {
+        None => Check::new("deploy", Severity::Ok, "no [deploy] section, skipping"),
+        Some(deploy) if deploy.bucket.trim().is_empty() => {
+            Check::new("deploy", Severity::Critical, "[deploy] bucket is empty")
+        }
+        Some(deploy) => Check::new(
+            "deploy",
+            Severity::Ok,
+            format!("{:?} to {}", deploy.strategy, deploy.bucket),
+        ),
+    });
+    checks
+}
+
+/// Check that AWS credentials can be found, if the config deploys to S3
+pub async fn check_aws_credentials(input_dir: &Path) -> Option<Check> {
+    let config = Config::load(input_dir).ok().flatten()?;
+    let deploy = config.deploy?;
+    if deploy.strategy != DeployStrategy::S3AndCloudfront {
+        return None;
+    }
+    let mut loader = aws_config::from_env();
+    if let Some(profile) = &deploy.profile {
+        loader = loader.profile_name(profile);
+    }
+    let aws_config = loader.load().await;
+    let Some(provider) = aws_config.credentials_provider() else {
+        return Some(Check::new(
+            "AWS credentials",
+            Severity::Critical,
+            "no credentials provider is configured",
+        ));
+    };
+    Some(match provider.provide_credentials().await {
+        Ok(_) => Check::new("AWS credentials", Severity::Ok, "found"),
+        Err(e) => Check::new("AWS credentials", Severity::Critical, e.to_string()),
+    })
+}
+
+/// True if any of the checks found a critical problem
+pub fn has_critical(checks: &[Check]) -> bool {
+    checks.iter().any(|c| c.severity == Severity::Critical)
+}
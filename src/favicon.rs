@@ -0,0 +1,105 @@
+//! Resize the site's icon to the common favicon sizes and generate a web app manifest for them
+use image::{imageops::FilterType, ImageFormat};
+use serde::Serialize;
+use serde_json::json;
+use std::{io::Cursor, path::PathBuf};
+
+use crate::config::FaviconConfig;
+
+pub const ICON_PATH: &str = "favicon-32x32.png";
+pub const APPLE_TOUCH_ICON_PATH: &str = "apple-touch-icon.png";
+pub const MANIFEST_PATH: &str = "site.webmanifest";
+
+/// The sizes the icon is resized to, with the file each one is written to, relative to the output directory
+pub const ICON_SIZES: [(u32, &str); 5] = [
+    (16, "favicon-16x16.png"),
+    (32, ICON_PATH),
+    (180, APPLE_TOUCH_ICON_PATH),
+    (192, "android-chrome-192x192.png"),
+    (512, "android-chrome-512x512.png"),
+];
+
+/// The urls of the icons and manifest, added to the context of every post and page as favicon,
+/// e.g. for a <link rel="icon" href="{{ favicon.icon }}"> in a head partial
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Favicon {
+    /// The 32x32 icon
+    pub icon: String,
+    /// The 180x180 icon, for <link rel="apple-touch-icon">
+    pub apple_touch_icon: String,
+    pub manifest: String,
+    /// Every size, smallest first, e.g. for a <link rel="icon" sizes="{{ icon.sizes }}"> each
+    pub icons: Vec<FaviconIcon>,
+}
+
+/// One size of the icon
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FaviconIcon {
+    pub src: String,
+    /// e.g. "16x16"
+    pub sizes: String,
+}
+
+/// A generated icon file, with the path to write it to, relative to the output directory
+#[derive(Debug, PartialEq, Eq)]
+pub struct FaviconFile {
+    pub path: PathBuf,
+    pub content: Vec<u8>,
+}
+
+/// The width and height of a PNG image, from its header
+pub fn png_size(png: &[u8]) -> Option<(u32, u32)> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if png.len() < 24 || !png.starts_with(SIGNATURE) || &png[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(png[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(png[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+/// The icons in each of ICON_SIZES and the web app manifest listing them, and the urls to add to the context.
+/// An icon that isn't square is cropped to the middle of it.
+/// Returns None if icon is not a PNG that can be decoded.
+pub fn favicon_files(config: &FaviconConfig, icon: Vec<u8>) -> Option<(Favicon, Vec<FaviconFile>)> {
+    png_size(&icon)?;
+    let source = image::load_from_memory_with_format(&icon, ImageFormat::Png).ok()?;
+    let mut files = vec![];
+    let mut icons = vec![];
+    for (size, path) in ICON_SIZES {
+        let resized = source.resize_to_fill(size, size, FilterType::Lanczos3);
+        let mut content = Cursor::new(vec![]);
+        resized.write_to(&mut content, ImageFormat::Png).ok()?;
+        files.push(FaviconFile {
+            path: PathBuf::from(path),
+            content: content.into_inner(),
+        });
+        icons.push(FaviconIcon {
+            src: format!("/{}", path),
+            sizes: format!("{}x{}", size, size),
+        });
+    }
+    let favicon = Favicon {
+        icon: format!("/{}", ICON_PATH),
+        apple_touch_icon: format!("/{}", APPLE_TOUCH_ICON_PATH),
+        manifest: format!("/{}", MANIFEST_PATH),
+        icons: icons.clone(),
+    };
+    let manifest = json!({
+        "name": config.name,
+        "short_name": config.short_name.as_ref().unwrap_or(&config.name),
+        "icons": icons
+            .iter()
+            .map(|icon| json!({ "src": icon.src, "sizes": icon.sizes, "type": "image/png" }))
+            .collect::<Vec<_>>(),
+        "theme_color": config.theme_color,
+        "background_color": config.background_color,
+        "display": "standalone",
+    });
+    let manifest = serde_json::to_string_pretty(&manifest).expect("manifest should serialize");
+    files.push(FaviconFile {
+        path: PathBuf::from(MANIFEST_PATH),
+        content: manifest.into_bytes(),
+    });
+    Some((favicon, files))
+}
@@ -0,0 +1,81 @@
+//! Feeds for some of the posts of a site, like a feed for each tag.
+//! These reuse the site's feed page, e.g. pages/atom.xml, rendered with just the posts that have the tag.
+use log::warn;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::post::Post;
+use crate::render::Error;
+use crate::util;
+
+/// The directory the tag feeds are written to
+pub const TAGS_DIR: &str = "tags";
+
+/// The tag feeds of a site, from posts_by_tag
+#[derive(Debug, Default)]
+pub struct TagFeeds {
+    /// The posts that have each tag, keyed by the slug of the tag, along with the tag itself
+    pub tags: BTreeMap<String, (String, Vec<Post>)>,
+    /// The problems with the tags, like a tag without a slug
+    pub warnings: Vec<String>,
+}
+
+/// The posts that have each tag, keyed by the slug of the tag.
+/// Tags that only differ in case, like "Rust" and "rust", share a feed. Tags that can't be slugged, like "日本語",
+/// get no feed, and different tags that share a slug, like "C++" and "C", share the feed of the first one.
+/// Each of these is a warning, or an error if strict is set.
+pub fn posts_by_tag(posts: &[Post], strict: bool) -> Result<TagFeeds, Error> {
+    let mut feeds = TagFeeds::default();
+    let mut warned = HashSet::new();
+    for post in posts {
+        for tag in &post.tags {
+            let slug = match util::slugify(tag) {
+                Ok(slug) => slug,
+                Err(e) if strict => return Err(e),
+                Err(_) => {
+                    if warned.insert((String::new(), tag.to_lowercase())) {
+                        let warning = format!("tag {:?} has no slug, so it has no feed", tag);
+                        warn!("{}", warning);
+                        feeds.warnings.push(warning);
+                    }
+                    continue;
+                }
+            };
+            let (first, tagged) = feeds
+                .tags
+                .entry(slug.clone())
+                .or_insert_with(|| (tag.clone(), vec![]));
+            if first.to_lowercase() != tag.to_lowercase() {
+                if strict {
+                    return Err(Error::TagSlugCollision(slug, first.clone(), tag.clone()));
+                }
+                if warned.insert((slug.clone(), tag.to_lowercase())) {
+                    let warning = format!(
+                        "tag slug collision: {:?} is the slug of both {:?} and {:?}, so they share a feed",
+                        slug, first, tag
+                    );
+                    warn!("{}", warning);
+                    feeds.warnings.push(warning);
+                }
+            }
+            if !tagged.iter().any(|p| p.input_path == post.input_path) {
+                tagged.push(post.clone());
+            }
+        }
+    }
+    Ok(feeds)
+}
+
+/// The posts that go in a feed: the first limit of posts, which are sorted newest first by Post::sort_newest_first,
+/// or all of them if limit is 0
+pub fn limit_posts(posts: &[Post], limit: usize) -> &[Post] {
+    match limit {
+        0 => posts,
+        limit => &posts[..limit.min(posts.len())],
+    }
+}
+
+/// Where the feed for a tag is written, relative to the output directory, e.g. tags/rust/atom.xml
+pub fn tag_feed_path(tag_slug: &str, feed_path: &Path) -> PathBuf {
+    Path::new(TAGS_DIR).join(tag_slug).join(feed_path)
+}
@@ -0,0 +1,20 @@
+//! Find the commit a site is built from, so that templates can show which build is live.
+//! HEAD is read with libgit2, through the git2 crate, so git doesn't need to be installed.
+use git2::Repository;
+use std::path::Path;
+
+/// The build_commit of a site that isn't in a git repository, or whose HEAD can't be read
+pub const UNKNOWN_COMMIT: &str = "unknown";
+/// The length of the short commit hash
+const SHORT_HASH_LENGTH: usize = 7;
+
+/// The short hash of the commit checked out in the git repository that dir is in, e.g. abc1234.
+/// The repository is found like git finds it, from dir or one of its parents, including through a .git file
+/// for a worktree or submodule.
+/// Returns None if dir isn't in a repository, or HEAD doesn't point to a commit yet.
+pub fn head_commit(dir: &Path) -> Option<String> {
+    let repo = Repository::discover(dir).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    let hash = commit.id().to_string();
+    Some(hash[..SHORT_HASH_LENGTH].to_string())
+}
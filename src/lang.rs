@@ -0,0 +1,68 @@
+//! Multilingual sites.
+//!
+//! When config.toml lists languages, a directory named after a language directly under posts/ or pages/
+//! holds the content in that language, e.g. posts/es/2024-12-01-hola.md is a Spanish post.
+//! Posts in a language directory are written under /es/, and pages keep their path, so pages/es/about.md
+//! is written to /es/about.html. Content outside of the language directories has no language.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// The same content in another language
+#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Translation {
+    pub lang: String,
+    pub title: String,
+    pub url: PathBuf,
+}
+
+/// The language of a file, from the directory it is in.
+/// input_path is relative to the root of the site, and read_directory is posts or pages.
+/// Pages read from the root of the site don't start with the read directory.
+pub fn language_of(
+    input_path: &Path,
+    read_directory: &str,
+    languages: &[String],
+) -> Option<String> {
+    let path = input_path
+        .strip_prefix(read_directory)
+        .unwrap_or(input_path);
+    let mut components = path.components();
+    let first = match components.next() {
+        Some(Component::Normal(first)) => first.to_str()?,
+        _ => return None,
+    };
+    // a file named like a language isn't in a language directory
+    components.next()?;
+    languages
+        .iter()
+        .any(|l| l == first)
+        .then(|| first.to_string())
+}
+
+/// Find the translations of each entry. Entries with the same key and different languages are
+/// translations of each other. Entries without a language have no translations.
+/// Returns the translations for each entry, in the same order as entries, sorted by language.
+pub fn find_translations(entries: &[(Option<String>, Translation)]) -> Vec<Vec<Translation>> {
+    let mut by_key: HashMap<&str, Vec<&Translation>> = HashMap::new();
+    for (key, translation) in entries {
+        if let Some(key) = key {
+            by_key.entry(key).or_default().push(translation);
+        }
+    }
+    entries
+        .iter()
+        .map(|(key, translation)| {
+            let Some(key) = key else {
+                return vec![];
+            };
+            let mut translations = by_key[key.as_str()]
+                .iter()
+                .filter(|other| other.lang != translation.lang)
+                .map(|other| (*other).clone())
+                .collect::<Vec<_>>();
+            translations.sort();
+            translations
+        })
+        .collect()
+}
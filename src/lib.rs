@@ -1,9 +1,24 @@
+pub mod author;
+pub mod cli_error;
 pub mod config;
+pub mod content;
+pub mod context;
+pub mod data;
 pub mod deploy;
+pub mod doctor;
+pub mod favicon;
+pub mod feed;
+pub mod git;
+pub mod lang;
+pub mod logger;
 pub mod page;
 pub mod parser;
 pub mod post;
 pub mod render;
+pub mod robots;
+pub mod section;
 pub mod serve;
+pub mod sitemap;
+pub mod url;
 pub mod util;
 pub mod watch;
@@ -0,0 +1,54 @@
+//! A logger that writes each log record to stderr as a line of JSON, for log-aggregation pipelines.
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{Level, Log, Metadata, Record, SetLoggerError};
+use serde::Serialize;
+use std::io::Write;
+
+/// A single log line, as written by the JSON logger
+#[derive(Debug, Serialize)]
+pub struct LogLine<'a> {
+    pub timestamp: String,
+    pub level: &'a str,
+    pub target: &'a str,
+    pub message: String,
+}
+
+/// Format a log record as a single line of JSON, without a trailing newline
+pub fn json_line(record: &Record, timestamp: DateTime<Utc>) -> String {
+    let line = LogLine {
+        timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        level: record.level().as_str(),
+        target: record.target(),
+        message: record.args().to_string(),
+    };
+    serde_json::to_string(&line).expect("log line should serialize")
+}
+
+struct JsonLogger {
+    level: Level,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = json_line(record, Utc::now());
+            // Write the whole line at once so lines from different threads don't interleave
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Initialize the global logger to write JSON lines to stderr, for records at or above the given level
+pub fn init_json(level: Level) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+    log::set_max_level(level.to_level_filter());
+    Ok(())
+}
@@ -1,15 +1,18 @@
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
 use include_dir_as_map::{include_dir_as_map, DirMap};
 use kalamos::{
+    cli_error::{CommandError, ErrorType},
     config::Config,
     deploy::{self},
-    render, serve, watch,
+    doctor, logger, render, serve, util, watch,
 };
 use log::info;
 use std::fs;
 use std::{
     path::{Path, PathBuf},
     thread,
+    time::Duration,
 };
 
 #[derive(Debug, Parser)]
@@ -20,6 +23,13 @@ struct Cli {
     /// The log-level is case insensitive.
     #[arg(short, long, default_value_t = log::Level::Info)]
     log_level: log::Level,
+    /// The format of the log lines. json writes one JSON object per line, with the timestamp, level, target and message.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+    /// The format of the error that stops a command, written to stderr. json writes {"error_type": ..., "message": ...},
+    /// for scripts. Each error_type exits with its own code: usage 2, config 3, render 4, serve 5, watch 6, deploy 7, io 8.
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Text, global = true)]
+    errors: ErrorFormat,
 }
 
 #[derive(Debug, Subcommand)]
@@ -32,6 +42,52 @@ enum Commands {
         /// the output directory.
         #[arg(default_value = DEFAULT_OUTPUT_DIR, short, long)]
         output_dir: PathBuf,
+        /// If this is true, then files in the output directory that were not generated by this build will be removed
+        #[arg(short, long, default_value_t = false)]
+        clean: bool,
+        /// The format of the render summary. json prints a versioned report to stdout, for tools.
+        /// Logs are always written to stderr.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output_format: OutputFormat,
+        /// Also render the site, including drafts, to this directory, e.g. to share a preview of upcoming posts.
+        /// The site in the output directory never includes drafts.
+        #[arg(long)]
+        drafts_to: Option<PathBuf>,
+        /// Fail on problems that are otherwise only warnings, e.g. two posts with the same slug
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+        /// Log how long each phase of the build took, e.g. reading posts and rendering templates
+        #[arg(long, default_value_t = false)]
+        time: bool,
+        /// Render the site without writing anything, and print the files that would be written and their sizes
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// The date of the build, used as current_date in templates, e.g. 2024-12-01 or 2024-12-01T09:00:00Z.
+        /// Defaults to SOURCE_DATE_EPOCH if it is set, and otherwise now.
+        #[arg(long, value_parser = parse_build_date)]
+        build_date: Option<DateTime<Utc>>,
+        /// Only render the posts dated on or after this date, e.g. 2024-01-01, for a fast preview while editing.
+        /// Pages and the list of posts in templates are complete, but older posts aren't rewritten,
+        /// so don't use this for a build you're going to publish.
+        #[arg(long)]
+        since: Option<NaiveDate>,
+        /// Read each post's body just before rendering it, and drop it after, so that only one is in memory at a time.
+        /// For very large sites. Every post is parsed twice, and the posts list in templates has no content, only excerpts.
+        #[arg(long, default_value_t = false)]
+        lazy_bodies: bool,
+        /// Add the ETag of each written file to the report, as printed with --output-format json.
+        /// It's the MD5 hash that S3 uses for files uploaded in one part.
+        #[arg(long, default_value_t = false)]
+        checksums: bool,
+        /// Load the templates from this directory instead of the configured one, e.g. to try out a theme.
+        #[arg(long, value_name = "DIR")]
+        template_dir: Option<PathBuf>,
+        /// Fail if no posts or pages were rendered, e.g. in CI, to catch a misconfigured input directory
+        #[arg(long, default_value_t = false)]
+        fail_on_empty: bool,
+        /// Copy every static file, even the ones that haven't changed since the last build
+        #[arg(long, default_value_t = false)]
+        force_copy: bool,
     },
 
     /// Serve a static site and watch for changes to the input directory.
@@ -46,6 +102,30 @@ enum Commands {
         /// the output directory.
         #[arg(default_value = DEFAULT_OUTPUT_DIR, short, long)]
         output_dir: PathBuf,
+        /// A directory to serve files from when they aren't in the output directory, e.g. the input directory
+        /// for assets that aren't part of the build yet. Can be given more than once; they are tried in order.
+        #[arg(long)]
+        fallback_dir: Vec<PathBuf>,
+        /// Poll the input directory for changes every this many milliseconds, instead of relying on
+        /// the filesystem's change events. Use this on network filesystems and Docker volumes that don't send them.
+        #[arg(long, value_name = "MS")]
+        poll: Option<u64>,
+        /// Let the browser cache responses. By default they are sent with Cache-Control: no-store.
+        #[arg(long, default_value_t = false)]
+        cache: bool,
+        /// Serve this file, with a 503 status, for every request, e.g. as a placeholder during a rebuild
+        #[arg(long, value_name = "FILE")]
+        maintenance: Option<PathBuf>,
+        /// Load the templates from this directory instead of the configured one, e.g. to try out a theme.
+        #[arg(long, value_name = "DIR")]
+        template_dir: Option<PathBuf>,
+        /// Include drafts. This is a preview build: templates get is_preview = true, so they can show that it isn't live.
+        #[arg(long, default_value_t = false)]
+        drafts: bool,
+        /// Serve the input directory as it is, as a plain static file server, without rendering or watching it.
+        /// It doesn't need to be a kalamos site.
+        #[arg(long = "static", alias = "serve-only", default_value_t = false)]
+        static_only: bool,
     },
 
     #[command()]
@@ -59,6 +139,25 @@ enum Commands {
         /// If this is true, then the site will not be generated before deploying
         #[arg(short, long, default_value_t = false)]
         skip_generate: bool,
+        /// If this is true, then every file will be uploaded, even if it hasn't changed since the last deploy
+        #[arg(short, long, default_value_t = false)]
+        force: bool,
+        /// If this is true, then only summaries are logged. The lines for each file are logged at the debug level.
+        #[arg(short, long, default_value_t = false)]
+        quiet: bool,
+        /// The named AWS profile to deploy with, overriding the profile in the [deploy] config.
+        /// Without either, AWS_PROFILE or the default credentials are used.
+        #[arg(long)]
+        profile: Option<String>,
+    },
+
+    /// Check that the project and environment are set up to build and deploy the site.
+    /// Nothing is changed. Exits with an error if there are critical problems.
+    #[command()]
+    Doctor {
+        /// The directory of the site
+        #[arg(default_value = DEFAULT_INPUT_DIR)]
+        input_dir: PathBuf,
     },
 
     /// Generate a new static site.
@@ -72,6 +171,28 @@ enum Commands {
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+fn parse_build_date(date: &str) -> Result<DateTime<Utc>, String> {
+    render::parse_build_date(date).map_err(|e| e.to_string())
+}
+
 const DEFAULT_OUTPUT_DIR: &str = "./site";
 const DEFAULT_INPUT_DIR: &str = ".";
 const DEFAULT_PORT: u16 = 9999;
@@ -79,63 +200,254 @@ const DEFAULT_PORT: u16 = 9999;
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
-    simple_logger::init_with_level(args.log_level).expect("Failed to initialize logger");
-    match args.command {
+    match args.log_format {
+        LogFormat::Text => simple_logger::init_with_level(args.log_level),
+        LogFormat::Json => logger::init_json(args.log_level),
+    }
+    .expect("Failed to initialize logger");
+    if let Err(e) = run(args.command).await {
+        match args.errors {
+            ErrorFormat::Text => eprintln!("{}", e),
+            ErrorFormat::Json => eprintln!("{}", e.to_json()),
+        }
+        std::process::exit(e.error_type.exit_code());
+    }
+}
+
+/// The --template-dir of a command, made absolute, since it is relative to the current directory
+/// rather than to the input directory like the configured templates dir
+fn absolute_template_dir(template_dir: Option<PathBuf>) -> Result<Option<PathBuf>, CommandError> {
+    template_dir
+        .map(|dir| {
+            if !dir.is_dir() {
+                return Err(CommandError::new(
+                    ErrorType::Usage,
+                    format!("template dir {:?} does not exist", dir),
+                ));
+            }
+            std::path::absolute(&dir).map_err(|e| CommandError::new(ErrorType::Io, e))
+        })
+        .transpose()
+}
+
+/// Make sure that --drafts-to won't interfere with the site: it can't be the output directory,
+/// however either is written, and it can't be inside the input directory, or the next build would read
+/// the drafts site back in. Nothing is created, so that a dry run leaves no directories behind.
+fn check_drafts_dir(
+    input_dir: &Path,
+    output_dir: &Path,
+    drafts_dir: &Path,
+) -> Result<(), CommandError> {
+    let canonical = |dir: &Path| {
+        util::canonicalize_uncreated(dir).map_err(|e| CommandError::new(ErrorType::Io, e))
+    };
+    let drafts_dir = canonical(drafts_dir)?;
+    if drafts_dir == canonical(output_dir)? {
+        return Err(CommandError::new(
+            ErrorType::Usage,
+            "--drafts-to must be a different directory than the output directory",
+        ));
+    }
+    if drafts_dir.starts_with(canonical(input_dir)?) {
+        return Err(CommandError::new(
+            ErrorType::Usage,
+            "--drafts-to must be outside the input directory, or the next build would read the drafts back in",
+        ));
+    }
+    Ok(())
+}
+
+async fn run(command: Commands) -> Result<(), CommandError> {
+    match command {
         Commands::Generate {
             input_dir,
             output_dir,
+            clean,
+            output_format,
+            drafts_to,
+            strict,
+            time,
+            dry_run,
+            build_date,
+            since,
+            lazy_bodies,
+            checksums,
+            template_dir,
+            fail_on_empty,
+            force_copy,
         } => {
             info!("input_dir: {:?}, output_dir: {:?}", input_dir, output_dir);
-            render::render_dir(&input_dir, &output_dir).unwrap_or_else(|e| {
-                panic!("Error rendering posts and pages: {}", e);
-            });
+            let template_dir = absolute_template_dir(template_dir)?;
+            let options = render::RenderOptions {
+                clean,
+                drafts: false,
+                strict,
+                dry_run,
+                build_date,
+                since,
+                lazy_bodies,
+                checksums,
+                template_dir: template_dir.clone(),
+                fail_on_empty,
+                force_copy,
+                ..render::RenderOptions::default()
+            };
+            if let Some(drafts_dir) = &drafts_to {
+                check_drafts_dir(&input_dir, &output_dir, drafts_dir)?;
+            }
+            let report = render::render_dir_with_options(&input_dir, &output_dir, &options)?;
+            if let Some(drafts_dir) = drafts_to {
+                info!("rendering drafts to {:?}", drafts_dir);
+                // A separate render, with its own templates and written files, so the two sites don't interfere
+                let options = render::RenderOptions {
+                    clean,
+                    drafts: true,
+                    strict,
+                    dry_run,
+                    build_date,
+                    since,
+                    lazy_bodies,
+                    template_dir,
+                    force_copy,
+                    ..render::RenderOptions::default()
+                };
+                render::render_dir_with_options(&input_dir, &drafts_dir, &options)?;
+            }
+            if time {
+                let timings = &report.stats.timings;
+                info!("loading templates: {}ms", timings.templates_ms);
+                info!("reading posts: {}ms", timings.posts_ms);
+                info!("reading pages: {}ms", timings.pages_ms);
+                info!("rendering: {}ms", timings.render_ms);
+                info!("copying static files: {}ms", timings.copy_ms);
+            }
+            match output_format {
+                OutputFormat::Text if dry_run => {
+                    for file in &report.planned {
+                        println!("{} ({} bytes)", file.path.display(), file.bytes);
+                    }
+                    info!(
+                        "dry run: would render {} posts and {} pages, and write {} files",
+                        report.stats.posts, report.stats.pages, report.stats.files_written
+                    );
+                }
+                OutputFormat::Text => info!(
+                    "rendered {} posts and {} pages, wrote {} files in {}ms",
+                    report.stats.posts,
+                    report.stats.pages,
+                    report.stats.files_written,
+                    report.stats.duration_ms
+                ),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("report should serialize")
+                ),
+            }
         }
         Commands::Serve {
             input_dir,
             output_dir,
             port,
+            fallback_dir,
+            poll,
+            cache,
+            maintenance,
+            template_dir,
+            drafts,
+            static_only,
         } => {
             info!("Serving {:?} on port {}...", input_dir, port);
-            let output_dir_clone = output_dir.clone();
+            if let Some(maintenance) = maintenance.as_ref().filter(|m| !m.is_file()) {
+                return Err(CommandError::new(
+                    ErrorType::Usage,
+                    format!("maintenance page {:?} does not exist", maintenance),
+                ));
+            }
+            let serve_options = serve::ServeOptions { cache, maintenance };
+            if static_only {
+                if !input_dir.is_dir() {
+                    return Err(CommandError::new(
+                        ErrorType::Usage,
+                        format!("{:?} is not a directory", input_dir),
+                    ));
+                }
+                // A plain directory has no config, so it gets the default index files and error pages
+                let config = Config::default();
+                let roots = [vec![input_dir], fallback_dir].concat();
+                return serve::serve(&roots, port, &config.serve, &config.mime, &serve_options)
+                    .map_err(|e| CommandError::new(ErrorType::Serve, format!("{:?}", e)));
+            }
+            let roots = [vec![output_dir.clone()], fallback_dir].concat();
+            let config = Config::load(&input_dir)?.unwrap_or_default();
+            let serve_config = config
+                .serve
+                .with_html_extension(&config.build.html_extension);
+
+            let render_options = render::RenderOptions {
+                template_dir: absolute_template_dir(template_dir)?,
+                drafts,
+                ..render::RenderOptions::default()
+            };
 
             // Render the site before serving
-            render::render_dir(&input_dir, &output_dir).unwrap_or_else(|e| {
-                panic!("Error rendering posts and pages: {}", e);
-            });
+            render::render_dir_with_options(&input_dir, &output_dir, &render_options)?;
             let server = thread::spawn(move || {
-                serve::serve(&output_dir_clone, port).unwrap_or_else(|e| {
-                    panic!("Error serving: {:?}", e);
-                });
+                serve::serve(&roots, port, &serve_config, &config.mime, &serve_options)
+                    .map_err(|e| CommandError::new(ErrorType::Serve, format!("{:?}", e)))
             });
             let watcher = thread::spawn(move || {
                 info!(
                     "Watching {:?} and outputting to {:?}",
                     input_dir, output_dir
                 );
-                watch::watch(&input_dir, &output_dir).unwrap_or_else(|e| {
-                    panic!("Error watching: {:?}", e);
-                });
+                watch::watch(
+                    &input_dir,
+                    &output_dir,
+                    poll.map(Duration::from_millis),
+                    &render_options,
+                )
+                .map_err(|e| CommandError::new(ErrorType::Watch, format!("{:?}", e)))
             });
-            server.join().unwrap();
-            watcher.join().unwrap();
+            server.join().expect("the server thread should not panic")?;
+            watcher
+                .join()
+                .expect("the watcher thread should not panic")?;
+        }
+        Commands::Doctor { input_dir } => {
+            let mut checks = doctor::check_project(&input_dir);
+            if !doctor::has_critical(&checks) {
+                checks.extend(doctor::check_aws_credentials(&input_dir).await);
+            }
+            for check in &checks {
+                println!("{}", check);
+            }
+            if doctor::has_critical(&checks) {
+                std::process::exit(1);
+            }
         }
         Commands::Deploy {
             input_dir,
             output_dir,
             skip_generate,
+            force,
+            quiet,
+            profile,
         } => {
-            let config = Config::load(&input_dir).unwrap_or_else(|e| {
-                panic!("Error loading config: {:?}", e);
-            });
+            let config = Config::load(&input_dir)?;
             if let Some(config) = config {
                 deploy::deploy(
                     &input_dir,
                     &output_dir,
                     &config.deploy.map(|c| c.into()),
-                    skip_generate,
+                    &config.mime,
+                    &deploy::DeployOptions {
+                        skip_generate,
+                        force,
+                        quiet,
+                        profile,
+                    },
                 )
-                .await
-                .unwrap_or_else(|e| panic!("Error deploying: {:?}", e));
+                .await?;
             } else {
                 println!("No config file found");
             }
@@ -151,13 +463,16 @@ async fn main() {
             for (file, contents) in examples {
                 let stripped = Path::new(&file)
                     .strip_prefix(&template)
-                    .map_err(|e| render::Error::StripPrefix(Path::new(&file).to_path_buf(), e))
-                    .unwrap_or_else(|e| panic!("Error while stripping prefix: {}", e));
+                    .map_err(|e| render::Error::StripPrefix(Path::new(&file).to_path_buf(), e))?;
                 let output_path = output_dir.join(stripped);
-                fs::create_dir_all(output_path.parent().unwrap()).unwrap();
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| CommandError::new(ErrorType::Io, e))?;
+                }
                 info!("Writing {:?} to {:?}", stripped, output_path);
-                fs::write(output_path, contents).unwrap();
+                fs::write(output_path, contents)
+                    .map_err(|e| CommandError::new(ErrorType::Io, e))?;
             }
         }
     }
+    Ok(())
 }
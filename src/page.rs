@@ -1,13 +1,17 @@
-use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::fs;
 use std::path::{Path, PathBuf};
 use tera::{Context, Tera};
+use walkdir::WalkDir;
 
+use crate::config::LineEndings;
+use crate::context::ContentContext;
+use crate::lang::{self, Translation};
 use crate::parser;
 use crate::post::Post;
-use crate::render::Render;
-use crate::render::{Error as RenderError, RenderableFromPath};
+use crate::render::{self, Error as RenderError, RenderableFromPath};
+use crate::render::{ReadOptions, Render};
+use crate::url;
+use crate::util;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Page {
@@ -37,9 +41,27 @@ pub struct Page {
     pub slug: String,
     /// The extension of the input file
     pub extension: String,
+    /// If this is true, search engines should not index the page
+    pub noindex: bool,
+    /// If this is false, the page is left out of the sitemap list
+    pub sitemap: bool,
+    /// Old urls of the page, which redirect to it
+    pub aliases: Vec<String>,
+    /// Drafts are only rendered when RenderOptions::drafts is true
+    pub draft: bool,
+    /// The position of the page in navigation. Lower weights come first, and pages without one come last.
+    pub weight: Option<i32>,
+    /// The language of the page, from the language directory it is in on a multilingual site
+    pub lang: Option<String>,
+    /// The pages with the same path in other language directories. This is filled in by set_translations.
+    #[serde(skip)]
+    pub translations: Vec<Translation>,
+    /// The frontmatter schema violations of the page, which end up in RenderReport::warnings
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct PageFile {
     pub slug: String,
     pub extension: String,
@@ -86,13 +108,10 @@ impl TryFrom<PathBuf> for PageFile {
             extension
         };
 
-        let stripped_path = path
-            .strip_prefix(Page::read_directory())
-            .map_err(|e| RenderError::StripPrefix(path.to_path_buf(), e))?;
-        let url = PathBuf::from("/")
-            .join(stripped_path)
-            .with_extension(url_extension);
-        let output_path = stripped_path.with_extension(url_extension);
+        // Pages read from the root of the site don't have a pages/ prefix
+        let stripped_path = path.strip_prefix(Page::read_directory()).unwrap_or(&path);
+        let url = url::page_url(stripped_path, url_extension);
+        let output_path = url::page_output_path(stripped_path, url_extension);
         Ok(Self {
             slug,
             extension: extension.to_string(),
@@ -109,6 +128,34 @@ impl TryFrom<PathBuf> for PageFile {
     }
 }
 
+impl PageFile {
+    /// Write the page with another extension than html, from ReadOptions::html_extension
+    pub fn with_html_extension(&self, extension: &str) -> Self {
+        let output_path = url::with_html_extension(&self.output_path, extension);
+        Self {
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            ..self.clone()
+        }
+    }
+
+    /// Replace the slug that was taken from the file name, and update the url and output path to match
+    pub fn with_slug(&self, slug: &str) -> Result<Self, RenderError> {
+        let slug = util::slugify(slug)?;
+        let extension = self.output_path.extension().unwrap_or_default();
+        let output_path = self
+            .output_path
+            .with_file_name(&slug)
+            .with_extension(extension);
+        Ok(Self {
+            slug,
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            ..self.clone()
+        })
+    }
+}
+
 impl RenderableFromPath for PageFile {
     fn url(&self) -> PathBuf {
         self.url.clone()
@@ -127,25 +174,175 @@ impl RenderableFromPath for PageFile {
 pub struct PageFrontmatter {
     pub title: String,
     pub template: Option<String>,
+    /// Overrides the slug from the file name
+    pub slug: Option<String>,
+    /// Ask search engines not to index the page. Exposed in the context as noindex,
+    /// so that templates can add <meta name="robots" content="noindex">
+    /// A page with noindex is also left out of the sitemap list.
+    pub noindex: Option<bool>,
+    /// Set this to false to leave the page out of the sitemap list
+    pub sitemap: Option<bool>,
+    /// Set this to true to leave the page out of the site unless drafts are being rendered
+    pub draft: Option<bool>,
+    /// Old urls of the page, e.g. /about-us.html. Each one gets a page that redirects to the page.
+    pub aliases: Option<Vec<String>>,
+    /// The position of the page in the pages list, for ordering menus
+    pub weight: Option<i32>,
+}
+
+/// A short description of a page, used for navigation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PageSummary {
+    pub title: String,
+    pub url: PathBuf,
+    pub slug: String,
+    pub weight: Option<i32>,
+    pub lang: Option<String>,
+}
+
+impl From<&Page> for PageSummary {
+    fn from(page: &Page) -> Self {
+        Self {
+            title: page.title.clone(),
+            url: page.url.clone(),
+            slug: page.slug.clone(),
+            weight: page.weight,
+            lang: page.lang.clone(),
+        }
+    }
 }
 
 impl Page {
     pub const DEFAULT_TEMPLATE: &str = "default";
+    /// The template that puts the body of a markdown page in the content block of the base layout
+    /// from TemplatesConfig::base. It's added when the templates are loaded.
+    pub const BASE_TEMPLATE: &str = "__base_page.html";
     pub const READ_DIRECTORY: &str = "pages";
-    pub const VALID_EXTENSIONS: [&str; 4] = ["md", "markdown", "html", "xml"];
+    pub const VALID_EXTENSIONS: [&str; 6] = ["md", "markdown", "adoc", "asciidoc", "html", "xml"];
+    /// Directories that are never read as pages when reading pages from the root of the site
+    pub const RESERVED_DIRECTORIES: [&str; 6] = [
+        "posts",
+        "layouts",
+        "static",
+        "data",
+        "direct_copy",
+        "themes",
+    ];
+
+    /// Read pages from the root of the site instead of the pages directory.
+    /// Reserved directories, hidden files and directories, the output directory, the templates directory,
+    /// and files without a page extension are skipped.
+    pub fn read_from_root(
+        root_dir: &Path,
+        output_dir: &Path,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Vec<Self>, RenderError> {
+        let output_dir = output_dir.canonicalize().ok();
+        let templates_dir = root_dir.join(&options.templates.dir);
+        let paths = WalkDir::new(root_dir)
+            .into_iter()
+            .filter_entry(|e| {
+                let is_hidden = e.depth() > 0 && e.file_name().to_string_lossy().starts_with('.');
+                let is_reserved = e.depth() == 1
+                    && e.file_type().is_dir()
+                    && Self::RESERVED_DIRECTORIES
+                        .contains(&e.file_name().to_string_lossy().as_ref());
+                let is_output_dir =
+                    output_dir.is_some() && e.path().canonicalize().ok() == output_dir;
+                let is_templates_dir = e.path() == templates_dir;
+                !is_hidden && !is_reserved && !is_output_dir && !is_templates_dir
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| {
+                let extension = e.path().extension().unwrap_or_default().to_string_lossy();
+                Self::VALID_EXTENSIONS.contains(&extension.as_ref())
+            })
+            .map(|e| e.path().to_path_buf())
+            .collect::<Vec<_>>();
+        Self::read_from_paths(root_dir, paths, templates, options)
+    }
+
+    /// Fill in the translations of each page. Pages at the same path in different language directories,
+    /// like es/about.html and en/about.html, are translations.
+    pub fn set_translations(pages: &mut [Page]) {
+        let entries = pages
+            .iter()
+            .map(|page| {
+                let key = page.lang.as_ref().and_then(|lang| {
+                    page.output_path
+                        .strip_prefix(lang)
+                        .ok()
+                        .map(|p| p.to_string_lossy().to_string())
+                });
+                let translation = Translation {
+                    lang: page.lang.clone().unwrap_or_default(),
+                    title: page.title.clone(),
+                    url: page.url.clone(),
+                };
+                (key, translation)
+            })
+            .collect::<Vec<_>>();
+        let translations = lang::find_translations(&entries);
+        for (page, translations) in pages.iter_mut().zip(translations) {
+            page.translations = translations;
+        }
+    }
+
+    /// The pages in navigation order, for menus: by weight, lowest first, and then by title.
+    /// Pages without a weight come after the ones with one.
+    pub fn navigation(pages: &[Page]) -> Vec<PageSummary> {
+        let mut summaries = pages.iter().map(PageSummary::from).collect::<Vec<_>>();
+        summaries.sort_by(|a, b| {
+            (a.weight.is_none(), a.weight, &a.title).cmp(&(b.weight.is_none(), b.weight, &b.title))
+        });
+        summaries
+    }
+
+    /// The home page is the index.html at the root of the site, or at the root of its language directory, e.g. es/index.html,
+    /// or index.htm, or index, with another html extension
+    pub fn is_home(&self) -> bool {
+        let root = self.lang.as_deref().unwrap_or("");
+        self.output_path.parent() == Some(Path::new(root))
+            && self.output_path.file_stem().is_some_and(|s| s == "index")
+            && self.extension != "xml"
+    }
 
+    /// True for pages that are written as html: markdown pages, and html templates, as opposed to e.g. feeds
+    pub fn is_html(&self) -> bool {
+        self.is_markdown() || self.extension == "html"
+    }
+
+    /// Markdown and the other formats with a parser, as opposed to html and xml, which are templates
     fn extension_is_markdown(extension: &str) -> bool {
-        extension == "md" || extension == "markdown"
+        parser::parser_for_extension(extension).is_some()
     }
 
     fn is_markdown(&self) -> bool {
         Self::extension_is_markdown(&self.extension)
     }
 
-    fn from_non_markdown_content(content: &str, page_file: &PageFile) -> Result<Self, RenderError> {
-        let (frontmatter, body) =
-            parser::extract_frontmatter(content).map_err(RenderError::Markdown)?;
+    fn from_non_markdown_content(
+        content: &str,
+        sidecar: Option<&str>,
+        defaults: &toml::Table,
+        page_file: &PageFile,
+        options: &ReadOptions,
+    ) -> Result<Self, RenderError> {
+        let (frontmatter, body) = parser::extract_frontmatter(content)
+            .map_err(|e| RenderError::Markdown(page_file.input_path.clone(), e))?;
+        let frontmatter = parser::merge_defaults(frontmatter, defaults);
+        let frontmatter = parser::merge_sidecar(frontmatter, sidecar)
+            .map_err(|e| RenderError::Markdown(page_file.input_path.clone(), e))?;
 
+        render::check_required_fields(&frontmatter, &page_file.input_path)?;
+        let warnings = render::check_schema(
+            &frontmatter,
+            &options.schema.page,
+            &page_file.input_path,
+            options.strict,
+        )?;
         let frontmatter: PageFrontmatter = frontmatter.try_into().map_err(|e| {
             RenderError::ParseFrontmatter(format!(
                 "frontmatter for {:?}: {:?}",
@@ -153,8 +350,18 @@ impl Page {
                 e.to_string()
             ))
         })?;
+        let page_file = match &frontmatter.slug {
+            Some(slug) => page_file.with_slug(slug)?,
+            None => page_file.clone(),
+        }
+        .with_html_extension(options.html_extension());
+        let page_file = &page_file;
 
-        let mut template = Page::DEFAULT_TEMPLATE.to_string();
+        let mut template = options
+            .templates
+            .page
+            .clone()
+            .unwrap_or(Page::DEFAULT_TEMPLATE.to_string());
         template.push_str(".html");
         Ok(Self {
             output_path: page_file.output_path.to_path_buf(),
@@ -166,22 +373,66 @@ impl Page {
             excerpt: body,
             slug: page_file.slug.clone(),
             extension: page_file.extension.to_string(),
+            noindex: frontmatter.noindex.unwrap_or_default(),
+            sitemap: frontmatter.sitemap.unwrap_or(true),
+            aliases: frontmatter.aliases.unwrap_or_default(),
+            draft: frontmatter.draft.unwrap_or_default(),
+            weight: frontmatter.weight,
+            lang: lang::language_of(
+                &page_file.input_path,
+                Page::READ_DIRECTORY,
+                &options.languages,
+            ),
+            translations: vec![],
+            warnings,
         })
     }
 
-    fn from_markdown_content(content: &str, page_file: &PageFile) -> Result<Self, RenderError> {
-        let parsed = parser::parse(content).map_err(RenderError::Markdown)?;
-        let frontmatter: PageFrontmatter = parsed.frontmatter.try_into().map_err(|e| {
+    fn from_markdown_content(
+        content: &str,
+        sidecar: Option<&str>,
+        defaults: &toml::Table,
+        page_file: &PageFile,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Self, RenderError> {
+        let parsed = parser::parse_file(&page_file.extension, content, templates, &options.parse)
+            .map_err(|e| RenderError::Markdown(page_file.input_path.clone(), e))?;
+        let frontmatter = parser::merge_defaults(parsed.frontmatter, defaults);
+        let frontmatter = parser::merge_sidecar(frontmatter, sidecar)
+            .map_err(|e| RenderError::Markdown(page_file.input_path.clone(), e))?;
+        render::check_required_fields(&frontmatter, &page_file.input_path)?;
+        let warnings = render::check_schema(
+            &frontmatter,
+            &options.schema.page,
+            &page_file.input_path,
+            options.strict,
+        )?;
+        let frontmatter: PageFrontmatter = frontmatter.try_into().map_err(|e| {
             RenderError::ParseFrontmatter(format!(
                 "frontmatter for {:?}: {:?}",
                 page_file.input_path,
                 e.to_string()
             ))
         })?;
-        let mut template = frontmatter
-            .template
-            .unwrap_or(Page::DEFAULT_TEMPLATE.to_string());
-        template.push_str(".html");
+        let page_file = match &frontmatter.slug {
+            Some(slug) => page_file.with_slug(slug)?,
+            None => page_file.clone(),
+        }
+        .with_html_extension(options.html_extension());
+        let page_file = &page_file;
+        let template = match (frontmatter.template, &options.templates.base) {
+            (Some(template), _) => format!("{}.html", template),
+            (None, Some(_)) => Page::BASE_TEMPLATE.to_string(),
+            (None, None) => format!(
+                "{}.html",
+                options
+                    .templates
+                    .page
+                    .as_deref()
+                    .unwrap_or(Page::DEFAULT_TEMPLATE)
+            ),
+        };
 
         Ok(Self {
             output_path: page_file.output_path.to_path_buf(),
@@ -193,6 +444,18 @@ impl Page {
             excerpt: parsed.excerpt.unwrap_or(parsed.body),
             slug: page_file.slug.clone(),
             extension: page_file.extension.to_string(),
+            noindex: frontmatter.noindex.unwrap_or_default(),
+            sitemap: frontmatter.sitemap.unwrap_or(true),
+            aliases: frontmatter.aliases.unwrap_or_default(),
+            draft: frontmatter.draft.unwrap_or_default(),
+            weight: frontmatter.weight,
+            lang: lang::language_of(
+                &page_file.input_path,
+                Page::READ_DIRECTORY,
+                &options.languages,
+            ),
+            translations: vec![],
+            warnings,
         })
     }
 }
@@ -201,34 +464,34 @@ impl Render for Page {
     type FileType = PageFile;
 
     fn to_context(&self) -> Context {
-        let mut context = Context::new();
-        context.insert("title", &self.title);
-        context.insert("path", &self.output_path);
-        context.insert("url", &self.url);
-        context.insert("body", &self.content);
-        context.insert("excerpt", &self.excerpt);
-        context.insert("slug", &self.slug);
-        context.insert("current_date", &Utc::now().naive_local());
-        context
+        ContentContext::from(self).into_context()
     }
 
-    fn from_content(page_file: PageFile, content: &str) -> Result<Self, RenderError> {
+    fn from_content_with_sidecar(
+        page_file: PageFile,
+        content: &str,
+        sidecar: Option<&str>,
+        defaults: &toml::Table,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Self, RenderError> {
         let page = if !Self::extension_is_markdown(&page_file.extension) {
-            Self::from_non_markdown_content(content, &page_file)?
+            Self::from_non_markdown_content(content, sidecar, defaults, &page_file, options)?
         } else {
-            Self::from_markdown_content(content, &page_file)?
+            Self::from_markdown_content(content, sidecar, defaults, &page_file, templates, options)?
         };
 
         Ok(page)
     }
 
-    fn render(
+    fn render_to_string(
         &self,
         templates: &Tera,
-        output_dir: &Path,
         posts: &[Post],
-    ) -> Result<(), RenderError> {
-        let mut context = self.to_context();
+        site_context: &Context,
+    ) -> Result<String, RenderError> {
+        let mut context = site_context.clone();
+        context.extend(self.to_context());
         context.insert("posts", posts);
 
         let output = if self.is_markdown() {
@@ -249,6 +512,11 @@ impl Render for Page {
                     "to_str after getting file_name".to_string(),
                 ))?;
             let mut templates = templates.clone();
+            // Every value in an xml page, like a feed, is escaped for xml.
+            // Anything marked safe isn't, so the output is validated below too.
+            if self.extension == "xml" {
+                templates.set_escape_fn(util::escape_xml);
+            }
             templates
                 .add_raw_template(template, &self.content)
                 .map_err(RenderError::Tera)?;
@@ -257,16 +525,25 @@ impl Render for Page {
                 .map_err(RenderError::Tera)?
         };
 
-        let output_path = output_dir.join(self.output_path.clone());
-        let parent = output_path
-            .parent()
-            .ok_or(RenderError::CreateDir(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "no parent directory",
-            )))?;
-        fs::create_dir_all(parent).map_err(RenderError::CreateDir)?;
-        fs::write(&output_path, output).map_err(RenderError::WriteFile)?;
-        Ok(())
+        // Make sure that feeds, sitemaps and other xml pages are well-formed,
+        // e.g. that a title containing an & was escaped
+        if self.extension == "xml" {
+            util::validate_xml(&output)
+                .map_err(|e| RenderError::InvalidXml(self.input_path.clone(), e))?;
+        }
+        Ok(output)
+    }
+
+    fn render(
+        &self,
+        templates: &Tera,
+        output_dir: &Path,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<(), RenderError> {
+        let output = self.render_to_string(templates, posts, site_context)?;
+        let output = util::normalize_output(&output, LineEndings::default());
+        render::write_output(&output_dir.join(&self.output_path), output)
     }
 
     fn read_directory() -> String {
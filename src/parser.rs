@@ -1,10 +1,29 @@
 //! Parse a markdown file with TOML frontmatter
 use regex::Regex;
-use tera::Context;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::ops::Range;
+use std::process::{Command, Stdio};
+use std::sync::{LazyLock, Mutex};
+use tera::{Context, Tera};
 use thiserror::Error;
 type Frontmatter = toml::Value;
 use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
 use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::config::{HighlightConfig, MarkdownConfig};
+
+/// The theme used to highlight code blocks
+const HIGHLIGHT_THEME: &str = "InspiredGitHub";
+/// Where the stylesheet for class-based highlighting is written, relative to the output directory
+pub const HIGHLIGHT_CSS_PATH: &str = "highlight.css";
+
+// Loading the syntaxes and themes is expensive, so they are only loaded the first time a code block is
+// highlighted, and then shared. Markdown without code blocks, like most excerpts, never loads them.
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
 
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum Error {
@@ -12,6 +31,25 @@ pub enum Error {
     InvalidFrontmatter(String),
     #[error("content before frontmatter: {0}")]
     ContentBeforeFrontmatter(String),
+    #[error("unknown shortcode: {0}. Shortcodes are loaded from layouts/shortcodes/")]
+    UnknownShortcode(String),
+    #[error("closing shortcode without an opening shortcode: {0}")]
+    UnexpectedClosingShortcode(String),
+    #[error("render shortcode error: {0}: {1}")]
+    RenderShortcode(String, String),
+    #[error("invalid sidecar frontmatter: {0}")]
+    InvalidSidecar(String),
+    #[error("highlight error: {0}")]
+    Highlight(String),
+    #[error("asciidoctor error: {0}. AsciiDoc content needs asciidoctor to be installed")]
+    Asciidoctor(String),
+}
+
+/// Options that change how markdown is converted to html
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub markdown: MarkdownConfig,
+    pub highlight: HighlightConfig,
 }
 
 #[derive(Debug)]
@@ -52,13 +90,16 @@ impl From<FrontmatterAndBody> for Context {
 }
 
 pub fn extract_frontmatter(markdown: &str) -> Result<(Frontmatter, String), Error> {
-    let mut sections = markdown.split("+++\n");
+    // The +++ lines can end in \r\n, e.g. in files checked out on Windows with git's autocrlf
+    let separator_re = Regex::new(r"\+\+\+\r?\n").expect("should be able to compile regex");
+    let sections: Vec<&str> = separator_re.split(markdown).collect();
+    let mut sections = sections.into_iter();
     // If there are less than 3 sections, there is no frontmatter,
     // so we just return the whole thing as the body.
     // If there are three or more sections, there is frontmatter,
     // so we parse the frontmatter and the body.
     // any further +++ lines are just part of the body
-    let frontmatter: Frontmatter = if sections.clone().count() < 3 {
+    let frontmatter: Frontmatter = if sections.len() < 3 {
         toml::from_str("").expect("empty frontmatter should be valid")
     } else {
         // get rid of the first `+++` line
@@ -76,10 +117,68 @@ pub fn extract_frontmatter(markdown: &str) -> Result<(Frontmatter, String), Erro
     Ok((frontmatter, body))
 }
 
-pub fn parse(markdown: &str) -> Result<FrontmatterAndBody, Error> {
+/// Merge the JSON frontmatter from a sidecar file (e.g. my-post.md.json) into the frontmatter from the file itself.
+/// Top level keys in the sidecar replace the same keys in the file's frontmatter. Other keys from both are kept.
+pub fn merge_sidecar(
+    frontmatter: Frontmatter,
+    sidecar: Option<&str>,
+) -> Result<Frontmatter, Error> {
+    let Some(sidecar) = sidecar else {
+        return Ok(frontmatter);
+    };
+    let sidecar: serde_json::Value =
+        serde_json::from_str(sidecar).map_err(|e| Error::InvalidSidecar(e.to_string()))?;
+    let sidecar =
+        Frontmatter::try_from(sidecar).map_err(|e| Error::InvalidSidecar(e.to_string()))?;
+    let Frontmatter::Table(sidecar) = sidecar else {
+        return Err(Error::InvalidSidecar(
+            "sidecar frontmatter must be a JSON object".to_string(),
+        ));
+    };
+    let mut merged = match frontmatter {
+        Frontmatter::Table(table) => table,
+        _ => toml::map::Map::new(),
+    };
+    merged.extend(sidecar);
+    Ok(Frontmatter::Table(merged))
+}
+
+/// Merge the frontmatter defaults for a file's directory (from its _defaults.toml files) into its frontmatter.
+/// Top level keys in the file's frontmatter replace the same keys in the defaults.
+pub fn merge_defaults(frontmatter: Frontmatter, defaults: &toml::Table) -> Frontmatter {
+    if defaults.is_empty() {
+        return frontmatter;
+    }
+    let mut merged = defaults.clone();
+    if let Frontmatter::Table(table) = frontmatter {
+        merged.extend(table);
+    }
+    Frontmatter::Table(merged)
+}
+
+/// Parse a markdown file with TOML frontmatter.
+/// Shortcodes in the body are expanded using the templates in shortcodes/ before the markdown is parsed.
+pub fn parse(markdown: &str, templates: &Tera) -> Result<FrontmatterAndBody, Error> {
+    parse_with_options(markdown, templates, &ParseOptions::default())
+}
+
+/// Parse a markdown file with TOML frontmatter, using options from the site config
+pub fn parse_with_options(
+    markdown: &str,
+    templates: &Tera,
+    options: &ParseOptions,
+) -> Result<FrontmatterAndBody, Error> {
     let (frontmatter, body) = extract_frontmatter(markdown)?;
-    let html = parse_markdown(&body)?;
-    let excerpt = extract_excerpt(&body)?;
+    let body = expand_shortcodes(&body, templates)?;
+    // The excerpt is the start of the body, so its code blocks were already highlighted for the body
+    let mut highlighted = HashMap::new();
+    let html = match &options.markdown.excerpt_separator {
+        Some(separator) => {
+            parse_markdown(&body.replacen(separator, "", 1), options, &mut highlighted)?
+        }
+        None => parse_markdown(&body, options, &mut highlighted)?,
+    };
+    let excerpt = extract_excerpt(&body, options, &mut highlighted)?;
 
     Ok(FrontmatterAndBody {
         frontmatter,
@@ -88,30 +187,256 @@ pub fn parse(markdown: &str) -> Result<FrontmatterAndBody, Error> {
     })
 }
 
-fn extract_excerpt(body: &str) -> Result<Option<String>, Error> {
-    let excerpt_re = Regex::new(r"\s*<!--more-->\s*\n").expect("should be able to compile regex");
-    let mut split = excerpt_re.splitn(body, 2);
-    let excerpt = split.next().map(|s| s.to_string());
-    if excerpt.is_none() || split.next().is_none() {
-        return Ok(None);
+/// Converts content files in one format, like markdown, to html.
+/// Every format has the same TOML frontmatter and shortcodes; only the body is converted differently.
+pub trait ContentParser: Sync {
+    /// The file extensions of the format, without the leading .
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn parse(
+        &self,
+        content: &str,
+        templates: &Tera,
+        options: &ParseOptions,
+    ) -> Result<FrontmatterAndBody, Error>;
+}
+
+/// Markdown, the default format
+pub struct Markdown;
+
+impl ContentParser for Markdown {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["md", "markdown"]
     }
-    let excerpt = excerpt.unwrap_or_default();
-    let html = parse_markdown(&excerpt)?;
+
+    fn parse(
+        &self,
+        content: &str,
+        templates: &Tera,
+        options: &ParseOptions,
+    ) -> Result<FrontmatterAndBody, Error> {
+        parse_with_options(content, templates, options)
+    }
+}
+
+/// AsciiDoc, converted by running asciidoctor, which has to be installed.
+/// Code is highlighted by asciidoctor rather than kalamos, and there is no excerpt.
+/// Shortcodes are expanded into passthroughs, so that asciidoctor keeps their html. See expand_asciidoc_shortcodes.
+pub struct AsciiDoc;
+
+impl ContentParser for AsciiDoc {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["adoc", "asciidoc"]
+    }
+
+    fn parse(
+        &self,
+        content: &str,
+        templates: &Tera,
+        _options: &ParseOptions,
+    ) -> Result<FrontmatterAndBody, Error> {
+        let (frontmatter, body) = extract_frontmatter(content)?;
+        let body = expand_asciidoc_shortcodes(&body, templates)?;
+        // --embedded leaves out the <html>, <head> and <body>, as the page's template provides them
+        let mut child = Command::new("asciidoctor")
+            .args(["--embedded", "--out-file", "-", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Asciidoctor(e.to_string()))?;
+        child
+            .stdin
+            .take()
+            .ok_or(Error::Asciidoctor("no stdin".to_string()))?
+            .write_all(body.as_bytes())
+            .map_err(|e| Error::Asciidoctor(e.to_string()))?;
+        let output = child
+            .wait_with_output()
+            .map_err(|e| Error::Asciidoctor(e.to_string()))?;
+        if !output.status.success() {
+            return Err(Error::Asciidoctor(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(FrontmatterAndBody {
+            frontmatter,
+            body: String::from_utf8_lossy(&output.stdout).to_string(),
+            excerpt: None,
+        })
+    }
+}
+
+/// The formats that content can be written in
+pub static CONTENT_PARSERS: [&dyn ContentParser; 2] = [&Markdown, &AsciiDoc];
+
+/// The parser for files with an extension, or None if content can't be written in that format
+pub fn parser_for_extension(extension: &str) -> Option<&'static dyn ContentParser> {
+    CONTENT_PARSERS
+        .iter()
+        .find(|parser| parser.extensions().contains(&extension))
+        .copied()
+}
+
+/// Parse a content file with the parser for its extension. Files without a parser are parsed as markdown.
+pub fn parse_file(
+    extension: &str,
+    content: &str,
+    templates: &Tera,
+    options: &ParseOptions,
+) -> Result<FrontmatterAndBody, Error> {
+    parser_for_extension(extension)
+        .unwrap_or(&Markdown)
+        .parse(content, templates, options)
+}
+
+/// Expand the shortcodes in a markdown body.
+/// A shortcode looks like `{{< callout type="warning" >}}Be careful!{{< /callout >}}`, and is rendered
+/// using the shortcodes/callout.html template. The arguments are available as variables in the template,
+/// and the content between the opening and closing shortcode is available as `inner`.
+/// The closing shortcode is optional: `{{< signup >}}` is rendered with an empty `inner`.
+/// Shortcodes can't be nested. Shortcodes in code blocks and inline code are left as they are,
+/// so that a post can show how to use them.
+pub fn expand_shortcodes(body: &str, templates: &Tera) -> Result<String, Error> {
+    expand_shortcodes_with(body, templates, |rendered, _| rendered)
+}
+
+/// Expand the shortcodes in an AsciiDoc body, like expand_shortcodes.
+/// asciidoctor escapes html, so each rendered shortcode is wrapped in a passthrough: a ++++ block
+/// when the shortcode is on lines of its own, and an inline pass:[] otherwise.
+pub fn expand_asciidoc_shortcodes(body: &str, templates: &Tera) -> Result<String, Error> {
+    expand_shortcodes_with(body, templates, |rendered, on_own_lines| {
+        if on_own_lines {
+            format!("\n++++\n{}\n++++\n", rendered.trim_end())
+        } else {
+            format!("pass:[{}]", rendered.replace(']', "\\]"))
+        }
+    })
+}
+
+/// Expand the shortcodes in body, passing each rendered shortcode through wrap, along with whether the shortcode
+/// is on lines of its own
+fn expand_shortcodes_with(
+    body: &str,
+    templates: &Tera,
+    wrap: impl Fn(String, bool) -> String,
+) -> Result<String, Error> {
+    let shortcode_re = Regex::new(r#"\{\{<\s*(/?)([\w-]+)((?:\s+[\w-]+\s*=\s*"[^"]*")*)\s*>\}\}"#)
+        .expect("should be able to compile regex");
+    let arg_re =
+        Regex::new(r#"([\w-]+)\s*=\s*"([^"]*)""#).expect("should be able to compile regex");
+
+    let code_ranges = code_ranges(body);
+    let mut expanded = String::new();
+    let mut position = 0;
+    while let Some(captures) = shortcode_re.captures_at(body, position) {
+        let tag = captures.get(0).expect("should have a match");
+        if code_ranges.iter().any(|code| code.contains(&tag.start())) {
+            expanded.push_str(&body[position..tag.end()]);
+            position = tag.end();
+            continue;
+        }
+        let name = &captures[2];
+        if !captures[1].is_empty() {
+            return Err(Error::UnexpectedClosingShortcode(name.to_string()));
+        }
+        let template_name = format!("shortcodes/{}.html", name);
+        if !templates.get_template_names().any(|t| t == template_name) {
+            return Err(Error::UnknownShortcode(name.to_string()));
+        }
+
+        let mut context = Context::new();
+        for arg in arg_re.captures_iter(&captures[3]) {
+            context.insert(&arg[1], &arg[2]);
+        }
+        let closing_re = Regex::new(&format!(r"\{{\{{<\s*/{}\s*>\}}\}}", regex::escape(name)))
+            .expect("should be able to compile regex");
+        let (inner, end) = match closing_re.find_at(body, tag.end()) {
+            Some(closing) => (&body[tag.end()..closing.start()], closing.end()),
+            None => ("", tag.end()),
+        };
+        context.insert("inner", inner);
+        let rendered = templates
+            .render(&template_name, &context)
+            .map_err(|e| Error::RenderShortcode(name.to_string(), format!("{:?}", e)))?;
+
+        let on_own_lines = body[..tag.start()].ends_with('\n') || tag.start() == 0;
+        let on_own_lines = on_own_lines && (body[end..].starts_with('\n') || end == body.len());
+        expanded.push_str(&body[position..tag.start()]);
+        expanded.push_str(&wrap(rendered, on_own_lines));
+        position = end;
+    }
+    expanded.push_str(&body[position..]);
+    Ok(expanded)
+}
+
+/// The byte ranges of the code blocks and inline code in a markdown body
+fn code_ranges(body: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    for (event, range) in pulldown_cmark::Parser::new(body).into_offset_iter() {
+        match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(_))
+            | pulldown_cmark::Event::Code(_) => ranges.push(range),
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// The marker that ends the excerpt. Spaces inside the comment are allowed, e.g. <!-- more -->
+const MORE_MARKER: &str = r"<!--\s*more\s*-->";
+
+fn extract_excerpt(
+    body: &str,
+    options: &ParseOptions,
+    highlighted: &mut HighlightedCodeBlocks,
+) -> Result<Option<String>, Error> {
+    let excerpt = match &options.markdown.excerpt_separator {
+        Some(separator) => body
+            .split_once(separator.as_str())
+            .map(|(excerpt, _)| excerpt),
+        None => {
+            let excerpt_re = Regex::new(&format!(r"\s*{}", MORE_MARKER))
+                .expect("should be able to compile regex");
+            excerpt_re.find(body).map(|marker| &body[..marker.start()])
+        }
+    };
+    let Some(excerpt) = excerpt else {
+        return Ok(None);
+    };
+    let html = parse_markdown(excerpt.trim_end(), options, highlighted)?;
     Ok(Some(html))
 }
 
-fn parse_markdown(body: &str) -> Result<String, Error> {
-    let ts = ThemeSet::load_defaults();
-    let theme = ts.themes.get("InspiredGitHub").expect("should have theme");
-    let syntax_set = SyntaxSet::load_defaults_newlines();
-    let events = pulldown_cmark::Parser::new(body);
+/// Convert markdown without frontmatter or shortcodes to html, e.g. an excerpt written in the frontmatter
+pub fn markdown_to_html(markdown: &str, options: &ParseOptions) -> Result<String, Error> {
+    parse_markdown(markdown, options, &mut HashMap::new())
+}
+
+/// Highlighted html for code blocks, keyed by the language and the code
+type HighlightedCodeBlocks = HashMap<(String, String), String>;
+
+fn parse_markdown(
+    body: &str,
+    options: &ParseOptions,
+    highlighted_code_blocks: &mut HighlightedCodeBlocks,
+) -> Result<String, Error> {
+    let mut markdown_options = pulldown_cmark::Options::empty();
+    if options.markdown.smart_punctuation {
+        markdown_options.insert(pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION);
+    }
+    if options.markdown.math {
+        markdown_options.insert(pulldown_cmark::Options::ENABLE_MATH);
+    }
+    let events = pulldown_cmark::Parser::new_ext(body, markdown_options);
     let mut highlighted_events = vec![];
     let mut in_codeblock = false;
     let mut codeblock_contents = String::new();
     let mut syntax_extension = String::new();
-    let default_syntax = syntax_set.find_syntax_plain_text();
+    let more_re = Regex::new(MORE_MARKER).expect("should be able to compile regex");
 
     for event in events {
+        #[allow(clippy::collapsible_match)]
         match event.clone() {
             // Start collecting codeblock contents
             pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(kind)) => {
@@ -127,16 +452,16 @@ fn parse_markdown(body: &str) -> Result<String, Error> {
             // End of a codeblock. Highlight the codeblock and add it to the highlighted events
             pulldown_cmark::Event::End(pulldown_cmark::TagEnd::CodeBlock) => {
                 in_codeblock = false;
-                let syntax = syntax_set
-                    .find_syntax_by_token(&syntax_extension)
-                    .unwrap_or(default_syntax);
-                let highlighted = syntect::html::highlighted_html_for_string(
-                    &codeblock_contents,
-                    &syntax_set,
-                    syntax,
-                    theme,
-                )
-                .unwrap_or(codeblock_contents.clone());
+                let key = (syntax_extension.clone(), codeblock_contents.clone());
+                let highlighted = match highlighted_code_blocks.get(&key) {
+                    Some(highlighted) => highlighted.clone(),
+                    None => {
+                        let highlighted =
+                            highlight_code_block(&codeblock_contents, &syntax_extension, options)?;
+                        highlighted_code_blocks.insert(key, highlighted.clone());
+                        highlighted
+                    }
+                };
                 highlighted_events.push(pulldown_cmark::Event::Html(highlighted.clone().into()));
             }
             pulldown_cmark::Event::Text(text) => {
@@ -146,6 +471,39 @@ fn parse_markdown(body: &str) -> Result<String, Error> {
                     highlighted_events.push(event.clone());
                 }
             }
+            // Math is wrapped in the \( \) and \[ \] delimiters that KaTeX's auto-render looks for by default
+            pulldown_cmark::Event::InlineMath(math) => {
+                highlighted_events.push(pulldown_cmark::Event::InlineHtml(
+                    format!(
+                        r#"<span class="math inline">\({}\)</span>"#,
+                        tera::escape_html(&math)
+                    )
+                    .into(),
+                ));
+            }
+            pulldown_cmark::Event::DisplayMath(math) => {
+                highlighted_events.push(pulldown_cmark::Event::InlineHtml(
+                    format!(
+                        r#"<span class="math display">\[{}\]</span>"#,
+                        tera::escape_html(&math)
+                    )
+                    .into(),
+                ));
+            }
+            // The excerpt marker is consumed. Any other html, including comments, is passed through.
+            pulldown_cmark::Event::Html(html) if more_re.is_match(&html) => {
+                let html = more_re.replace_all(&html, "");
+                if !html.trim().is_empty() {
+                    highlighted_events.push(pulldown_cmark::Event::Html(html.to_string().into()));
+                }
+            }
+            pulldown_cmark::Event::InlineHtml(html) if more_re.is_match(&html) => {
+                let html = more_re.replace_all(&html, "");
+                if !html.is_empty() {
+                    highlighted_events
+                        .push(pulldown_cmark::Event::InlineHtml(html.to_string().into()));
+                }
+            }
             _ => {
                 highlighted_events.push(event.clone());
             }
@@ -155,3 +513,81 @@ fn parse_markdown(body: &str) -> Result<String, Error> {
     pulldown_cmark::html::push_html(&mut html, highlighted_events.into_iter());
     Ok(html)
 }
+
+/// Highlight a code block with syntect, using the language's syntax if it is known
+fn highlight_code_block(
+    code: &str,
+    language: &str,
+    options: &ParseOptions,
+) -> Result<String, Error> {
+    let syntax_set = &*SYNTAX_SET;
+    let syntax = syntax_set
+        .find_syntax_by_token(language)
+        .unwrap_or(syntax_set.find_syntax_plain_text());
+    if options.highlight.classes {
+        return highlighted_html_with_classes(
+            code,
+            syntax_set,
+            syntax,
+            &options.highlight.class_prefix,
+        );
+    }
+    let theme = THEME_SET
+        .themes
+        .get(HIGHLIGHT_THEME)
+        .expect("should have theme");
+    Ok(
+        syntect::html::highlighted_html_for_string(code, syntax_set, syntax, theme)
+            .unwrap_or(code.to_string()),
+    )
+}
+
+/// Highlight a code block using css classes, which are styled by highlight_css, instead of inline styles
+fn highlighted_html_with_classes(
+    code: &str,
+    syntax_set: &SyntaxSet,
+    syntax: &syntect::parsing::SyntaxReference,
+    prefix: &str,
+) -> Result<String, Error> {
+    let class_style = ClassStyle::SpacedPrefixed {
+        prefix: static_prefix(prefix),
+    };
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, class_style);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .map_err(|e| Error::Highlight(e.to_string()))?;
+    }
+    Ok(format!(
+        "<pre class=\"{}code\"><code>{}</code></pre>\n",
+        prefix,
+        generator.finalize()
+    ))
+}
+
+/// The stylesheet for code blocks highlighted with css classes with the given prefix
+pub fn highlight_css(prefix: &str) -> Result<String, Error> {
+    let theme = THEME_SET
+        .themes
+        .get(HIGHLIGHT_THEME)
+        .expect("should have theme");
+    let class_style = ClassStyle::SpacedPrefixed {
+        prefix: static_prefix(prefix),
+    };
+    syntect::html::css_for_theme_with_class_style(theme, class_style)
+        .map_err(|e| Error::Highlight(e.to_string()))
+}
+
+/// syntect needs a &'static str for the class prefix. Each distinct prefix is leaked once,
+/// so rebuilding the site in watch mode doesn't leak more memory.
+fn static_prefix(prefix: &str) -> &'static str {
+    static PREFIXES: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+    let mut prefixes = PREFIXES.lock().expect("prefix lock should not be poisoned");
+    let prefixes = prefixes.get_or_insert_with(HashSet::new);
+    if let Some(existing) = prefixes.get(prefix) {
+        return existing;
+    }
+    let leaked: &'static str = Box::leak(prefix.to_string().into_boxed_str());
+    prefixes.insert(leaked);
+    leaked
+}
@@ -1,14 +1,20 @@
-use chrono::{Datelike, NaiveDate};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tera::{Context, Tera};
 
+use crate::config::LineEndings;
+use crate::context::ContentContext;
+use crate::lang::{self, Translation};
 use crate::parser;
-use crate::render::Render;
-use crate::render::{Error as RenderError, RenderableFromPath};
+use crate::render::{self, Error as RenderError, RenderableFromPath};
+use crate::render::{ReadOptions, Render};
+use crate::section::Section;
+use crate::url;
+use crate::util;
 
-#[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Post {
     /// path of the input file, relative to the root of the site
     /// posts/my-post.html
@@ -24,23 +30,105 @@ pub struct Post {
     pub content: String,
     /// The excerpt of the page. This is the content of the page up to the first <!--more-->
     /// in a markdown file. If it is a non-markdown file, or if there is no <!--more--> in a markdown file,
-    /// it will be the same as the content.
+    /// it will be the same as the content. An excerpt in the frontmatter takes precedence over all of these.
     pub excerpt: String,
+    /// True if the excerpt is shorter than the body, e.g. it ended at a <!--more--> marker or is from the frontmatter,
+    /// so there is more to read than the excerpt
+    pub has_more: bool,
     /// The date the post was published
     pub date: NaiveDate,
+    /// The date and time the post was published, with its timezone.
+    /// This comes from the date in the frontmatter if there is one. Otherwise, it is midnight UTC on the date in the file name.
+    pub datetime: DateTime<FixedOffset>,
     /// The date the post was published, as a string in the format YYYY-MM-DD
     pub date_str: String,
     /// The date the post was published as a DateStruct
     pub date_struct: DateStruct,
+    /// The datetime the post was published, in the format of TemplatesConfig::date_format
+    pub date_formatted: String,
     /// The url of the post. This is output_path, but with a leading / and an extension of html
     /// /2024/12/28/my-post.html
     pub url: PathBuf,
     /// The slug of the post
     /// my-post
     pub slug: String,
+    /// The slug as a valid html id, to link to the post when it is rendered in a list of posts
+    pub anchor: String,
+    /// The tags of the post
+    pub tags: Vec<String>,
+    /// The authors of the post, from author and authors in the frontmatter
+    pub authors: Vec<String>,
+    /// If this is true, search engines should not index the post
+    pub noindex: bool,
+    /// If this is false, the post is left out of the sitemap list
+    pub sitemap: bool,
+    /// Old urls of the post, which redirect to it
+    pub aliases: Vec<String>,
+    /// Drafts are only rendered when RenderOptions::drafts is true
+    pub draft: bool,
+    /// The posts that share the most tags with this post, most shared tags first.
+    /// This is filled in by set_related_posts.
+    #[serde(skip)]
+    pub related: Vec<PostSummary>,
+    /// The name of the series the post is part of, e.g. a multi-part tutorial
+    pub series: Option<String>,
+    /// The position of the post in its series. Posts without one are ordered by date after the posts with one.
+    pub series_order: Option<i64>,
+    /// Featured posts are listed in featured_posts, e.g. for a carousel on the home page
+    pub featured: bool,
+    /// The position of the post in featured_posts. Featured posts without one come after those with one, newest first.
+    pub featured_order: Option<i64>,
+    /// Where the post is in its series. This is filled in by set_series.
+    #[serde(skip)]
+    pub series_position: Option<SeriesPosition>,
+    /// The language of the post, from the language directory it is in on a multilingual site
+    pub lang: Option<String>,
+    /// The posts with the same slug in other languages. This is filled in by set_translations.
+    #[serde(skip)]
+    pub translations: Vec<Translation>,
+    /// The frontmatter schema violations of the post, which end up in RenderReport::warnings
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+/// Where a post is in its series, used for "Part 2 of 5" navigation
+#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct SeriesPosition {
+    /// The position of the post in the series, starting at 1
+    pub index: usize,
+    /// The number of posts in the series
+    pub total: usize,
+    pub prev: Option<PostSummary>,
+    pub next: Option<PostSummary>,
+}
+
+/// A short description of a post, used when a post needs to refer to other posts
+#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct PostSummary {
+    pub title: String,
+    pub url: PathBuf,
+    pub date: NaiveDate,
+    pub date_str: String,
+    pub date_formatted: String,
+    pub slug: String,
+    pub excerpt: String,
+}
+
+impl From<&Post> for PostSummary {
+    fn from(post: &Post) -> Self {
+        Self {
+            title: post.title.clone(),
+            url: post.url.clone(),
+            date: post.date,
+            date_str: post.date_str.clone(),
+            date_formatted: post.date_formatted.clone(),
+            slug: post.slug.clone(),
+            excerpt: post.excerpt.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct PostFile {
     pub date: NaiveDate,
     pub slug: String,
@@ -54,7 +142,13 @@ impl TryFrom<PathBuf> for PostFile {
     type Error = RenderError;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        let (date, slug) = Self::extract_date_and_slug(&path)?;
+        // The date and slug of a bundle, like posts/2024-12-01-my-post/index.md, come from its directory
+        let (date, slug) = match path.parent() {
+            Some(bundle_dir) if Post::is_bundle_index(&path) => {
+                Self::extract_date_and_slug(bundle_dir)?
+            }
+            _ => Self::extract_date_and_slug(&path)?,
+        };
         let extension = path
             .extension()
             .unwrap_or_default()
@@ -66,17 +160,15 @@ impl TryFrom<PathBuf> for PostFile {
                 "not a valid extension".to_string(),
             ));
         }
-        let date_path = date.format("%Y/%m");
-        // E.g. /2024/12/my-post.html
-        let url = PathBuf::from(format!("/{}/{}.html", date_path, slug));
-        let output_path = PathBuf::from(format!("{}/{}.html", date_path, slug));
+        // E.g. 2024/12/my-post.html
+        let output_path = url::post_output_path(date, &slug, url::DEFAULT_POST_PATTERN)?;
         Ok(Self {
             date,
             slug,
             extension: extension.to_string(),
-            url: url.clone(),
+            url: url::url_from_output_path(&output_path),
             input_path: path.to_path_buf(),
-            output_path: output_path.clone(),
+            output_path,
         })
     }
 }
@@ -96,6 +188,60 @@ impl RenderableFromPath for PostFile {
 }
 
 impl PostFile {
+    /// Replace the url and output path with the ones from a permalink
+    pub fn with_permalink(&self, permalink: &str) -> Result<Self, RenderError> {
+        let output_path = url::output_path_from_permalink(permalink)?;
+        Ok(Self {
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            ..self.clone()
+        })
+    }
+
+    /// Write the post with another extension than html, from ReadOptions::html_extension
+    pub fn with_html_extension(&self, extension: &str) -> Self {
+        let output_path = url::with_html_extension(&self.output_path, extension);
+        Self {
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            ..self.clone()
+        }
+    }
+
+    /// Put the post under a language directory, e.g. /es/2024/12/my-post.html
+    pub fn with_language(&self, lang: &str) -> Self {
+        let output_path = Path::new(lang).join(&self.output_path);
+        Self {
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            ..self.clone()
+        }
+    }
+
+    /// Replace the date that was taken from the file name, e.g. with the date in the frontmatter,
+    /// and update the url and output path to match
+    pub fn with_date(&self, date: NaiveDate) -> Result<Self, RenderError> {
+        let output_path = url::post_output_path(date, &self.slug, url::DEFAULT_POST_PATTERN)?;
+        Ok(Self {
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            date,
+            ..self.clone()
+        })
+    }
+
+    /// Replace the slug that was taken from the file name, and update the url and output path to match
+    pub fn with_slug(&self, slug: &str) -> Result<Self, RenderError> {
+        let slug = util::slugify(slug)?;
+        let output_path = url::post_output_path(self.date, &slug, url::DEFAULT_POST_PATTERN)?;
+        Ok(Self {
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            slug,
+            ..self.clone()
+        })
+    }
+
     /// Extracts the date and slug from a file name
     /// The file name must be in the format YYYY-MM-DD-slug.md
     fn extract_date_and_slug(path: &Path) -> Result<(NaiveDate, String), RenderError> {
@@ -125,22 +271,306 @@ impl PostFile {
 impl Post {
     pub const DEFAULT_TEMPLATE: &str = "post";
     pub const READ_DIRECTORY: &str = "posts";
-    pub const VALID_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+    pub const VALID_EXTENSIONS: [&str; 4] = ["md", "markdown", "adoc", "asciidoc"];
+    /// The file names of the post in a bundle directory
+    pub const BUNDLE_INDEX_NAMES: [&str; 4] =
+        ["index.md", "index.markdown", "index.adoc", "index.asciidoc"];
+
+    /// A post is a bundle if it is an index.md in a directory named like a post, e.g. posts/2024-12-01-my-post/index.md.
+    /// The other files in the directory are its assets, which are copied next to it.
+    pub fn is_bundle_index(path: &Path) -> bool {
+        let is_index = path.file_name().is_some_and(|name| {
+            Self::BUNDLE_INDEX_NAMES.contains(&name.to_string_lossy().as_ref())
+        });
+        is_index
+            && path
+                .parent()
+                .is_some_and(|dir| PostFile::extract_date_and_slug(dir).is_ok())
+    }
+
+    /// The directory holding the post and its assets, if the post is a bundle
+    pub fn bundle_dir(&self) -> Option<&Path> {
+        self.input_path
+            .parent()
+            .filter(|_| Self::is_bundle_index(&self.input_path))
+    }
+
+    /// Fill in the related posts for each post. The related posts for a post are the
+    /// limit other posts that share the most tags with it, with ties going to the most recent post.
+    /// Posts that don't share any tags are never related.
+    pub fn set_related_posts(posts: &mut [Post], limit: usize) {
+        let related = posts
+            .iter()
+            .map(|post| {
+                let tags = post.tags.iter().collect::<HashSet<_>>();
+                let mut candidates = posts
+                    .iter()
+                    .filter(|other| other.input_path != post.input_path)
+                    .map(|other| {
+                        let shared = other.tags.iter().filter(|t| tags.contains(t)).count();
+                        (shared, other)
+                    })
+                    .filter(|(shared, _)| *shared > 0)
+                    .collect::<Vec<_>>();
+                candidates.sort_by(|(a_shared, a), (b_shared, b)| {
+                    b_shared
+                        .cmp(a_shared)
+                        .then_with(|| b.datetime.cmp(&a.datetime))
+                });
+                candidates
+                    .into_iter()
+                    .take(limit)
+                    .map(|(_, other)| PostSummary::from(other))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for (post, related) in posts.iter_mut().zip(related) {
+            post.related = related;
+        }
+    }
+
+    /// Fill in the translations of each post. Posts with the same slug in different languages are translations.
+    pub fn set_translations(posts: &mut [Post]) {
+        let entries = posts
+            .iter()
+            .map(|post| {
+                let key = post.lang.as_ref().map(|_| post.slug.clone());
+                (key, post.translation())
+            })
+            .collect::<Vec<_>>();
+        let translations = lang::find_translations(&entries);
+        for (post, translations) in posts.iter_mut().zip(translations) {
+            post.translations = translations;
+        }
+    }
+
+    fn translation(&self) -> Translation {
+        Translation {
+            lang: self.lang.clone().unwrap_or_default(),
+            title: self.title.clone(),
+            url: self.url.clone(),
+        }
+    }
+
+    /// The post with its body read again from root_dir, for a post that was read with ReadOptions::lazy_bodies.
+    /// Everything but the body is kept, including what was filled in after reading, like the related posts.
+    pub fn with_body(
+        &self,
+        root_dir: &Path,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Post, RenderError> {
+        let options = ReadOptions {
+            lazy_bodies: false,
+            ..options.clone()
+        };
+        let read = Self::read_from_paths(
+            root_dir,
+            vec![root_dir.join(&self.input_path)],
+            templates,
+            &options,
+        )?;
+        let content = read
+            .into_iter()
+            .next()
+            .map(|post| post.content)
+            .unwrap_or_default();
+        Ok(Post {
+            content,
+            ..self.clone()
+        })
+    }
+
+    /// Sort posts newest first by datetime, which is the date in the frontmatter if there is one,
+    /// so that a frontmatter date moves a post in the list as well as in the url.
+    /// Posts published at the same time are ordered by input path, last first.
+    pub fn sort_newest_first(posts: &mut [Post]) {
+        posts.sort_by(|a, b| {
+            b.datetime
+                .cmp(&a.datetime)
+                .then_with(|| b.input_path.cmp(&a.input_path))
+        });
+    }
+
+    /// The featured posts, ordered by featured_order and then by date, newest first.
+    /// posts must already be sorted newest first, see sort_newest_first.
+    pub fn featured(posts: &[Post]) -> Vec<&Post> {
+        let mut featured = posts.iter().filter(|p| p.featured).collect::<Vec<_>>();
+        // the sort is stable, so posts with the same featured_order stay newest first
+        featured.sort_by_key(|p| (p.featured_order.is_none(), p.featured_order));
+        featured
+    }
+
+    /// The posts grouped by the year they were published, newest year first, and newest post first within a year.
+    /// posts must already be sorted newest first, see sort_newest_first.
+    pub fn by_year(posts: &[Post]) -> Vec<PostsInYear<'_>> {
+        let mut years: Vec<PostsInYear> = vec![];
+        for post in posts {
+            match years.last_mut() {
+                Some(year) if year.year == post.date_struct.year => year.posts.push(post),
+                _ => years.push(PostsInYear {
+                    year: post.date_struct.year,
+                    posts: vec![post],
+                }),
+            }
+        }
+        years
+    }
+
+    /// Render the post with a template other than its own, e.g. for an output variant like AMP.
+    /// The context is the same as for the post's own template.
+    pub fn render_variant_to_string(
+        &self,
+        templates: &Tera,
+        template: &str,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<String, RenderError> {
+        let mut context = site_context.clone();
+        context.extend(self.to_context());
+        context.insert("posts", &posts);
+        templates
+            .render(template, &context)
+            .map_err(RenderError::Tera)
+    }
+
+    /// Fill in the series position of each post that is part of a series.
+    /// The posts in a series are ordered by series_order, and then by date, oldest first.
+    pub fn set_series(posts: &mut [Post]) {
+        let mut series: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, post) in posts.iter().enumerate() {
+            if let Some(name) = &post.series {
+                series.entry(name).or_default().push(i);
+            }
+        }
+        let mut positions = vec![None; posts.len()];
+        for indexes in series.values_mut() {
+            indexes.sort_by_key(|&i| {
+                let post = &posts[i];
+                (
+                    post.series_order.is_none(),
+                    post.series_order,
+                    post.datetime,
+                )
+            });
+            for (position, &i) in indexes.iter().enumerate() {
+                positions[i] = Some(SeriesPosition {
+                    index: position + 1,
+                    total: indexes.len(),
+                    prev: position
+                        .checked_sub(1)
+                        .map(|prev| PostSummary::from(&posts[indexes[prev]])),
+                    next: indexes
+                        .get(position + 1)
+                        .map(|&next| PostSummary::from(&posts[next])),
+                });
+            }
+        }
+        for (post, position) in posts.iter_mut().zip(positions) {
+            post.series_position = position;
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PostFrontmatter {
     pub title: String,
     pub template: Option<String>,
+    /// The date the post was published. This can be a date (2024-12-01) or a date and time
+    /// with an optional offset (2024-12-01T09:00:00-05:00). Times without an offset are UTC.
+    /// It replaces the date in the file name, including in the url, e.g. /2024/12/my-post.html.
+    pub date: Option<String>,
+    /// The tags of the post. Posts that share tags are related.
+    pub tags: Option<Vec<String>>,
+    /// The author of the post. Use authors for a post with more than one.
+    pub author: Option<String>,
+    /// The authors of the post, after author if both are set
+    pub authors: Option<Vec<String>>,
+    /// Overrides the slug from the file name
+    pub slug: Option<String>,
+    /// Overrides the whole url of the post, e.g. /about-us.html, instead of /YYYY/MM/slug.html
+    #[serde(alias = "path")]
+    pub permalink: Option<String>,
+    /// Ask search engines not to index the post. Exposed in the context as noindex,
+    /// so that templates can add <meta name="robots" content="noindex">
+    /// A post with noindex is also left out of the sitemap list.
+    pub noindex: Option<bool>,
+    /// Set this to false to leave the post out of the sitemap list
+    pub sitemap: Option<bool>,
+    /// Set this to true to leave the post out of the site unless drafts are being rendered
+    pub draft: Option<bool>,
+    /// The name of the series the post is part of
+    pub series: Option<String>,
+    /// The position of the post in its series
+    pub series_order: Option<i64>,
+    /// Set this to true to list the post in featured_posts
+    pub featured: Option<bool>,
+    /// The position of the post in featured_posts
+    pub featured_order: Option<i64>,
+    /// Old urls of the post, e.g. /old-slug.html. Each one gets a page that redirects to the post.
+    pub aliases: Option<Vec<String>>,
+    /// A summary in markdown to use as the excerpt, instead of the start of the post
+    pub excerpt: Option<String>,
+}
+
+impl PostFrontmatter {
+    /// Convert the frontmatter date to a DateTime. Dates without a time are midnight UTC.
+    fn datetime(&self, input_path: &Path) -> Result<Option<DateTime<FixedOffset>>, RenderError> {
+        let Some(date_str) = &self.date else {
+            return Ok(None);
+        };
+        let path = input_path.to_string_lossy().to_string();
+        let date = date_str.parse::<toml::value::Datetime>().map_err(|e| {
+            RenderError::ParseFrontmatter(format!(
+                "date for {:?}: {:?}: {}",
+                input_path, date_str, e
+            ))
+        })?;
+        let date_str = date.to_string();
+        let datetime = match (&date.date, &date.time, &date.offset) {
+            (Some(_), Some(_), Some(_)) => DateTime::parse_from_rfc3339(&date_str)
+                .map_err(|e| RenderError::ParseDate(path, e))?,
+            (Some(_), Some(_), None) => {
+                NaiveDateTime::parse_from_str(&date_str, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map_err(|e| RenderError::ParseDate(path, e))?
+                    .and_utc()
+                    .fixed_offset()
+            }
+            (Some(_), None, _) => midnight_utc(
+                NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
+                    .map_err(|e| RenderError::ParseDate(path, e))?,
+            ),
+            (None, _, _) => {
+                return Err(RenderError::ParseFrontmatter(format!(
+                    "date for {:?} must include a date: {}",
+                    input_path, date_str
+                )))
+            }
+        };
+        Ok(Some(datetime))
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Ord, PartialOrd)]
+fn midnight_utc(date: NaiveDate) -> DateTime<FixedOffset> {
+    date.and_time(chrono::NaiveTime::MIN)
+        .and_utc()
+        .fixed_offset()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct DateStruct {
     pub year: i32,
     pub month: u32,
     pub day: u32,
 }
 
+/// The posts published in a year, for posts_by_year in templates
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PostsInYear<'a> {
+    pub year: i32,
+    pub posts: Vec<&'a Post>,
+}
+
 impl From<NaiveDate> for DateStruct {
     fn from(date: NaiveDate) -> Self {
         Self {
@@ -158,29 +588,47 @@ impl Render for Post {
         Post::READ_DIRECTORY.to_string()
     }
 
+    /// Every file in a bundle directory, or one of its subdirectories, other than the post itself is an asset.
+    /// So is the _index.md of a section, which is read as a Section.
+    fn is_asset(path: &Path) -> bool {
+        if Section::is_index(path) {
+            return true;
+        }
+        if Post::is_bundle_index(path) {
+            return false;
+        }
+        path.ancestors().skip(1).any(|dir| {
+            Post::BUNDLE_INDEX_NAMES
+                .iter()
+                .any(|name| Post::is_bundle_index(&dir.join(name)) && dir.join(name).is_file())
+        })
+    }
+
     fn to_context(&self) -> Context {
-        let date_struct = DateStruct {
-            year: self.date.year(),
-            month: self.date.month(),
-            day: self.date.day(),
-        };
-        let mut context = Context::new();
-        context.insert("title", &self.title);
-        context.insert("path", &self.output_path);
-        context.insert("url", &self.url);
-        context.insert("date", &self.date);
-        context.insert("date_str", &self.date_str);
-        context.insert("date_struct", &date_struct);
-        context.insert("body", &self.content);
-        context.insert("context", &self.excerpt);
-        context.insert("slug", &self.slug);
-        context.insert("next", "nice");
-        context
-    }
-
-    fn from_content(post_file: PostFile, content: &str) -> Result<Self, RenderError> {
-        let parsed = parser::parse(content).map_err(RenderError::Markdown)?;
-        let res: PostFrontmatter = parsed.frontmatter.try_into().map_err(|e| {
+        ContentContext::from(self).into_context()
+    }
+
+    fn from_content_with_sidecar(
+        post_file: PostFile,
+        content: &str,
+        sidecar: Option<&str>,
+        defaults: &toml::Table,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Self, RenderError> {
+        let parsed = parser::parse_file(&post_file.extension, content, templates, &options.parse)
+            .map_err(|e| RenderError::Markdown(post_file.input_path.clone(), e))?;
+        let frontmatter = parser::merge_defaults(parsed.frontmatter, defaults);
+        let frontmatter = parser::merge_sidecar(frontmatter, sidecar)
+            .map_err(|e| RenderError::Markdown(post_file.input_path.clone(), e))?;
+        render::check_required_fields(&frontmatter, &post_file.input_path)?;
+        let warnings = render::check_schema(
+            &frontmatter,
+            &options.schema.post,
+            &post_file.input_path,
+            options.strict,
+        )?;
+        let res: PostFrontmatter = frontmatter.try_into().map_err(|e| {
             RenderError::ParseFrontmatter(format!(
                 "frontmatter for {:?}: {:?}",
                 post_file.input_path,
@@ -188,8 +636,51 @@ impl Render for Post {
             ))
         })?;
 
-        let mut template = res.template.unwrap_or(Post::DEFAULT_TEMPLATE.to_string());
+        // A date in the frontmatter replaces the one in the file name, in the url as well
+        let datetime = res.datetime(&post_file.input_path)?;
+        let post_file = match datetime {
+            Some(datetime) => post_file.with_date(datetime.date_naive())?,
+            None => post_file,
+        };
+        let post_file = match &res.slug {
+            Some(slug) => post_file.with_slug(slug)?,
+            None => post_file,
+        }
+        .with_html_extension(options.html_extension());
+        let lang = lang::language_of(
+            &post_file.input_path,
+            Post::READ_DIRECTORY,
+            &options.languages,
+        );
+        // A permalink is the whole url, so it isn't put under the language directory
+        let post_file = match (&res.permalink, &lang) {
+            (Some(permalink), _) => post_file.with_permalink(permalink)?,
+            (None, Some(lang)) => post_file.with_language(lang),
+            (None, None) => post_file,
+        };
+        let datetime = datetime.unwrap_or(midnight_utc(post_file.date));
+        let date = datetime.date_naive();
+        let date_format = options
+            .templates
+            .date_format
+            .as_deref()
+            .unwrap_or("%Y-%m-%d");
+        let date_formatted = util::format_datetime(&datetime, date_format)?;
+        let mut template = res.template.unwrap_or(
+            options
+                .templates
+                .post
+                .clone()
+                .unwrap_or(Post::DEFAULT_TEMPLATE.to_string()),
+        );
         template.push_str(".html");
+        // An excerpt in the frontmatter takes precedence over the one from the excerpt separator
+        let excerpt = match &res.excerpt {
+            Some(excerpt) => parser::markdown_to_html(excerpt, &options.parse)
+                .map_err(|e| RenderError::Markdown(post_file.input_path.clone(), e))?,
+            None => parsed.excerpt.unwrap_or(parsed.body.clone()),
+        };
+        let has_more = excerpt != parsed.body;
 
         Ok(Post {
             input_path: post_file.input_path.clone(),
@@ -197,36 +688,60 @@ impl Render for Post {
             title: res.title,
             template,
             content: parsed.body.clone(),
-            excerpt: parsed.excerpt.unwrap_or(parsed.body),
-            date: post_file.date,
-            date_str: post_file.date.format("%Y-%m-%d").to_string(),
-            date_struct: DateStruct::from(post_file.date),
+            excerpt,
+            has_more,
+            date,
+            datetime,
+            date_str: date.format("%Y-%m-%d").to_string(),
+            date_struct: DateStruct::from(date),
+            date_formatted,
             url: post_file.url.clone(),
             slug: post_file.slug.clone(),
+            anchor: util::html_id(&post_file.slug),
+            tags: res.tags.unwrap_or_default(),
+            authors: res
+                .author
+                .into_iter()
+                .chain(res.authors.unwrap_or_default())
+                .collect(),
+            noindex: res.noindex.unwrap_or_default(),
+            sitemap: res.sitemap.unwrap_or(true),
+            aliases: res.aliases.unwrap_or_default(),
+            draft: res.draft.unwrap_or_default(),
+            related: vec![],
+            series: res.series,
+            series_order: res.series_order,
+            featured: res.featured.unwrap_or_default(),
+            featured_order: res.featured_order,
+            series_position: None,
+            lang,
+            translations: vec![],
+            warnings,
         })
     }
 
+    fn drop_body(&mut self) {
+        self.content = String::new();
+    }
+
+    fn render_to_string(
+        &self,
+        templates: &Tera,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<String, RenderError> {
+        self.render_variant_to_string(templates, &self.template, posts, site_context)
+    }
+
     fn render(
         &self,
         templates: &Tera,
         output_dir: &Path,
         posts: &[Post],
+        site_context: &Context,
     ) -> Result<(), RenderError> {
-        let mut context = self.to_context();
-        context.insert("posts", &posts);
-        let output = templates
-            .render(&self.template, &context)
-            .map_err(RenderError::Tera)?;
-        let output_path = output_dir.join(self.output_path.clone());
-        let parent = output_path
-            .parent()
-            .ok_or(RenderError::CreateDir(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "no parent directory",
-            )))?;
-
-        fs::create_dir_all(parent).map_err(RenderError::CreateDir)?;
-        fs::write(&output_path, output).map_err(RenderError::WriteFile)?;
-        Ok(())
+        let output = self.render_to_string(templates, posts, site_context)?;
+        let output = util::normalize_output(&output, LineEndings::default());
+        render::write_output(&output_dir.join(&self.output_path), output)
     }
 }
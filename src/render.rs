@@ -1,13 +1,30 @@
 //! Render the whole static site.
+use chrono::{DateTime, NaiveDate, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
 use tera::{self, Context, Tera};
 use thiserror::Error;
 use walkdir::WalkDir;
 
+use crate::author;
+use crate::config::{self, Config};
+use crate::config::{FrontmatterSchema, SchemaConfig, TemplatesConfig};
+use crate::data;
+use crate::favicon;
+use crate::feed;
+use crate::git;
 use crate::page::Page;
-use crate::parser;
+use crate::parser::{self, ParseOptions};
 use crate::post::Post;
+use crate::robots;
+use crate::section::Section;
+use crate::sitemap;
+use crate::url;
 use crate::util;
 
 pub trait RenderableFromPath: TryFrom<PathBuf, Error = Error> + std::fmt::Debug {
@@ -22,28 +39,104 @@ where
 {
     type FileType: RenderableFromPath;
 
-    /// Create a Page or Post object from a file
-    fn from_content(file: Self::FileType, content: &str) -> Result<Self, Error>;
+    /// Create a Page or Post object from a file.
+    /// The templates are used to expand shortcodes in markdown content.
+    fn from_content(file: Self::FileType, content: &str, templates: &Tera) -> Result<Self, Error> {
+        Self::from_content_with_sidecar(
+            file,
+            content,
+            None,
+            &toml::Table::new(),
+            templates,
+            &ReadOptions::default(),
+        )
+    }
+
+    /// Create a Page or Post object from a file and the contents of its JSON sidecar file, if it has one.
+    /// Keys in the sidecar take precedence over the same keys in the file's frontmatter,
+    /// which take precedence over the defaults for the file's directory.
+    /// The read options come from the site config, e.g. how markdown is converted to html.
+    fn from_content_with_sidecar(
+        file: Self::FileType,
+        content: &str,
+        sidecar: Option<&str>,
+        defaults: &toml::Table,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Self, Error>;
 
     /// Generate a context for the template
     fn to_context(&self) -> Context;
 
-    /// Render the file and write it to the output directory
-    fn render(&self, templates: &Tera, output_dir: &Path, posts: &[Post]) -> Result<(), Error>;
+    /// Render the file, without writing it.
+    /// site_context holds the values shared by every page and post, such as the site data.
+    fn render_to_string(
+        &self,
+        templates: &Tera,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<String, Error>;
+
+    /// Render the file and write it to the output directory.
+    /// site_context holds the values shared by every page and post, such as the site data.
+    fn render(
+        &self,
+        templates: &Tera,
+        output_dir: &Path,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<(), Error>;
 
     /// The directory to read from. For Posts, this is the posts directory. For Pages, this is the pages directory.
     fn read_directory() -> String;
 
+    /// Files that belong to a Post or Page, like the images in a post bundle, rather than being one themselves.
+    /// They are skipped when reading.
+    fn is_asset(_path: &Path) -> bool {
+        false
+    }
+
+    /// Free the rendered body once it has been read, for ReadOptions::lazy_bodies.
+    /// Only Posts have bodies that can be read again later; Pages keep theirs.
+    fn drop_body(&mut self) {}
+
     /// For Posts, read all files in the posts directory and create Posts from them
     /// For Pages, read all files in the pages directory and create Pages from them
-    fn read_from_directory(root_dir: &Path) -> Result<Vec<Self>, Error> {
+    /// If the directory doesn't exist, e.g. on a brand new site, there are no Posts or Pages.
+    fn read_from_directory(root_dir: &Path, templates: &Tera) -> Result<Vec<Self>, Error> {
+        Self::read_from_directory_with_options(root_dir, templates, &ReadOptions::default())
+    }
+
+    /// Read all of the Posts or Pages, using the read options from the site config
+    fn read_from_directory_with_options(
+        root_dir: &Path,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Vec<Self>, Error> {
         let posts_path = root_dir.join(Self::read_directory());
-        let post_files = WalkDir::new(posts_path)
+        if !posts_path.is_dir() {
+            info!("{:?} does not exist, skipping it", posts_path);
+            return Ok(vec![]);
+        }
+        let paths = util::walk_files(&posts_path, options.output_dir.as_deref())
+            .map(|e| e.path().to_path_buf())
+            .collect::<Vec<_>>();
+        Self::read_from_paths(root_dir, paths, templates, options)
+    }
+
+    /// Create Posts or Pages from a list of files in root_dir.
+    /// If a file has a JSON sidecar file next to it, e.g. my-post.md.json, its frontmatter is merged in.
+    /// The frontmatter defaults in the _defaults.toml files of its directories are merged in as well.
+    fn read_from_paths(
+        root_dir: &Path,
+        paths: Vec<PathBuf>,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Vec<Self>, Error> {
+        let post_files = paths
             .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .map(|e| -> Result<Self::FileType, Error> {
-                let p = e.path().to_path_buf();
+            .filter(|p| !is_sidecar(p) && !is_defaults_file(p) && !Self::is_asset(p))
+            .map(|p| -> Result<Self::FileType, Error> {
                 let path = p
                     .strip_prefix(root_dir)
                     .map_err(|e| Error::StripPrefix(p.clone(), e))?
@@ -51,18 +144,151 @@ where
                 Self::FileType::try_from(path)
             })
             .collect::<Result<Vec<_>, Error>>()?;
+        let mut defaults_cache = HashMap::new();
         let posts = post_files
             .into_iter()
             .map(|post_file| {
+                let input_path = post_file.input_path();
+                let dir = input_path.parent().unwrap_or(Path::new(""));
+                let defaults = directory_defaults(root_dir, dir, &mut defaults_cache)?;
                 let full_path = root_dir.join(post_file.input_path().as_path());
-                let content = fs::read_to_string(full_path).map_err(Error::ReadFile)?;
-                Self::from_content(post_file, &content)
+                let content = fs::read_to_string(&full_path).map_err(Error::ReadFile)?;
+                let sidecar_path = sidecar_path(&full_path);
+                let sidecar = if sidecar_path.is_file() {
+                    Some(fs::read_to_string(sidecar_path).map_err(Error::ReadFile)?)
+                } else {
+                    None
+                };
+                let mut post = Self::from_content_with_sidecar(
+                    post_file,
+                    &content,
+                    sidecar.as_deref(),
+                    &defaults,
+                    templates,
+                    options,
+                )?;
+                if options.lazy_bodies {
+                    post.drop_body();
+                }
+                Ok(post)
             })
             .collect::<Result<Vec<_>, Error>>()?;
         Ok(posts.into_iter().collect())
     }
 }
 
+/// The frontmatter fields that every post and page must have
+pub const REQUIRED_FIELDS: [&str; 1] = ["title"];
+
+/// Check that frontmatter has all of the required fields, so that a missing one gets an error naming the field
+/// and the file, rather than a generic deserialization error
+pub fn check_required_fields(frontmatter: &toml::Value, input_path: &Path) -> Result<(), Error> {
+    let Some(table) = frontmatter.as_table() else {
+        return Ok(());
+    };
+    match REQUIRED_FIELDS
+        .iter()
+        .find(|field| !table.contains_key(**field))
+    {
+        Some(field) => Err(Error::MissingField(
+            input_path.to_path_buf(),
+            field.to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Check frontmatter against the schema from the config. Each violation is a warning, which is returned
+/// for RenderReport::warnings, unless strict is true, when they fail the render with the file and the fields
+/// that broke the schema.
+pub fn check_schema(
+    frontmatter: &toml::Value,
+    schema: &FrontmatterSchema,
+    input_path: &Path,
+    strict: bool,
+) -> Result<Vec<String>, Error> {
+    let Some(table) = frontmatter.as_table() else {
+        return Ok(vec![]);
+    };
+    let violations = schema.violations(table);
+    if violations.is_empty() {
+        return Ok(vec![]);
+    }
+    if strict {
+        return Err(Error::Schema(input_path.to_path_buf(), violations));
+    }
+    let warnings = violations
+        .into_iter()
+        .map(|violation| format!("frontmatter of {:?}: {}", input_path, violation))
+        .collect::<Vec<_>>();
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    Ok(warnings)
+}
+
+/// Write a rendered file, creating its directory if needed.
+/// Files that haven't changed since the last build aren't rewritten.
+pub fn write_output(output_path: &Path, content: impl AsRef<[u8]>) -> Result<(), Error> {
+    let parent = output_path
+        .parent()
+        .ok_or(Error::CreateDir(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no parent directory",
+        )))?;
+    fs::create_dir_all(parent).map_err(Error::CreateDir)?;
+    util::write_if_changed(output_path, content.as_ref())?;
+    Ok(())
+}
+
+/// The path of the JSON sidecar file for a post or page: my-post.md -> my-post.md.json
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar_path = path.as_os_str().to_os_string();
+    sidecar_path.push(".json");
+    PathBuf::from(sidecar_path)
+}
+
+/// The name of the file holding the frontmatter defaults for the posts or pages in its directory and below
+pub const DEFAULTS_FILE_NAME: &str = "_defaults.toml";
+
+fn is_defaults_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == DEFAULTS_FILE_NAME)
+}
+
+/// The frontmatter defaults for the posts or pages in dir, a directory relative to root_dir.
+/// The _defaults.toml files of dir and each of its parents, e.g. posts/_defaults.toml and
+/// posts/tutorials/_defaults.toml, are merged, with the deeper directories taking precedence.
+/// The root directory itself has no defaults.
+/// The defaults for each directory are cached, as most directories hold more than one file.
+pub fn directory_defaults(
+    root_dir: &Path,
+    dir: &Path,
+    cache: &mut HashMap<PathBuf, toml::Table>,
+) -> Result<toml::Table, Error> {
+    let Some(parent) = dir.parent() else {
+        return Ok(toml::Table::new());
+    };
+    if let Some(defaults) = cache.get(dir) {
+        return Ok(defaults.clone());
+    }
+    let mut defaults = directory_defaults(root_dir, parent, cache)?;
+    let defaults_path = root_dir.join(dir).join(DEFAULTS_FILE_NAME);
+    if defaults_path.is_file() {
+        let content = fs::read_to_string(&defaults_path).map_err(Error::ReadFile)?;
+        let table = content
+            .parse::<toml::Table>()
+            .map_err(|e| Error::InvalidDefaults(defaults_path.clone(), e.to_string()))?;
+        defaults.extend(table);
+    }
+    cache.insert(dir.to_path_buf(), defaults.clone());
+    Ok(defaults)
+}
+
+/// A file is a sidecar if it is a JSON file next to a file with the same name, without the .json
+fn is_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "json") && path.with_extension("").is_file()
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("tera error: {0}")]
@@ -71,14 +297,16 @@ pub enum Error {
     Path(PathBuf, String),
     #[error("read error: {0}")]
     ReadFile(std::io::Error),
-    #[error("markdown error: {0}")]
-    Markdown(parser::Error),
+    #[error("markdown error: {0}: {1}")]
+    Markdown(PathBuf, parser::Error),
     #[error("write error: {0}")]
     WriteFile(std::io::Error),
     #[error("parse frontmatter error: {0}")]
     ParseFrontmatter(String),
-    #[error("missing field: {0}")]
-    MissingField(String),
+    #[error("missing required field `{1}` in {0}")]
+    MissingField(PathBuf, String),
+    #[error("frontmatter of {path} doesn't match the schema: {violations}", path = .0.display(), violations = .1.join(", "))]
+    Schema(PathBuf, Vec<String>),
     #[error("extract date from file name: {0}. File name format should be YYYY-MM-DD-slug.md")]
     ExtractDate(String),
     #[error("parse date error: {0}")]
@@ -89,40 +317,1161 @@ pub enum Error {
     CreateDir(std::io::Error),
     #[error("copy dir error: {0}")]
     CopyDir(std::io::Error),
+    #[error("copy conflict: {0} is copied from both {1} and {2}")]
+    CopyConflict(PathBuf, PathBuf, PathBuf),
+    #[error("invalid copy map: {0} = {1:?}. Both sides must be relative paths without ..")]
+    InvalidCopyMap(PathBuf, String),
+    #[error("invalid frontmatter defaults: {0}: {1}")]
+    InvalidDefaults(PathBuf, String),
+    #[error("invalid favicon: {0}. The favicon source must be a PNG")]
+    InvalidFavicon(PathBuf),
+    #[error("invalid build date: {0:?}")]
+    InvalidBuildDate(String),
+    #[error("theme not found: {0}")]
+    MissingTheme(PathBuf),
+    #[error("parse data error: {0}: {1}")]
+    ParseData(PathBuf, String),
+    #[error("duplicate data key: {0} and {1} would both be data.{2}")]
+    DuplicateData(PathBuf, PathBuf, String),
+    #[error("clean error: {0}: {1}")]
+    Clean(PathBuf, String),
+    #[error("remove file error: {0}")]
+    RemoveFile(std::io::Error),
+    #[error("config error: {0:?}")]
+    Config(config::ConfigError),
+    #[error("invalid slug: {0:?}")]
+    InvalidSlug(String),
+    #[error("invalid permalink: {0:?}")]
+    InvalidPermalink(String),
+    #[error("output path collision: {0} is generated by both {1} and {2}")]
+    OutputPathCollision(PathBuf, PathBuf, PathBuf),
+    #[error("invalid xml: {0}: {1}")]
+    InvalidXml(PathBuf, String),
+    #[error("duplicate slug: {0:?} is used by both {1} and {2}")]
+    DuplicateSlug(String, PathBuf, PathBuf),
+    #[error("missing images (source file, image): {0:?}")]
+    MissingImages(Vec<(PathBuf, String)>),
+    #[error("empty output: no posts or pages were rendered from {0:?}")]
+    EmptyOutput(PathBuf),
+    #[error("invalid date format: {0:?}")]
+    InvalidDateFormat(String),
+    #[error("invalid glob {0:?}: {1}")]
+    InvalidGlob(String, String),
+    #[error("tag slug collision: {0:?} is the slug of both {1:?} and {2:?}")]
+    TagSlugCollision(String, String, String),
+}
+/// Load the templates in the templates directory of the site at path, with the extensions from the config.
+/// Eg. with the default config, load_templates("/path/to/project", &config) would load all the templates in
+/// /path/to/project/layouts/**/*.html
+/// Templates in subdirectories are named by their path, e.g. shortcodes/callout.html
+/// Posts and pages name their template without an extension, and .html is added, so a template with another
+/// extension, like post.tera, is also available as post.html unless there is a post.html.
+pub fn load_templates(path: &Path, config: &TemplatesConfig) -> Result<Tera, Error> {
+    load_templates_with_theme(path, config, None)
+}
+
+/// Load the templates of the site at path, and those in the layouts directory of the theme in theme_dir.
+/// A site template replaces the theme template with the same name, and the theme's templates can extend
+/// or include the site's, so a site can override just the parts of a theme it needs to, like base.html.
+pub fn load_templates_with_theme(
+    path: &Path,
+    config: &TemplatesConfig,
+    theme_dir: Option<&Path>,
+) -> Result<Tera, Error> {
+    let mut tera = parse_templates(&path.join(&config.dir), &config.extensions)?;
+    if let Some(theme_dir) = theme_dir {
+        let theme = parse_templates(&theme_dir.join(THEME_LAYOUTS_DIR), &config.extensions)?;
+        // extend only adds the theme templates that the site doesn't have
+        tera.extend(&theme).map_err(Error::Tera)?;
+    }
+    if let Some(base) = &config.base {
+        let wrapper = format!(
+            "{{% extends \"{}.html\" %}}{{% block content %}}{{{{ body | safe }}}}{{% endblock content %}}",
+            base
+        );
+        tera.add_raw_template(Page::BASE_TEMPLATE, &wrapper)
+            .map_err(Error::Tera)?;
+    }
+    tera.build_inheritance_chains().map_err(Error::Tera)?;
+    tera.check_macro_files().map_err(Error::Tera)?;
+    tera.register_function(
+        "include_file",
+        IncludeFile {
+            root_dir: path.to_path_buf(),
+        },
+    );
+    Ok(tera)
+}
+
+/// A template function that returns the contents of a file in the site, e.g. to inline an svg or critical css:
+/// {{ include_file(path="static/logo.svg") }}
+/// The path is relative to the root of the site, and can't point outside of it. The contents aren't escaped.
+pub struct IncludeFile {
+    pub root_dir: PathBuf,
 }
-/// pass in a path containing glob patterns for the pages
-/// Eg. load_templates("/path/to/project") would load all the templates in /path/to/project/layouts/*.html
-pub fn load_templates(path: &Path) -> Result<Tera, Error> {
-    let layout_path = path.join("layouts/*.html");
+
+impl tera::Function for IncludeFile {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = args
+            .get("path")
+            .and_then(|p| p.as_str())
+            .ok_or("include_file: the path argument is required and must be a string")?;
+        let root_dir = self
+            .root_dir
+            .canonicalize()
+            .map_err(|e| format!("include_file: {:?}: {}", self.root_dir, e))?;
+        let file = root_dir
+            .join(path)
+            .canonicalize()
+            .map_err(|e| format!("include_file: {:?}: {}", path, e))?;
+        if !file.starts_with(&root_dir) {
+            return Err(format!("include_file: {:?} is outside of the site", path).into());
+        }
+        let contents =
+            fs::read_to_string(&file).map_err(|e| format!("include_file: {:?}: {}", path, e))?;
+        Ok(tera::Value::String(contents))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// A template filter that formats a date or datetime with a strftime format:
+/// {{ post.date | date_fmt(format="%B %-d, %Y") }} gives December 1, 2024.
+/// It takes dates like 2024-12-01, which are midnight UTC, and RFC 3339 datetimes, like the datetime of posts.
+pub fn date_fmt(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let format = args
+        .get("format")
+        .and_then(|f| f.as_str())
+        .ok_or("date_fmt: the format argument is required and must be a string")?;
+    let value = value
+        .as_str()
+        .ok_or("date_fmt: the value must be a date or datetime string")?;
+    let datetime = DateTime::parse_from_rfc3339(value)
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|datetime| datetime.and_utc().fixed_offset())
+        })
+        .ok_or_else(|| format!("date_fmt: {:?} is not a date or datetime", value))?;
+    let formatted =
+        util::format_datetime(&datetime, format).map_err(|e| format!("date_fmt: {}", e))?;
+    Ok(tera::Value::String(formatted))
+}
+
+/// A template function that returns the subresource integrity hash of a static file, for its integrity attribute:
+/// <script src="/js/site.js" integrity="{{ asset_integrity(path="js/site.js") }}" crossorigin="anonymous"></script>
+/// The path is relative to the static directory, and a site's static file is used over the theme's.
+/// The hash is SHA-384, in base64, e.g. sha384-H8BR...; see util::sri_hash. Each file is hashed once per build.
+pub struct AssetIntegrity {
+    /// The static directories to look in, in order
+    pub static_dirs: Vec<PathBuf>,
+    hashes: Mutex<HashMap<PathBuf, String>>,
+}
+
+impl AssetIntegrity {
+    pub fn new(static_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            static_dirs,
+            hashes: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl tera::Function for AssetIntegrity {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = args
+            .get("path")
+            .and_then(|p| p.as_str())
+            .ok_or("asset_integrity: the path argument is required and must be a string")?;
+        let relative = Path::new(path.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(format!(
+                "asset_integrity: {:?} is outside of the static directory",
+                path
+            )
+            .into());
+        }
+        let mut hashes = self
+            .hashes
+            .lock()
+            .map_err(|_| "asset_integrity: the hashes are poisoned")?;
+        if let Some(hash) = hashes.get(relative) {
+            return Ok(tera::Value::String(hash.clone()));
+        }
+        let file = self
+            .static_dirs
+            .iter()
+            .map(|dir| dir.join(relative))
+            .find(|file| file.is_file())
+            .ok_or_else(|| format!("asset_integrity: there is no static file {:?}", path))?;
+        let content = fs::read(&file).map_err(|e| format!("asset_integrity: {:?}: {}", path, e))?;
+        let hash = util::sri_hash(&content);
+        hashes.insert(relative.to_path_buf(), hash.clone());
+        Ok(tera::Value::String(hash))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// The directory of a site or theme that holds the files that are copied to the output as they are
+pub const STATIC_DIR: &str = "static";
+
+/// The directory in a theme that holds its templates
+pub const THEME_LAYOUTS_DIR: &str = "layouts";
+
+/// Parse the templates in dir with the given extensions, and add the .html names of the templates with other
+/// extensions. The inheritance chains are not built, so that they can be built once every template is loaded.
+fn parse_templates(dir: &Path, extensions: &[String]) -> Result<Tera, Error> {
+    let extensions = match extensions {
+        [] => "html".to_string(),
+        [extension] => extension.clone(),
+        extensions => format!("{{{}}}", extensions.join(",")),
+    };
+    let layout_path = dir.join(format!("**/*.{}", extensions));
     let layout_path = layout_path.to_str().ok_or(Error::Path(
-        path.to_path_buf(),
+        dir.to_path_buf(),
         "path to templates not found".to_string(),
     ))?;
-    Tera::new(layout_path).map_err(Error::Tera)
+    let mut tera = Tera::parse(layout_path).map_err(Error::Tera)?;
+    let aliases = tera
+        .templates
+        .iter()
+        .filter_map(|(name, template)| {
+            let alias = Path::new(name).with_extension("html");
+            let alias = alias.to_string_lossy().to_string();
+            if alias == *name || tera.templates.contains_key(&alias) {
+                return None;
+            }
+            let mut template = template.clone();
+            template.name = alias.clone();
+            Some((alias, template))
+        })
+        .collect::<Vec<_>>();
+    tera.templates.extend(aliases);
+    Ok(tera)
 }
 
-pub fn render_dir(root_dir: &Path, output_dir: &Path) -> Result<(), Error> {
-    let templates = load_templates(root_dir)?;
+/// Options from the site config that change how posts and pages are read
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// How markdown content is converted to html
+    pub parse: ParseOptions,
+    /// The templates to use when the frontmatter doesn't set one
+    pub templates: TemplatesConfig,
+    /// The languages of a multilingual site. Posts and pages in a directory with one of these names are in that language.
+    pub languages: Vec<String>,
+    /// Drop the body of each post as soon as it's read, keeping only its excerpt and frontmatter.
+    /// See RenderOptions::lazy_bodies.
+    pub lazy_bodies: bool,
+    /// The frontmatter schemas for posts and pages
+    pub schema: SchemaConfig,
+    /// Fail on frontmatter that breaks the schema, instead of warning. See RenderOptions::strict.
+    pub strict: bool,
+    /// The extension of the html files written for posts and pages, from BuildConfig. None is html.
+    pub html_extension: Option<String>,
+    /// The canonical output directory, which is skipped when reading in case it's inside the input directory
+    pub output_dir: Option<PathBuf>,
+}
+
+impl ReadOptions {
+    /// The extension of the html files written for posts and pages, without the leading .
+    pub fn html_extension(&self) -> &str {
+        self.html_extension.as_deref().unwrap_or("html")
+    }
+}
+
+/// Options that change how render_dir_with_options renders the site
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Remove any files in the output directory that were not written by this build
+    pub clean: bool,
+    /// Render the posts and pages that are marked as drafts. This makes the render a preview build:
+    /// templates get is_preview = true, so they can mark the site as a preview, and a banner is logged.
+    pub drafts: bool,
+    /// Fail the render on problems that would otherwise only be warnings, e.g. duplicate slugs
+    pub strict: bool,
+    /// Render everything, but don't write anything to the output directory.
+    /// The files that would be written are listed in RenderReport::planned.
+    pub dry_run: bool,
+    /// The date of the build, available to templates as current_date. See build_date for the default.
+    pub build_date: Option<DateTime<Utc>>,
+    /// Called with the templates once they are loaded, to add custom filters and functions.
+    /// See register_filters.
+    pub templates_hook: Option<TemplatesHook>,
+    /// Only render the posts dated on or after this date, for faster previews of recent posts.
+    /// Every post is still available to templates, e.g. for lists of posts, and all of the pages are rendered.
+    /// The files of the older posts are left as they are, so a full build needs this to be None.
+    pub since: Option<NaiveDate>,
+    /// Keep only one post body in memory at a time, for very large sites.
+    /// The posts are read up front without their bodies, and each post is read again just before it's rendered,
+    /// and dropped after. This bounds memory by the largest post rather than the whole archive,
+    /// at the cost of parsing every rendered post twice.
+    /// The content of the posts in the posts list that templates get is empty; use their excerpts instead.
+    pub lazy_bodies: bool,
+    /// Only render these posts, by their input paths relative to the root directory, e.g. posts/2024-12-01-my-post.md.
+    /// Like since, every post is still available to templates, all of the pages are rendered,
+    /// and the files of the other posts are left as they are. The watcher uses this to render just what changed.
+    pub only_posts: Option<HashSet<PathBuf>>,
+    /// Hash each written file into RenderReport::checksums, so that deploys and CDNs can tell which files changed
+    /// without hashing them again. Dry runs don't write anything, so they have no checksums.
+    pub checksums: bool,
+    /// Load the site's templates from this directory instead of the one in TemplatesConfig::dir, e.g. to try a theme.
+    /// A relative path is relative to the root directory.
+    pub template_dir: Option<PathBuf>,
+    /// Fail with Error::EmptyOutput if no posts or pages were rendered, which usually means the wrong directory
+    /// was rendered. Off by default, so that a site can be empty on purpose.
+    pub fail_on_empty: bool,
+    /// Copy every static file and bundle asset, instead of skipping the ones that haven't changed since they were
+    /// last copied. See util::copy_if_changed.
+    pub force_copy: bool,
+}
+
+/// A function that extends the templates, e.g. with tera.register_filter
+pub type TemplatesHook = fn(&mut Tera);
+
+/// Register the filters and functions that templates can use: the ones that come with kalamos, like date_fmt and
+/// asset_integrity, and then the ones from the templates hook in options, if there is one, so a hook can replace them.
+/// root_dir and theme_dir are the site's and its theme's, for asset_integrity to find their static files.
+/// Programs that embed kalamos can add their own filters with a hook:
+/// ```
+/// use kalamos::render::RenderOptions;
+///
+/// let options = RenderOptions {
+///     templates_hook: Some(|tera| {
+///         tera.register_filter("currency", |value: &tera::Value, _: &_| {
+///             Ok(tera::Value::String(format!("${:.2}", value.as_f64().unwrap_or_default())))
+///         })
+///     }),
+///     ..RenderOptions::default()
+/// };
+/// ```
+pub fn register_filters(
+    tera: &mut Tera,
+    root_dir: &Path,
+    theme_dir: Option<&Path>,
+    options: &RenderOptions,
+) {
+    tera.register_filter("date_fmt", date_fmt);
+    tera.register_function(
+        "asset_integrity",
+        AssetIntegrity::new(
+            [Some(root_dir), theme_dir]
+                .into_iter()
+                .flatten()
+                .map(|dir| dir.join(STATIC_DIR))
+                .collect(),
+        ),
+    );
+    if let Some(hook) = options.templates_hook {
+        hook(tera);
+    }
+}
+
+/// The environment variable with the build date for reproducible builds, as seconds since the epoch.
+/// See https://reproducible-builds.org/specs/source-date-epoch/
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// The date of the build: the build date from the options if there is one, then SOURCE_DATE_EPOCH if it is set,
+/// and otherwise now. Setting one of them makes builds reproducible.
+pub fn build_date(options: &RenderOptions) -> Result<DateTime<Utc>, Error> {
+    if let Some(build_date) = options.build_date {
+        return Ok(build_date);
+    }
+    match std::env::var(SOURCE_DATE_EPOCH) {
+        Ok(epoch) => parse_source_date_epoch(&epoch),
+        Err(_) => Ok(Utc::now()),
+    }
+}
+
+/// Parse the value of SOURCE_DATE_EPOCH, the number of seconds since the epoch
+pub fn parse_source_date_epoch(epoch: &str) -> Result<DateTime<Utc>, Error> {
+    epoch
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .ok_or(Error::InvalidBuildDate(epoch.to_string()))
+}
+
+/// Parse a build date given as an RFC 3339 date and time, e.g. 2024-12-01T09:00:00Z, or a date, e.g. 2024-12-01,
+/// which is midnight UTC
+pub fn parse_build_date(date: &str) -> Result<DateTime<Utc>, Error> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| datetime.and_utc())
+        .ok_or(Error::InvalidBuildDate(date.to_string()))
+}
+
+/// The version of the RenderReport format. Bump this whenever the serialized report changes shape,
+/// so that tools reading `kalamos generate --output-format json` can tell.
+pub const RENDER_REPORT_VERSION: u32 = 4;
+
+/// What a render produced. This is printed as JSON by `kalamos generate --output-format json`.
+#[derive(Debug, Serialize)]
+pub struct RenderReport {
+    pub version: u32,
+    /// The posts and pages that were rendered
+    pub rendered: Vec<RenderedFile>,
+    /// The paths of all of the files that were written, relative to the output directory
+    pub written: Vec<PathBuf>,
+    /// The paths of the files removed by a clean render, relative to the output directory
+    pub removed: Vec<PathBuf>,
+    /// Problems that didn't stop the render
+    pub warnings: Vec<String>,
+    pub stats: RenderStats,
+    /// For a dry run, the files that would have been written and their sizes. Empty otherwise.
+    pub planned: Vec<PlannedFile>,
+    /// With RenderOptions::checksums, the ETag of each written file, by its path relative to the output directory.
+    /// See util::etag. Empty otherwise.
+    pub checksums: BTreeMap<PathBuf, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedFile {
+    /// The file, relative to the output directory
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Where a render writes its files: the output directory, or, for a dry run, a list of the files
+/// that would have been written. A dry run keeps their contents, so that they can be checked.
+struct Output<'a> {
+    dir: &'a Path,
+    dry_run: bool,
+    /// Copy static files and bundle assets even if they are unchanged. See RenderOptions::force_copy.
+    force_copy: bool,
+    /// The size of each file a dry run would write, and the contents of the ones that were rendered
+    planned: BTreeMap<PathBuf, (u64, Option<Vec<u8>>)>,
+}
+
+impl<'a> Output<'a> {
+    fn into_planned(self) -> Vec<PlannedFile> {
+        self.planned
+            .into_iter()
+            .map(|(path, (bytes, _))| PlannedFile { path, bytes })
+            .collect()
+    }
+
+    fn new(dir: &'a Path, dry_run: bool, force_copy: bool) -> Self {
+        Self {
+            dir,
+            dry_run,
+            force_copy,
+            planned: BTreeMap::new(),
+        }
+    }
+
+    /// Write a file, relative to the output directory
+    fn write(&mut self, path: &Path, content: impl Into<Vec<u8>>) -> Result<(), Error> {
+        if self.dry_run {
+            let content = content.into();
+            self.planned
+                .insert(path.to_path_buf(), (content.len() as u64, Some(content)));
+            return Ok(());
+        }
+        write_output(&self.dir.join(path), content.into())
+    }
+
+    /// Copy the files in the directory src, returning their paths relative to the output directory
+    fn copy_dir(
+        &mut self,
+        src: &Path,
+        map: &HashMap<String, String>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        if !self.dry_run {
+            return util::copy_dir_with_map(src, self.dir, map, self.force_copy);
+        }
+        let mut copied = vec![];
+        let exclude = self.dir.canonicalize().ok();
+        for (source, destination) in util::plan_copy_dir_excluding(src, map, exclude.as_deref())? {
+            let bytes = fs::metadata(&source).map_err(Error::ReadFile)?.len();
+            self.planned.insert(destination.clone(), (bytes, None));
+            copied.push(destination);
+        }
+        Ok(copied)
+    }
+
+    /// Copy the file src to path, relative to the output directory
+    fn copy_file(&mut self, src: &Path, path: &Path) -> Result<(), Error> {
+        if self.dry_run {
+            let bytes = fs::metadata(src).map_err(Error::ReadFile)?.len();
+            self.planned.insert(path.to_path_buf(), (bytes, None));
+            return Ok(());
+        }
+        let output_path = self.dir.join(path);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent).map_err(Error::CreateDir)?;
+        }
+        util::copy_if_changed(src, &output_path, self.force_copy)?;
+        Ok(())
+    }
+
+    /// Read a file that was written, relative to the output directory
+    fn read_to_string(&self, path: &Path) -> Result<String, Error> {
+        match self.planned.get(path) {
+            Some((_, Some(content))) => Ok(String::from_utf8_lossy(content).to_string()),
+            _ => fs::read_to_string(self.dir.join(path)).map_err(Error::ReadFile),
+        }
+    }
+
+    /// The ETag of each of the files that were written, relative to the output directory
+    fn checksums(&self, written: &[PathBuf]) -> Result<BTreeMap<PathBuf, String>, Error> {
+        if self.dry_run {
+            return Ok(BTreeMap::new());
+        }
+        written
+            .iter()
+            .map(|path| {
+                let content = fs::read(self.dir.join(path)).map_err(Error::ReadFile)?;
+                Ok((path.clone(), util::etag(&content)))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderedFile {
+    /// "post" or "page"
+    pub kind: String,
+    /// The source file, relative to the root of the site
+    pub input_path: PathBuf,
+    /// The rendered file, relative to the output directory
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderStats {
+    pub posts: usize,
+    pub pages: usize,
+    pub files_written: usize,
+    pub files_removed: usize,
+    pub duration_ms: u128,
+    pub timings: PhaseTimings,
+}
+
+/// How long each phase of a render took, in milliseconds. Printed by `kalamos generate --time`.
+#[derive(Debug, Default, Serialize)]
+pub struct PhaseTimings {
+    /// Loading the config, templates and data
+    pub templates_ms: u128,
+    /// Reading and parsing the posts, including highlighting code blocks
+    pub posts_ms: u128,
+    /// Reading and parsing the pages
+    pub pages_ms: u128,
+    /// Rendering the posts and pages with their templates and writing them
+    pub render_ms: u128,
+    /// Copying the static directory
+    pub copy_ms: u128,
+}
+
+/// The milliseconds since the start of a phase. The next phase starts now.
+fn end_phase(phase_start: &mut Instant) -> u128 {
+    let elapsed = phase_start.elapsed().as_millis();
+    *phase_start = Instant::now();
+    elapsed
+}
+
+/// Render the site in root_dir to output_dir with the default options.
+/// Returns the paths of all of the files that were written, relative to output_dir.
+pub fn render_dir(root_dir: &Path, output_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(render_dir_with_options(root_dir, output_dir, &RenderOptions::default())?.written)
+}
+
+/// Render the site in root_dir to output_dir. The output directory is created if it doesn't exist.
+/// Returns a report of what was rendered and written.
+pub fn render_dir_with_options(
+    root_dir: &Path,
+    output_dir: &Path,
+    options: &RenderOptions,
+) -> Result<RenderReport, Error> {
+    let start = Instant::now();
+    let mut phase_start = start;
+    let mut timings = PhaseTimings::default();
+    if !options.dry_run {
+        fs::create_dir_all(output_dir).map_err(Error::CreateDir)?;
+    }
+    let mut output = Output::new(output_dir, options.dry_run, options.force_copy);
+    let config = Config::load(root_dir)
+        .map_err(Error::Config)?
+        .unwrap_or_default();
+    let theme_dir = config.theme_dir(root_dir);
+    if let Some(theme_dir) = &theme_dir {
+        if !theme_dir.is_dir() {
+            return Err(Error::MissingTheme(theme_dir.clone()));
+        }
+    }
+    let mut templates_config = config.templates.clone();
+    if let Some(template_dir) = &options.template_dir {
+        templates_config.dir = template_dir.to_string_lossy().to_string();
+    }
+    let mut templates =
+        load_templates_with_theme(root_dir, &templates_config, theme_dir.as_deref())?;
+    register_filters(&mut templates, root_dir, theme_dir.as_deref(), options);
+    // The site context is added to the context of every post and page
+    let mut site_context = Context::new();
+    site_context.insert("data", &data::load_data(root_dir)?);
+    let read_options = ReadOptions {
+        parse: ParseOptions {
+            markdown: config.markdown.clone(),
+            highlight: config.highlight.clone(),
+        },
+        templates: templates_config.clone(),
+        languages: config.languages.clone(),
+        lazy_bodies: options.lazy_bodies,
+        schema: config.schema.clone(),
+        strict: options.strict,
+        html_extension: Some(config.build.html_extension.clone()),
+        output_dir: output_dir.canonicalize().ok(),
+    };
+    site_context.insert("languages", &config.languages);
+    let build_date = build_date(options)?;
+    site_context.insert("current_date", &build_date);
+    // Which commit and when the site was built, e.g. for a footer that says "built from abc1234"
+    site_context.insert(
+        "build_commit",
+        &git::head_commit(root_dir).unwrap_or(git::UNKNOWN_COMMIT.to_string()),
+    );
+    site_context.insert("build_time", &build_date.to_rfc3339());
+    site_context.insert("config", &config.context);
+    site_context.insert("is_preview", &options.drafts);
+    if options.drafts {
+        warn!("PREVIEW BUILD: drafts and future posts included.");
+    }
+    let line_endings = config.build.line_endings;
+    let favicon_files = match &config.favicon {
+        Some(favicon_config) => {
+            let source = root_dir.join(&favicon_config.source);
+            if source.is_file() {
+                let icon = fs::read(&source).map_err(Error::ReadFile)?;
+                let (favicon, files) = favicon::favicon_files(favicon_config, icon)
+                    .ok_or(Error::InvalidFavicon(source))?;
+                site_context.insert("favicon", &favicon);
+                files
+            } else {
+                info!("{:?} does not exist, skipping the favicon", source);
+                vec![]
+            }
+        }
+        None => vec![],
+    };
+    timings.templates_ms = end_phase(&mut phase_start);
+
     // get all the md files in the posts directory and create Posts from them
     // We need the posts as a variable to pass to the render function for posts and pages.
     // It can be used, for example, to get a list of all the posts to pass to the RSS feed
     // or to get a list of posts for a sidebar or an archives page.
-    let mut posts = Post::read_from_directory(root_dir)?;
-    posts.sort();
-    posts.reverse();
+    let mut posts = Post::read_from_directory_with_options(root_dir, &templates, &read_options)?;
+    if !options.drafts {
+        posts.retain(|post| !post.draft);
+    }
+    Post::sort_newest_first(&mut posts);
+    if config.related_posts.enabled {
+        Post::set_related_posts(&mut posts, config.related_posts.limit);
+    }
+    Post::set_series(&mut posts);
+    Post::set_translations(&mut posts);
+    site_context.insert("featured_posts", &Post::featured(&posts));
+    site_context.insert("posts_by_year", &Post::by_year(&posts));
+    timings.posts_ms = end_phase(&mut phase_start);
 
+    let mut pages = if config.pages.flat_root {
+        Page::read_from_root(root_dir, output_dir, &templates, &read_options)?
+    } else {
+        Page::read_from_directory_with_options(root_dir, &templates, &read_options)?
+    };
+    if !options.drafts {
+        pages.retain(|page| !page.draft);
+    }
+    Page::set_translations(&mut pages);
+    site_context.insert("pages", &Page::navigation(&pages));
+    site_context.insert(
+        "sitemap",
+        &sitemap::entries(&posts, &pages, &config.sitemap)?,
+    );
+    timings.pages_ms = end_phase(&mut phase_start);
+    check_output_paths(&posts, &pages, &config.variants)?;
+    let mut warnings = check_slugs(&posts, options.strict)?;
+    warnings.extend(
+        posts
+            .iter()
+            .flat_map(|post| post.warnings.iter())
+            .chain(pages.iter().flat_map(|page| page.warnings.iter()))
+            .cloned(),
+    );
+
+    let mut written = vec![];
+    // The files of the posts skipped because of options.since or options.only_posts, which cleaning must not remove
+    let mut skipped = vec![];
+    let is_rendered = |post: &Post| {
+        options.since.is_none_or(|since| post.date >= since)
+            && options
+                .only_posts
+                .as_ref()
+                .is_none_or(|only| only.contains(&post.input_path))
+    };
     for post in &posts {
-        post.render(&templates, output_dir, &posts)?;
+        if !is_rendered(post) {
+            skipped.push(post.output_path.clone());
+            skipped.extend(
+                config
+                    .variants
+                    .iter()
+                    .map(|v| v.output_path(&post.output_path)),
+            );
+            continue;
+        }
+        // With lazy bodies, this post's body is read again here and dropped at the end of the iteration
+        let full_post;
+        let post = if options.lazy_bodies {
+            full_post = post.with_body(root_dir, &templates, &read_options)?;
+            &full_post
+        } else {
+            post
+        };
+        let html = post.render_to_string(&templates, &posts, &site_context)?;
+        let html = util::normalize_output(&html, line_endings);
+        output.write(&post.output_path, html)?;
+        written.push(post.output_path.clone());
+        for variant in &config.variants {
+            let template = format!("{}.html", variant.template);
+            let html =
+                post.render_variant_to_string(&templates, &template, &posts, &site_context)?;
+            let html = util::normalize_output(&html, line_endings);
+            let output_path = variant.output_path(&post.output_path);
+            output.write(&output_path, html)?;
+            written.push(output_path);
+        }
     }
 
-    // get all the md, html and xml files in the pages directory, render them and write them to the output directory
-    let pages = Page::read_from_directory(root_dir)?;
+    // render all the md, html and xml files in the pages directory and write them to the output directory
     for page in &pages {
-        page.render(&templates, output_dir, &posts)?;
+        let page_posts = if config.feeds.is_feed(&page.output_path) {
+            feed::limit_posts(&posts, config.feeds.limit)
+        } else {
+            &posts
+        };
+        let html = page.render_to_string(&templates, page_posts, &site_context)?;
+        let html = util::normalize_output(&html, line_endings);
+        output.write(&page.output_path, html)?;
+        written.push(page.output_path.clone());
+    }
+
+    // copy the assets of each post bundle next to the post, so that relative links to them work
+    let mut sources = posts
+        .iter()
+        .map(|p| (p.output_path.clone(), p.input_path.clone()))
+        .chain(
+            pages
+                .iter()
+                .map(|p| (p.output_path.clone(), p.input_path.clone())),
+        )
+        .collect::<HashMap<_, _>>();
+    for post in &posts {
+        let Some(bundle_dir) = post.bundle_dir() else {
+            continue;
+        };
+        let output_dir = post.output_path.parent().unwrap_or(Path::new(""));
+        for (source, destination) in
+            util::plan_copy_dir(&root_dir.join(bundle_dir), &HashMap::new())?
+        {
+            let input_path = bundle_dir.join(&destination);
+            if input_path == post.input_path || is_sidecar(&source) || is_defaults_file(&source) {
+                continue;
+            }
+            let output_path = output_dir.join(&destination);
+            if let Some(other) = sources.insert(output_path.clone(), input_path.clone()) {
+                return Err(Error::OutputPathCollision(output_path, other, input_path));
+            }
+            output.copy_file(&source, &output_path)?;
+            written.push(output_path);
+        }
+    }
+    // write a redirect to each post and page from each of its aliases, unless something else is written there
+    let aliases = posts
+        .iter()
+        .map(|p| (&p.aliases, &p.url, &p.input_path))
+        .chain(pages.iter().map(|p| (&p.aliases, &p.url, &p.input_path)));
+    for (aliases, url, input_path) in aliases {
+        let target = match &config.base_url {
+            Some(base_url) => format!("{}{}", base_url.trim_end_matches('/'), url.display()),
+            None => url.display().to_string(),
+        };
+        for alias in aliases {
+            let output_path = url::output_path_from_permalink(alias)?;
+            if let Some(other) = sources.get(&output_path) {
+                let warning = format!(
+                    "alias {:?} of {:?} would overwrite {:?}, skipping it",
+                    alias, input_path, other
+                );
+                warn!("{}", warning);
+                warnings.push(warning);
+                continue;
+            }
+            sources.insert(output_path.clone(), input_path.clone());
+            output.write(&output_path, url::redirect_html(&target))?;
+            written.push(output_path);
+        }
+    }
+    // render the landing page of each section with an _index.md
+    for section in Section::read_from_directory(root_dir, &templates, &read_options)? {
+        let output_path = section.output_path.clone();
+        if let Some(other) = sources.insert(output_path.clone(), section.input_path.clone()) {
+            return Err(Error::OutputPathCollision(
+                output_path,
+                other,
+                section.input_path,
+            ));
+        }
+        let html = section.render_to_string(&templates, &posts, &site_context)?;
+        let html = util::normalize_output(&html, line_endings);
+        output.write(&output_path, html)?;
+        written.push(output_path);
+    }
+    // render a page for each author, if there is a layout for them
+    if templates
+        .get_template_names()
+        .any(|name| name == author::TEMPLATE)
+    {
+        let input_path = Path::new(&templates_config.dir).join(author::TEMPLATE);
+        for (slug, (author, authored)) in
+            author::posts_by_author(&posts, &config.authors, read_options.html_extension())?
+        {
+            let output_path = author::output_path(&slug, read_options.html_extension());
+            if let Some(other) = sources.insert(output_path.clone(), input_path.clone()) {
+                return Err(Error::OutputPathCollision(output_path, other, input_path));
+            }
+            let mut context = site_context.clone();
+            context.insert("author", &author);
+            context.insert("author_posts", &authored);
+            context.insert("posts", &posts);
+            context.insert("path", &output_path);
+            context.insert("url", &author.url);
+            let html = templates
+                .render(author::TEMPLATE, &context)
+                .map_err(Error::Tera)?;
+            let html = util::normalize_output(&html, line_endings);
+            output.write(&output_path, html)?;
+            written.push(output_path);
+        }
     }
+    // render the feed page again for each tag, with only the most recent posts that have the tag
+    if config.feeds.tags {
+        let feed_page = pages
+            .iter()
+            .find(|p| p.output_path == Path::new(&config.feeds.page));
+        match feed_page {
+            Some(feed_page) => {
+                let tag_feeds = feed::posts_by_tag(&posts, options.strict)?;
+                warnings.extend(tag_feeds.warnings);
+                for (slug, (tag, tagged)) in tag_feeds.tags {
+                    let output_path = feed::tag_feed_path(&slug, &feed_page.output_path);
+                    let input_path = feed_page.input_path.clone();
+                    if let Some(other) = sources.insert(output_path.clone(), input_path.clone()) {
+                        return Err(Error::OutputPathCollision(output_path, other, input_path));
+                    }
+                    let mut context = site_context.clone();
+                    context.insert("tag", &tag);
+                    let tagged = feed::limit_posts(&tagged, config.feeds.limit);
+                    let xml = feed_page.render_to_string(&templates, tagged, &context)?;
+                    let xml = util::normalize_output(&xml, line_endings);
+                    output.write(&output_path, xml)?;
+                    written.push(output_path);
+                }
+            }
+            None => {
+                let warning = format!(
+                    "tag feeds are on, but there is no feed page at {:?}",
+                    config.feeds.page
+                );
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+    }
+    timings.render_ms = end_phase(&mut phase_start);
+
+    // copy all files in the theme's static directory and then the site's, so that the site's files replace
+    // the theme's, moving the directories in the [copy] map
+    let static_paths = theme_dir
+        .iter()
+        .map(|theme_dir| theme_dir.join(STATIC_DIR))
+        .chain([root_dir.join(STATIC_DIR)]);
+    let rendered_paths = written.iter().cloned().collect::<HashSet<_>>();
+    let mut copied_paths = HashSet::new();
+    for static_path in static_paths {
+        if !static_path.is_dir() {
+            info!("{:?} does not exist, skipping it", static_path);
+            continue;
+        }
+        let copied = output.copy_dir(&static_path, &config.copy.map)?;
+        for path in copied {
+            if rendered_paths.contains(&path) {
+                let warning = format!("static file {:?} overwrote a rendered post or page", path);
+                warn!("{}", warning);
+                warnings.push(warning);
+            } else if copied_paths.insert(path.clone()) {
+                written.push(path);
+            }
+        }
+    }
+    timings.copy_ms = end_phase(&mut phase_start);
+
+    // A highlight.css in the static directory replaces the generated one
+    let highlight_css_path = PathBuf::from(parser::HIGHLIGHT_CSS_PATH);
+    if config.highlight.classes && !written.contains(&highlight_css_path) {
+        let css = parser::highlight_css(&config.highlight.class_prefix)
+            .map_err(|e| Error::Markdown(highlight_css_path.clone(), e))?;
+        output.write(&highlight_css_path, css)?;
+        written.push(highlight_css_path);
+    }
+
+    // An icon or site.webmanifest in the static directory replaces the generated one
+    for file in favicon_files {
+        if written.contains(&file.path) {
+            continue;
+        }
+        output.write(&file.path, file.content)?;
+        written.push(file.path);
+    }
+
+    if let Some(robots_txt) = robots::robots_txt(&config, &written) {
+        output.write(Path::new(robots::OUTPUT_PATH), robots_txt)?;
+        written.push(PathBuf::from(robots::OUTPUT_PATH));
+    }
+
+    let rendered_posts = posts.iter().filter(|p| is_rendered(p)).collect::<Vec<_>>();
+    warnings.extend(check_images(
+        &output,
+        &rendered_posts,
+        &pages,
+        &written,
+        options.strict,
+    )?);
 
-    // copy all files in the static directory
-    let static_path = root_dir.join("static");
-    util::copy_dir(&static_path, output_dir)?;
+    let removed = if options.clean && !options.dry_run {
+        let kept = [written.as_slice(), skipped.as_slice()].concat();
+        let removed = clean_output_dir(root_dir, output_dir, &kept)?;
+        info!("files removed: {:?}", removed);
+        removed
+    } else {
+        vec![]
+    };
+
+    let rendered = rendered_posts
+        .iter()
+        .map(|p| ("post", &p.input_path, &p.output_path))
+        .chain(
+            pages
+                .iter()
+                .map(|p| ("page", &p.input_path, &p.output_path)),
+        )
+        .map(|(kind, input_path, output_path)| RenderedFile {
+            kind: kind.to_string(),
+            input_path: input_path.clone(),
+            output_path: output_path.clone(),
+        })
+        .collect();
+    let checksums = if options.checksums {
+        output.checksums(&written)?
+    } else {
+        BTreeMap::new()
+    };
+    let stats = RenderStats {
+        posts: rendered_posts.len(),
+        pages: pages.len(),
+        files_written: written.len(),
+        files_removed: removed.len(),
+        duration_ms: start.elapsed().as_millis(),
+        timings,
+    };
+    if options.fail_on_empty && stats.posts == 0 && stats.pages == 0 {
+        return Err(Error::EmptyOutput(root_dir.to_path_buf()));
+    }
+    Ok(RenderReport {
+        version: RENDER_REPORT_VERSION,
+        rendered,
+        written,
+        removed,
+        warnings,
+        stats,
+        planned: output.into_planned(),
+        checksums,
+    })
+}
+
+/// Make sure that no two posts, post variants or pages are written to the same output path
+fn check_output_paths(
+    posts: &[Post],
+    pages: &[Page],
+    variants: &[config::VariantConfig],
+) -> Result<(), Error> {
+    let mut seen = HashMap::new();
+    let variant_paths = variants.iter().flat_map(|variant| {
+        posts
+            .iter()
+            .map(|p| (variant.output_path(&p.output_path), &p.input_path))
+    });
+    let paths = posts
+        .iter()
+        .map(|p| (p.output_path.clone(), &p.input_path))
+        .chain(variant_paths)
+        .chain(pages.iter().map(|p| (p.output_path.clone(), &p.input_path)));
+    for (output_path, input_path) in paths {
+        if let Some(other) = seen.insert(output_path.clone(), input_path) {
+            return Err(Error::OutputPathCollision(
+                output_path,
+                other.clone(),
+                input_path.clone(),
+            ));
+        }
+    }
     Ok(())
 }
+
+/// Find posts in the same language that share a slug, even if their dates differ.
+/// Returns a warning for each one, or an error if strict is set.
+fn check_slugs(posts: &[Post], strict: bool) -> Result<Vec<String>, Error> {
+    let mut seen = HashMap::new();
+    let mut warnings = vec![];
+    for post in posts {
+        if let Some(other) = seen.insert((&post.lang, &post.slug), &post.input_path) {
+            if strict {
+                return Err(Error::DuplicateSlug(
+                    post.slug.clone(),
+                    other.clone(),
+                    post.input_path.clone(),
+                ));
+            }
+            let warning = format!(
+                "duplicate slug: {:?} is used by both {:?} and {:?}",
+                post.slug, other, post.input_path
+            );
+            warn!("{}", warning);
+            warnings.push(warning);
+        }
+    }
+    Ok(warnings)
+}
+
+/// Make sure that every local image used by a rendered post or page was written to the output directory,
+/// e.g. copied from the static directory. Remote images and data URIs are skipped.
+/// Returns a warning for each missing image, with the post or page that uses it,
+/// or an error with all of them if strict is set.
+fn check_images(
+    output: &Output,
+    posts: &[&Post],
+    pages: &[Page],
+    written: &[PathBuf],
+    strict: bool,
+) -> Result<Vec<String>, Error> {
+    let written = written.iter().collect::<HashSet<_>>();
+    // Only html is checked, which is every post and some pages, whatever the html_extension they are written with
+    let rendered = posts
+        .iter()
+        .map(|p| (&p.input_path, &p.output_path, &p.url))
+        .chain(
+            pages
+                .iter()
+                .filter(|p| p.is_html())
+                .map(|p| (&p.input_path, &p.output_path, &p.url)),
+        );
+    let mut missing = vec![];
+    for (input_path, output_path, url) in rendered {
+        let html = output.read_to_string(output_path)?;
+        for src in util::image_sources(&html) {
+            let exists = util::local_image_path(url, &src)
+                .map(|path| written.contains(&path))
+                .unwrap_or(true);
+            if !exists {
+                missing.push((input_path.clone(), src));
+            }
+        }
+    }
+    if strict && !missing.is_empty() {
+        return Err(Error::MissingImages(missing));
+    }
+    let warnings = missing
+        .into_iter()
+        .map(|(input_path, src)| {
+            let warning = format!("missing image: {:?} uses {:?}", input_path, src);
+            warn!("{}", warning);
+            warning
+        })
+        .collect();
+    Ok(warnings)
+}
+
+/// Remove every file in output_dir that is not in written, which is the list of paths returned by render_dir.
+/// Directories that are left empty are removed as well.
+/// Returns the paths of the removed files, relative to output_dir.
+pub fn clean_output_dir(
+    root_dir: &Path,
+    output_dir: &Path,
+    written: &[PathBuf],
+) -> Result<Vec<PathBuf>, Error> {
+    let root_dir = root_dir
+        .canonicalize()
+        .map_err(|e| Error::Path(root_dir.to_path_buf(), e.to_string()))?;
+    let output_dir = output_dir
+        .canonicalize()
+        .map_err(|e| Error::Path(output_dir.to_path_buf(), e.to_string()))?;
+    // If the input directory is inside the output directory, cleaning would delete the site's sources
+    if root_dir.starts_with(&output_dir) {
+        return Err(Error::Clean(
+            output_dir,
+            "refusing to clean an output directory that contains the input directory".to_string(),
+        ));
+    }
+
+    let written = written.iter().collect::<HashSet<_>>();
+    let mut removed = vec![];
+    for entry in WalkDir::new(&output_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let p = entry.path();
+        let stripped = p
+            .strip_prefix(&output_dir)
+            .map_err(|e| Error::StripPrefix(p.to_path_buf(), e))?;
+        if written.contains(&stripped.to_path_buf()) {
+            continue;
+        }
+        fs::remove_file(p).map_err(Error::RemoveFile)?;
+        removed.push(stripped.to_path_buf());
+    }
+
+    // remove any directories that are now empty, deepest first
+    for entry in WalkDir::new(&output_dir)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir())
+    {
+        let is_empty = fs::read_dir(entry.path())
+            .map_err(Error::ReadFile)?
+            .next()
+            .is_none();
+        if is_empty {
+            fs::remove_dir(entry.path()).map_err(Error::RemoveFile)?;
+        }
+    }
+    Ok(removed)
+}
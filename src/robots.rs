@@ -0,0 +1,42 @@
+//! Generate a robots.txt for the site
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+pub const OUTPUT_PATH: &str = "robots.txt";
+pub const SITEMAP_PATH: &str = "sitemap.xml";
+
+/// The contents of robots.txt, or None if one shouldn't be generated.
+/// A robots.txt is generated if there is a [robots] section or a base_url in the config.
+/// written is the list of files written by the build: if it contains a sitemap.xml and there is a base_url,
+/// the sitemap is referenced. If it already contains a robots.txt, that one is kept.
+pub fn robots_txt(config: &Config, written: &[PathBuf]) -> Option<String> {
+    if config.robots.is_none() && config.base_url.is_none() {
+        return None;
+    }
+    if written.iter().any(|p| p == Path::new(OUTPUT_PATH)) {
+        return None;
+    }
+    let mut robots = String::from("User-agent: *\n");
+    let disallow = config
+        .robots
+        .as_ref()
+        .map(|r| r.disallow.clone())
+        .unwrap_or_default();
+    if disallow.is_empty() {
+        // An empty Disallow allows everything
+        robots.push_str("Disallow:\n");
+    }
+    for path in disallow {
+        robots.push_str(&format!("Disallow: {}\n", path));
+    }
+    let has_sitemap = written.iter().any(|p| p == Path::new(SITEMAP_PATH));
+    if let (Some(base_url), true) = (&config.base_url, has_sitemap) {
+        robots.push_str(&format!(
+            "\nSitemap: {}/{}\n",
+            base_url.trim_end_matches('/'),
+            SITEMAP_PATH
+        ));
+    }
+    Some(robots)
+}
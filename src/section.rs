@@ -0,0 +1,146 @@
+//! Sections are the subdirectories of the posts directory, like posts/tutorials/.
+//! A section with an _index.md gets a landing page, rendered to tutorials/index.html with the posts in the section.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tera::{Context, Tera};
+
+use crate::parser;
+use crate::post::Post;
+use crate::render::{self, Error, ReadOptions};
+use crate::url;
+use crate::util;
+
+/// A section's landing page, read from its _index.md
+#[derive(Debug, Clone, Serialize)]
+pub struct Section {
+    /// The _index.md, relative to the root of the site, e.g. posts/tutorials/_index.md
+    pub input_path: PathBuf,
+    /// The directory of the section, relative to the root of the site, e.g. posts/tutorials
+    pub dir: PathBuf,
+    /// The landing page, relative to the output directory, e.g. tutorials/index.html
+    pub output_path: PathBuf,
+    /// /tutorials/index.html
+    pub url: PathBuf,
+    pub title: String,
+    pub description: Option<String>,
+    pub template: String,
+    /// The rendered html of the _index.md
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectionFrontmatter {
+    title: String,
+    description: Option<String>,
+    template: Option<String>,
+}
+
+impl Section {
+    /// The name of the file with a section's landing page
+    pub const INDEX_NAME: &str = "_index.md";
+    /// The template for sections that don't set one in their frontmatter
+    pub const DEFAULT_TEMPLATE: &str = "section";
+
+    /// True for the _index.md of a section. It is read as a Section, not a Post.
+    pub fn is_index(path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| name == Self::INDEX_NAME)
+    }
+
+    /// Read the _index.md of every section in the posts directory of root_dir.
+    /// An _index.md directly in posts/ is a section too, rendered to index.html.
+    pub fn read_from_directory(
+        root_dir: &Path,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Vec<Section>, Error> {
+        let posts_dir = root_dir.join(Post::READ_DIRECTORY);
+        if !posts_dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut sections = util::walk_files(&posts_dir, options.output_dir.as_deref())
+            .filter(|e| Self::is_index(e.path()))
+            .map(|e| {
+                let content = fs::read_to_string(e.path()).map_err(Error::ReadFile)?;
+                let input_path = e
+                    .path()
+                    .strip_prefix(root_dir)
+                    .map_err(|err| Error::StripPrefix(e.path().to_path_buf(), err))?;
+                Self::from_content(input_path, &content, templates, options)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        sections.sort_by(|a, b| a.dir.cmp(&b.dir));
+        Ok(sections)
+    }
+
+    /// Create a Section from the content of its _index.md, at input_path relative to the root of the site
+    pub fn from_content(
+        input_path: &Path,
+        content: &str,
+        templates: &Tera,
+        options: &ReadOptions,
+    ) -> Result<Section, Error> {
+        let parsed = parser::parse_file("md", content, templates, &options.parse)
+            .map_err(|e| Error::Markdown(input_path.to_path_buf(), e))?;
+        render::check_required_fields(&parsed.frontmatter, input_path)?;
+        let frontmatter: SectionFrontmatter = parsed.frontmatter.try_into().map_err(|e| {
+            Error::ParseFrontmatter(format!(
+                "frontmatter for {:?}: {:?}",
+                input_path,
+                e.to_string()
+            ))
+        })?;
+        let dir = input_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let section_path = dir
+            .strip_prefix(Post::READ_DIRECTORY)
+            .map_err(|e| Error::StripPrefix(dir.clone(), e))?;
+        let output_path =
+            url::with_html_extension(&section_path.join("index.html"), options.html_extension());
+        let mut template = frontmatter
+            .template
+            .unwrap_or(Self::DEFAULT_TEMPLATE.to_string());
+        template.push_str(".html");
+        Ok(Section {
+            input_path: input_path.to_path_buf(),
+            url: url::url_from_output_path(&output_path),
+            output_path,
+            dir,
+            title: frontmatter.title,
+            description: frontmatter.description,
+            template,
+            content: parsed.body,
+        })
+    }
+
+    /// The posts in the section or in any of its subdirectories, in the order of posts
+    pub fn posts<'a>(&self, posts: &'a [Post]) -> Vec<&'a Post> {
+        posts
+            .iter()
+            .filter(|p| p.input_path.starts_with(&self.dir))
+            .collect()
+    }
+
+    /// Render the landing page. Its template gets the section's title, description, body, path and url,
+    /// its posts as section_posts, and all of the posts as posts.
+    pub fn render_to_string(
+        &self,
+        templates: &Tera,
+        posts: &[Post],
+        site_context: &Context,
+    ) -> Result<String, Error> {
+        let mut context = site_context.clone();
+        context.insert("title", &self.title);
+        context.insert("description", &self.description);
+        context.insert("body", &self.content);
+        context.insert("path", &self.output_path);
+        context.insert("url", &self.url);
+        context.insert("is_section", &true);
+        context.insert("section_posts", &self.posts(posts));
+        context.insert("posts", posts);
+        templates
+            .render(&self.template, &context)
+            .map_err(Error::Tera)
+    }
+}
@@ -1,36 +1,96 @@
-use log::info;
-use mime_guess;
+use crate::config::{MimeConfig, ServeConfig};
+use crate::util;
+use log::{error, info};
 use mime_guess::mime::Mime;
 use regex::Regex;
 use simple_server::{Server, StatusCode};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 struct RequestInfo {
     content: Vec<u8>,
     status_code: StatusCode,
     mime_type: Mime,
+    /// The Content-Encoding of a precompressed file, e.g. br
+    content_encoding: Option<&'static str>,
 }
 
+/// The precompressed variants of a file that are served when the client accepts them, in order of preference,
+/// with the extension of the variant's file and its Content-Encoding
+pub const PRECOMPRESSED_VARIANTS: [(&str, &str); 2] = [("br", "br"), ("gz", "gzip")];
+
 const NOT_FOUND_PATH: &str = "404.html";
 
-pub fn serve(input_dir: &Path, port: u16) -> Result<(), simple_server::Error> {
+/// Options for the development server that come from the command line rather than the config
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    /// Let the browser cache responses, instead of sending Cache-Control: no-store
+    pub cache: bool,
+    /// Serve this file with a 503 for every request, e.g. while the site is being rebuilt
+    pub maintenance: Option<PathBuf>,
+}
+
+/// Serve the files in roots. Each request is resolved against the roots in order, and the first match is served,
+/// so files that aren't in the first root fall through to the next one.
+/// Unless options.cache is true, responses tell the browser not to cache them, so changes always show up.
+pub fn serve(
+    roots: &[PathBuf],
+    port: u16,
+    serve_config: &ServeConfig,
+    mime_config: &MimeConfig,
+    options: &ServeOptions,
+) -> Result<(), simple_server::Error> {
     // remove leading slash from request path, so that we can use it as a relative path
     let slash_remover = Regex::new(r"^/").expect("should be able to parse regex");
 
     let host = "127.0.0.1";
-    info!("Serving from {:?}...", input_dir);
-    let input_dir = input_dir.to_path_buf();
+    info!("Serving from {:?}...", roots);
+    let roots = roots.to_vec();
+    let serve_config = serve_config.clone();
+    let mime_config = mime_config.clone();
+    let options = options.clone();
     let server = Server::new(move |request, mut response| {
-        let request_path = request.uri().path();
-        let request_path = slash_remover.replace(request_path, "").to_string();
+        let start = Instant::now();
+        let request_path = slash_remover.replace(request.uri().path(), "").to_string();
+        let accept_encoding = request
+            .headers()
+            .get("Accept-Encoding")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
         let RequestInfo {
             content,
             status_code,
             mime_type,
-        } = file_content(&input_dir, &request_path)?;
-        info!("Serving file: {}", &request_path);
+            content_encoding,
+        } = match &options.maintenance {
+            Some(maintenance) => maintenance_content(maintenance, &mime_config)?,
+            None => file_content(
+                &roots,
+                &request_path,
+                accept_encoding,
+                &serve_config,
+                &mime_config,
+            )?,
+        };
+        info!(
+            "{}",
+            access_log_line(
+                request.method().as_str(),
+                request.uri().path(),
+                status_code.as_u16(),
+                content.len(),
+                start.elapsed()
+            )
+        );
         response.header("content_type", mime_type.essence_str());
+        if let Some(encoding) = content_encoding {
+            response.header("Content-Encoding", encoding);
+            response.header("Vary", "Accept-Encoding");
+        }
+        for (name, value) in cache_headers(options.cache) {
+            response.header(name, value);
+        }
         response.status(status_code);
         Ok(response.body(content)?)
     });
@@ -38,41 +98,260 @@ pub fn serve(input_dir: &Path, port: u16) -> Result<(), simple_server::Error> {
     server.listen(host, port.to_string().as_str());
 }
 
-fn file_content(root_path: &Path, path: &str) -> Result<RequestInfo, simple_server::Error> {
-    let path = root_path.join(path);
-    let path_with_index = path.join("index.html");
-    match (&path.is_file(), &path_with_index.is_file()) {
-        (true, _) => {
-            let content = std::fs::read(&path)?;
-            let mime_type = mime_guess::from_path(&path).first_or_text_plain();
-            Ok(RequestInfo {
-                content,
-                status_code: StatusCode::OK,
-                mime_type,
-            })
+/// A line of the access log, e.g. GET /about.html 200 1234 bytes 2ms
+pub fn access_log_line(
+    method: &str,
+    path: &str,
+    status: u16,
+    bytes: usize,
+    duration: Duration,
+) -> String {
+    format!(
+        "{} {} {} {} bytes {}ms",
+        method,
+        path,
+        status,
+        bytes,
+        duration.as_millis()
+    )
+}
+
+/// The headers that control caching. Without cache, the browser is told not to store anything,
+/// so that a stale cache, e.g. of a service worker, doesn't hide changes during development.
+pub fn cache_headers(cache: bool) -> Vec<(&'static str, &'static str)> {
+    if cache {
+        return vec![];
+    }
+    vec![("Cache-Control", "no-store"), ("Pragma", "no-cache")]
+}
+
+/// Look up a request path in the routes from the serve config.
+/// Returns the path of the file to serve for it, relative to the output directory,
+/// or the request path itself if there is no route for it.
+/// Leading and trailing slashes are ignored, so /feed/ matches a "feed" route.
+/// Config::load rejects routes that only differ in them, so at most one route matches.
+pub fn resolve_route<'a>(path: &'a str, serve_config: &'a ServeConfig) -> &'a str {
+    serve_config
+        .routes
+        .iter()
+        .find(|(route, _)| route.trim_matches('/') == path.trim_matches('/'))
+        .map(|(_, file)| file.trim_start_matches('/'))
+        .unwrap_or(path)
+}
+
+/// Find the file to serve for a request path, relative to root_path.
+/// If the path is a directory, the first of the configured index files that exists in it is used.
+/// Returns None if there is no file to serve.
+pub fn resolve_file(root_path: &Path, path: &str, serve_config: &ServeConfig) -> Option<PathBuf> {
+    let path = root_path.join(resolve_route(path, serve_config));
+    if path.is_file() {
+        return Some(path);
+    }
+    if !path.is_dir() {
+        return None;
+    }
+    serve_config
+        .index_files
+        .iter()
+        .map(|index_file| path.join(index_file))
+        .find(|index_path| index_path.is_file())
+}
+
+/// Find the file to serve for a request path in the first of roots that has one.
+pub fn resolve_file_in_roots(
+    roots: &[PathBuf],
+    path: &str,
+    serve_config: &ServeConfig,
+) -> Option<PathBuf> {
+    roots
+        .iter()
+        .find_map(|root| resolve_file(root, path, serve_config))
+}
+
+/// True if the Accept-Encoding header value allows the content coding encoding, e.g. "gzip, br;q=0.8" allows br.
+/// A coding with q=0 is refused, and * allows any coding that isn't listed.
+pub fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    let mut wildcard = false;
+    for coding in accept_encoding.split(',') {
+        let mut parts = coding.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let refused = parts.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        if name.eq_ignore_ascii_case(encoding) {
+            return !refused;
         }
-        (_, true) => {
-            let content = std::fs::read(&path_with_index)?;
-            let mime_type = mime_guess::from_path(&path_with_index).first_or_text_plain();
-            Ok(RequestInfo {
-                content,
-                status_code: StatusCode::OK,
-                mime_type,
-            })
+        if name == "*" {
+            wildcard = !refused;
+        }
+    }
+    wildcard
+}
+
+/// The precompressed file to serve instead of path, e.g. page.html.br for page.html, with its Content-Encoding.
+/// Returns None if the client doesn't accept any of the variants that exist, so the original is served.
+pub fn precompressed_variant(
+    path: &Path,
+    accept_encoding: &str,
+) -> Option<(PathBuf, &'static str)> {
+    PRECOMPRESSED_VARIANTS
+        .iter()
+        .filter(|(_, encoding)| accepts_encoding(accept_encoding, encoding))
+        .map(|(extension, encoding)| {
+            let mut variant = path.as_os_str().to_owned();
+            variant.push(".");
+            variant.push(extension);
+            (PathBuf::from(variant), *encoding)
+        })
+        .find(|(variant, _)| variant.is_file())
+}
+
+/// Find the directory for a request path in the first of roots that has one.
+/// Paths that would leave the root, with a .., are never listed.
+pub fn resolve_dir_in_roots(roots: &[PathBuf], path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_matches('/'));
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    roots
+        .iter()
+        .map(|root| root.join(relative))
+        .find(|dir| dir.is_dir())
+}
+
+/// A page listing the files and directories in dir, which is served at request_path.
+/// Directories are listed first, with a trailing /. The names are escaped.
+pub fn directory_listing(dir: &Path, request_path: &str) -> std::io::Result<String> {
+    let mut entries = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            (!is_dir, entry.file_name().to_string_lossy().to_string())
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    let base = match request_path.trim_matches('/') {
+        "" => "/".to_string(),
+        path => format!("/{}/", path),
+    };
+    let title = tera::escape_html(&format!("Index of {}", base));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><title>{}</title></head>\n<body>\n<h1>{}</h1>\n<ul>\n",
+        title, title
+    );
+    for (is_file, name) in entries {
+        let name = if is_file { name } else { format!("{}/", name) };
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            tera::escape_html(&format!("{}{}", base, name)),
+            tera::escape_html(&name)
+        ));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Find the page to serve with an error status, from the error_pages in the serve config.
+/// 404s fall back to 404.html. Returns None if there is no page for the status in root_path.
+pub fn resolve_error_page(
+    root_path: &Path,
+    status: u16,
+    serve_config: &ServeConfig,
+) -> Option<PathBuf> {
+    let page = match serve_config.error_pages.get(&status.to_string()) {
+        Some(page) => page.trim_start_matches('/'),
+        None if status == StatusCode::NOT_FOUND.as_u16() => NOT_FOUND_PATH,
+        None => return None,
+    };
+    Some(root_path.join(page)).filter(|path| path.is_file())
+}
+
+fn file_content(
+    roots: &[PathBuf],
+    path: &str,
+    accept_encoding: &str,
+    serve_config: &ServeConfig,
+    mime_config: &MimeConfig,
+) -> Result<RequestInfo, simple_server::Error> {
+    let Some(path) = resolve_file_in_roots(roots, path, serve_config) else {
+        if serve_config.autoindex {
+            if let Some(dir) = resolve_dir_in_roots(roots, path) {
+                return Ok(RequestInfo {
+                    content: directory_listing(&dir, path)?.into_bytes(),
+                    status_code: StatusCode::OK,
+                    mime_type: Mime::from_str("text/html")
+                        .expect("should be able to parse mime type"),
+                    content_encoding: None,
+                });
+            }
         }
-        (_, _) => {
-            let not_found_path = root_path.join(NOT_FOUND_PATH);
-            let content = if not_found_path.exists() {
-                std::fs::read(not_found_path)?
-            } else {
-                "<h1>404</h1><p>Not found!<p>".as_bytes().to_vec()
-            };
-            let mime_type = Mime::from_str("text/html").expect("should be able to parse mime type");
-            Ok(RequestInfo {
-                content,
-                status_code: StatusCode::NOT_FOUND,
-                mime_type,
-            })
+        return error_content(roots, StatusCode::NOT_FOUND, serve_config);
+    };
+    // The mime type is always the original file's, e.g. text/html for page.html.br
+    let mime_type = util::mime_type(&path, mime_config);
+    let (file, content_encoding) = match precompressed_variant(&path, accept_encoding) {
+        Some((variant, encoding)) => (variant, Some(encoding)),
+        None => (path, None),
+    };
+    match std::fs::read(&file) {
+        Ok(content) => Ok(RequestInfo {
+            content,
+            status_code: StatusCode::OK,
+            mime_type,
+            content_encoding,
+        }),
+        Err(e) => {
+            error!("Error reading {:?}: {}", file, e);
+            error_content(roots, StatusCode::INTERNAL_SERVER_ERROR, serve_config)
         }
     }
 }
+
+/// The response for every request in maintenance mode: the maintenance page, with a 503
+fn maintenance_content(
+    path: &Path,
+    mime_config: &MimeConfig,
+) -> Result<RequestInfo, simple_server::Error> {
+    Ok(RequestInfo {
+        content: std::fs::read(path)?,
+        status_code: StatusCode::SERVICE_UNAVAILABLE,
+        mime_type: util::mime_type(path, mime_config),
+        content_encoding: None,
+    })
+}
+
+/// The response for an error status: its error page from the first root that has one, or a minimal html page
+fn error_content(
+    roots: &[PathBuf],
+    status_code: StatusCode,
+    serve_config: &ServeConfig,
+) -> Result<RequestInfo, simple_server::Error> {
+    let error_page = roots
+        .iter()
+        .find_map(|root| resolve_error_page(root, status_code.as_u16(), serve_config));
+    let content = match error_page {
+        Some(page) => std::fs::read(page)?,
+        None if status_code == StatusCode::NOT_FOUND => {
+            "<h1>404</h1><p>Not found!<p>".as_bytes().to_vec()
+        }
+        None => format!(
+            "<h1>{}</h1><p>{}<p>",
+            status_code.as_u16(),
+            status_code.canonical_reason().unwrap_or_default()
+        )
+        .into_bytes(),
+    };
+    let mime_type = Mime::from_str("text/html").expect("should be able to parse mime type");
+    Ok(RequestInfo {
+        content,
+        status_code,
+        mime_type,
+        content_encoding: None,
+    })
+}
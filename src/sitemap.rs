@@ -0,0 +1,70 @@
+//! The list of pages for the site's sitemap. The sitemap is a page like any other, e.g. pages/sitemap.xml,
+//! which gets the list as sitemap:
+//! {% for entry in sitemap %}<url><loc>{{ base_url }}{{ entry.url }}</loc></url>{% endfor %}
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+
+use crate::config::SitemapConfig;
+use crate::page::Page;
+use crate::post::Post;
+use crate::render::Error;
+
+/// A page in the sitemap
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SitemapEntry {
+    pub url: PathBuf,
+    /// The date of a post, for the sitemap's lastmod. Pages don't have one.
+    pub lastmod: Option<NaiveDate>,
+}
+
+/// The posts and html pages to list in the sitemap, ordered by url.
+/// Posts and pages with noindex or sitemap = false in their frontmatter are left out, as are the 404 page,
+/// pages that aren't html, like the feed and the sitemap itself, and output paths matching the config's exclude globs.
+pub fn entries(
+    posts: &[Post],
+    pages: &[Page],
+    config: &SitemapConfig,
+) -> Result<Vec<SitemapEntry>, Error> {
+    let exclude = exclude_globs(&config.exclude)?;
+    let is_excluded =
+        |output_path: &Path| is_not_found_page(output_path) || exclude.is_match(output_path);
+    let posts = posts
+        .iter()
+        .filter(|p| !p.noindex && p.sitemap && !is_excluded(&p.output_path))
+        .map(|p| SitemapEntry {
+            url: p.url.clone(),
+            lastmod: Some(p.date),
+        });
+    let pages = pages
+        .iter()
+        .filter(|p| p.is_html() && !p.noindex && p.sitemap && !is_excluded(&p.output_path))
+        .map(|p| SitemapEntry {
+            url: p.url.clone(),
+            lastmod: None,
+        });
+    let mut entries = posts.chain(pages).collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(entries)
+}
+
+/// The 404 page at the root of the site, e.g. 404.html, which is never in the sitemap
+fn is_not_found_page(output_path: &Path) -> bool {
+    output_path.parent() == Some(Path::new(""))
+        && output_path.file_stem().is_some_and(|stem| stem == "404")
+}
+
+fn exclude_globs(globs: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(
+            Glob::new(glob.trim_start_matches('/'))
+                .map_err(|e| Error::InvalidGlob(glob.to_string(), e.to_string()))?,
+        );
+    }
+    builder
+        .build()
+        .map_err(|e| Error::InvalidGlob(globs.join(", "), e.to_string()))
+}
@@ -0,0 +1,100 @@
+//! Build the urls and output paths of posts and pages.
+//!
+//! An output path is relative to the output directory, e.g. 2024/12/my-post.html, and the url is the
+//! output path with a leading /, e.g. /2024/12/my-post.html. Urls always use forward slashes.
+use chrono::NaiveDate;
+use std::path::{Component, Path, PathBuf};
+
+use crate::render::Error;
+
+/// The pattern for post urls. :year, :month, :day and :slug are replaced with the post's values.
+pub const DEFAULT_POST_PATTERN: &str = "/:year/:month/:slug.html";
+
+/// The url for an output path: the path with a leading / and forward slashes
+pub fn url_from_output_path(output_path: &Path) -> PathBuf {
+    let parts = output_path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    PathBuf::from(format!("/{}", parts.join("/")))
+}
+
+/// The output path for a post, from a pattern like DEFAULT_POST_PATTERN
+pub fn post_output_path(date: NaiveDate, slug: &str, pattern: &str) -> Result<PathBuf, Error> {
+    let permalink = pattern
+        .replace(":year", &date.format("%Y").to_string())
+        .replace(":month", &date.format("%m").to_string())
+        .replace(":day", &date.format("%d").to_string())
+        .replace(":slug", slug);
+    output_path_from_permalink(&permalink)
+}
+
+/// The url for a post, from a pattern like DEFAULT_POST_PATTERN
+pub fn post_url(date: NaiveDate, slug: &str, pattern: &str) -> Result<PathBuf, Error> {
+    Ok(url_from_output_path(&post_output_path(
+        date, slug, pattern,
+    )?))
+}
+
+/// The output path for a page, from its path relative to the pages directory (or the site root for flat sites).
+/// Markdown pages are written as html, so extension replaces the extension of the source file.
+pub fn page_output_path(relative_path: &Path, extension: &str) -> PathBuf {
+    relative_path.with_extension(extension)
+}
+
+/// The url for a page, from its path relative to the pages directory (or the site root for flat sites)
+pub fn page_url(relative_path: &Path, extension: &str) -> PathBuf {
+    url_from_output_path(&page_output_path(relative_path, extension))
+}
+
+/// Replace the .html extension of an output path with extension, e.g. htm, or remove it if extension is empty.
+/// Other output paths, like feed.xml, are returned unchanged.
+pub fn with_html_extension(output_path: &Path, extension: &str) -> PathBuf {
+    if output_path.extension().is_some_and(|e| e == "html") {
+        output_path.with_extension(extension)
+    } else {
+        output_path.to_path_buf()
+    }
+}
+
+/// Convert a permalink like /about-us.html into an output path relative to the output directory.
+/// A permalink ending in / is written to index.html in that directory.
+/// Permalinks that would be written outside of the output directory are an error.
+pub fn output_path_from_permalink(permalink: &str) -> Result<PathBuf, Error> {
+    let mut output_path = PathBuf::from(permalink.trim_start_matches('/'));
+    if permalink.ends_with('/') {
+        output_path.push("index.html");
+    }
+    let is_safe = output_path
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)));
+    if output_path.as_os_str().is_empty() || !is_safe {
+        return Err(Error::InvalidPermalink(permalink.to_string()));
+    }
+    Ok(output_path)
+}
+
+/// A page that redirects to url, for the old urls of a post or page.
+/// It uses a meta refresh, since a static site can't send a redirect status, and marks url as canonical.
+pub fn redirect_html(url: &str) -> String {
+    let url = tera::escape_html(url);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Redirecting to {url}</title>
+<link rel="canonical" href="{url}">
+<meta name="robots" content="noindex">
+<meta http-equiv="refresh" content="0; url={url}">
+</head>
+<body>
+<p>This page has moved to <a href="{url}">{url}</a>.</p>
+</body>
+</html>
+"#
+    )
+}
@@ -1,34 +1,394 @@
-use std::{fs, path::Path};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::LazyLock,
+};
 
+use base64::Engine;
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset,
+};
+use log::warn;
+use md5::{Digest, Md5};
+use mime_guess::mime::Mime;
+use quick_xml::events::Event;
+use regex::Regex;
 use walkdir::WalkDir;
 
+use crate::config::{LineEndings, MimeConfig};
 use crate::render::Error;
 
-pub fn copy_dir(src: &Path, dst: &Path) -> Result<(), Error> {
-    let src = src
-        .canonicalize()
-        .map_err(|e| Error::Path(src.to_path_buf(), e.to_string()))?;
+/// Normalize rendered output before it is written: remove a leading UTF-8 byte order mark,
+/// which templates can pick up from their source files, and make every line end with line_endings.
+pub fn normalize_output(content: &str, line_endings: LineEndings) -> String {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let content = content.replace("\r\n", "\n").replace('\r', "\n");
+    match line_endings {
+        LineEndings::Lf => content,
+        LineEndings::Crlf => content.replace('\n', "\r\n"),
+    }
+}
+
+/// Format datetime with a strftime format, e.g. "%B %-d, %Y" for December 1, 2024.
+/// A format with an unknown specifier, like %Q, is an error rather than a panic.
+pub fn format_datetime(datetime: &DateTime<FixedOffset>, format: &str) -> Result<String, Error> {
+    let items = StrftimeItems::new(format).collect::<Vec<_>>();
+    if items.contains(&Item::Error) {
+        return Err(Error::InvalidDateFormat(format.to_string()));
+    }
+    let mut formatted = String::new();
+    write!(formatted, "{}", datetime.format_with_items(items.iter()))
+        .map_err(|_| Error::InvalidDateFormat(format.to_string()))?;
+    Ok(formatted)
+}
+
+/// Copy all of the files in src to dst, returning the paths of the copied files relative to dst
+pub fn copy_dir(src: &Path, dst: &Path) -> Result<Vec<PathBuf>, Error> {
+    copy_dir_with_map(src, dst, &HashMap::new(), false)
+}
 
+/// Copy all of the files in src to dst, returning the paths of the copied files relative to dst.
+/// Files under a directory that is a key in map are copied to the directory it maps to instead,
+/// e.g. with { "assets" = "static" }, src/assets/logo.png is copied to dst/static/logo.png.
+/// It is an error for two files to be copied to the same place.
+/// Files that are unchanged since they were last copied are skipped unless force is true; see copy_if_changed.
+/// They are still in the returned paths.
+pub fn copy_dir_with_map(
+    src: &Path,
+    dst: &Path,
+    map: &HashMap<String, String>,
+    force: bool,
+) -> Result<Vec<PathBuf>, Error> {
     fs::create_dir_all(dst).map_err(Error::CopyDir)?;
     let dst = dst
         .canonicalize()
         .map_err(|e| Error::Path(dst.to_path_buf(), e.to_string()))?;
-    for entry in WalkDir::new(&src)
+    // Work out where every file goes before copying anything, so that conflicts don't leave a partial copy.
+    // If dst is inside src, the files already copied to it aren't copied again.
+    let copies = plan_copy_dir_excluding(src, map, Some(&dst))?;
+
+    let mut copied = vec![];
+    for (p, destination) in copies {
+        let output_path = dst.join(&destination);
+        let output_dir = output_path.parent().ok_or(Error::Path(
+            output_path.to_path_buf(),
+            "parent not found".to_string(),
+        ))?;
+        fs::create_dir_all(output_dir).map_err(Error::CopyDir)?;
+        copy_if_changed(&p, &output_path, force)?;
+        copied.push(destination);
+    }
+    Ok(copied)
+}
+
+/// Copy src to dst, unless dst is already a copy of it: the same size, and modified no earlier than src.
+/// A copy is modified when it's made, so a source that has changed since is newer than its copy.
+/// Skipping unchanged files keeps their modification times, so the dev server's caching keeps working.
+/// With force, src is always copied. Returns true if it was copied.
+pub fn copy_if_changed(src: &Path, dst: &Path, force: bool) -> Result<bool, Error> {
+    if !force {
+        let src_metadata = fs::metadata(src).map_err(Error::CopyDir)?;
+        if let Ok(dst_metadata) = fs::metadata(dst) {
+            let is_unchanged = dst_metadata.len() == src_metadata.len()
+                && matches!(
+                    (dst_metadata.modified(), src_metadata.modified()),
+                    (Ok(dst_modified), Ok(src_modified)) if dst_modified >= src_modified
+                );
+            if is_unchanged {
+                return Ok(false);
+            }
+        }
+    }
+    fs::copy(src, dst).map_err(Error::CopyDir)?;
+    Ok(true)
+}
+
+/// The files in dir and its subdirectories, skipping the directory exclude, which must be canonical.
+/// The output directory is excluded like this when it's inside the input directory, so that a build never reads
+/// its own output.
+pub fn walk_files(dir: &Path, exclude: Option<&Path>) -> impl Iterator<Item = walkdir::DirEntry> {
+    let exclude = exclude.map(Path::to_path_buf);
+    WalkDir::new(dir)
         .into_iter()
+        .filter_entry(move |e| {
+            let is_excluded = e.file_type().is_dir()
+                && exclude.as_deref().is_some_and(|exclude| {
+                    e.path().canonicalize().ok().as_deref() == Some(exclude)
+                });
+            !is_excluded
+        })
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file())
-    {
+}
+
+/// Work out where copy_dir_with_map would copy each file in src, without copying anything.
+/// Returns the path of each file and its destination, relative to the destination directory.
+pub fn plan_copy_dir(
+    src: &Path,
+    map: &HashMap<String, String>,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    plan_copy_dir_excluding(src, map, None)
+}
+
+/// Like plan_copy_dir, but skipping the directory exclude, e.g. the output directory when it is inside src.
+/// exclude must be canonical.
+pub fn plan_copy_dir_excluding(
+    src: &Path,
+    map: &HashMap<String, String>,
+    exclude: Option<&Path>,
+) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let src = src
+        .canonicalize()
+        .map_err(|e| Error::Path(src.to_path_buf(), e.to_string()))?;
+    let mut destinations: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut copies = vec![];
+    for entry in walk_files(&src, exclude) {
         let p = entry.path();
         let stripped = p
             .strip_prefix(&src)
             .map_err(|e| Error::StripPrefix(p.to_path_buf(), e))?;
-        let output_path = dst.join(stripped);
-        let output_dir = output_path.parent().ok_or(Error::Path(
-            output_path.to_path_buf(),
-            "parent not found".to_string(),
-        ))?;
-        fs::create_dir_all(output_dir).map_err(Error::CopyDir)?;
-        fs::copy(p, output_path).map_err(Error::CopyDir)?;
+        let destination = map_destination(stripped, map)?;
+        if let Some(other) = destinations.insert(destination.clone(), stripped.to_path_buf()) {
+            return Err(Error::CopyConflict(
+                destination,
+                other,
+                stripped.to_path_buf(),
+            ));
+        }
+        copies.push((p.to_path_buf(), destination));
+    }
+    Ok(copies)
+}
+
+/// Write content to path, unless the file already has exactly that content.
+/// Skipping the write keeps the modification time of unchanged files, like the feed and sitemap, stable.
+/// Returns true if the file was written.
+pub fn write_if_changed(path: &Path, content: &[u8]) -> Result<bool, Error> {
+    if let Ok(existing) = fs::read(path) {
+        if existing == content {
+            return Ok(false);
+        }
+    }
+    fs::write(path, content).map_err(Error::WriteFile)?;
+    Ok(true)
+}
+
+/// The hex encoded MD5 hash of content. This is the ETag that S3 gives a file uploaded in one part,
+/// without the quotes, so it can be compared with the ETags of the files in a bucket.
+pub fn etag(content: &[u8]) -> String {
+    format!("{:x}", Md5::digest(content))
+}
+
+/// The subresource integrity hash of content, for the integrity attribute of a script or link tag,
+/// e.g. sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO
+pub fn sri_hash(content: &[u8]) -> String {
+    let digest = sha2::Sha384::digest(content);
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    )
+}
+
+/// Rewrite the start of a relative path using the longest matching directory in map.
+/// Paths that don't start with any of the directories are returned unchanged.
+/// An empty destination maps the directory to the root.
+pub fn map_destination(path: &Path, map: &HashMap<String, String>) -> Result<PathBuf, Error> {
+    let mut best: Option<(&Path, &str)> = None;
+    for (from, to) in map {
+        let from = Path::new(from.trim_matches('/'));
+        let is_safe = |p: &Path| p.components().all(|c| matches!(c, Component::Normal(_)));
+        if from.as_os_str().is_empty()
+            || !is_safe(from)
+            || !is_safe(Path::new(to.trim_matches('/')))
+        {
+            return Err(Error::InvalidCopyMap(from.to_path_buf(), to.to_string()));
+        }
+        let is_longer =
+            best.is_none_or(|(best, _)| from.components().count() > best.components().count());
+        if path.starts_with(from) && is_longer {
+            best = Some((from, to));
+        }
+    }
+    let Some((from, to)) = best else {
+        return Ok(path.to_path_buf());
+    };
+    let rest = path
+        .strip_prefix(from)
+        .map_err(|e| Error::StripPrefix(path.to_path_buf(), e))?;
+    Ok(Path::new(to.trim_matches('/')).join(rest))
+}
+
+/// Canonicalize a path that may not exist yet, without creating it: the nearest ancestor that exists
+/// is canonicalized, and the rest of the path is added back onto it.
+pub fn canonicalize_uncreated(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"));
+    let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+    Ok(existing.canonicalize()?.join(rest))
+}
+
+/// Lowercase text and replace each run of characters other than a-z, 0-9, - and _ with a single -,
+/// trimming any - from the ends. This is shared by slugify and html_id.
+fn normalize_identifier(text: &str) -> String {
+    let mut normalized = String::new();
+    for c in text.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            normalized.push(c);
+        } else if !normalized.ends_with('-') {
+            normalized.push('-');
+        }
+    }
+    normalized.trim_matches('-').to_string()
+}
+
+/// Normalize a slug so that it is safe to use in a url.
+/// The slug is lowercased, and each run of characters other than a-z, 0-9, - and _ is replaced with a single -.
+/// Returns an error if nothing is left of the slug.
+pub fn slugify(slug: &str) -> Result<String, Error> {
+    let normalized = normalize_identifier(slug);
+    if normalized.is_empty() {
+        return Err(Error::InvalidSlug(slug.to_string()));
+    }
+    Ok(normalized)
+}
+
+/// Make a valid html id out of some text, e.g. a slug.
+/// The id is lowercased, each run of characters other than a-z, 0-9, - and _ is replaced with a single -,
+/// and it is given a post- prefix if it doesn't start with a letter.
+pub fn html_id(text: &str) -> String {
+    let id = normalize_identifier(text);
+    if id.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        id
+    } else {
+        format!("post-{}", id).trim_end_matches('-').to_string()
+    }
+}
+
+/// The MIME type for a file. The overrides in mime_config are checked first, and then the type is guessed
+/// from the file's extension, falling back to text/plain.
+pub fn mime_type(path: &Path, mime_config: &MimeConfig) -> Mime {
+    let extension = path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let overridden = mime_config
+        .overrides
+        .iter()
+        .find(|(ext, _)| ext.to_lowercase() == extension)
+        .and_then(|(ext, mime_type)| match mime_type.parse::<Mime>() {
+            Ok(mime) => Some(mime),
+            Err(e) => {
+                warn!(
+                    "invalid MIME type override for {}: {}: {}",
+                    ext, mime_type, e
+                );
+                None
+            }
+        });
+    overridden.unwrap_or_else(|| mime_guess::from_path(path).first_or_text_plain())
+}
+
+/// Escape a value for xml with quick-xml, replacing &, <, >, ' and ".
+/// Unlike Tera's html escaping, / is left alone, so urls in feeds don't need to be marked safe.
+pub fn escape_xml(value: &str) -> String {
+    quick_xml::escape::escape(value).into_owned()
+}
+
+/// Check that xml is well-formed: every tag is closed in the right order, and all of the text and
+/// attribute values are properly escaped. Returns a description of the first problem found.
+pub fn validate_xml(xml: &str) -> Result<(), String> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut open_tags = vec![];
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("at position {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(start) => {
+                for attribute in start.attributes() {
+                    attribute
+                        .map_err(|e| e.to_string())?
+                        .unescape_value()
+                        .map_err(|e| e.to_string())?;
+                }
+                open_tags.push(start.name().as_ref().to_vec());
+            }
+            Event::Empty(empty) => {
+                for attribute in empty.attributes() {
+                    attribute
+                        .map_err(|e| e.to_string())?
+                        .unescape_value()
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            Event::End(_) => {
+                open_tags.pop();
+            }
+            Event::Text(text) => {
+                text.unescape()
+                    .map_err(|e| format!("at position {}: {}", reader.buffer_position(), e))?;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    match open_tags.last() {
+        Some(tag) => Err(format!("unclosed tag: {}", String::from_utf8_lossy(tag))),
+        None => Ok(()),
+    }
+}
+
+/// The src attribute of an <img> tag, compiled once rather than for every page
+static IMAGE_SRC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<img\b[^>]*?\ssrc\s*=\s*(?:"([^"]*)"|'([^']*)')"#)
+        .expect("image regex should be valid")
+});
+
+/// Find the src of every <img> tag in some html.
+/// Markdown images have already been rendered to <img> tags by the time a page is written.
+pub fn image_sources(html: &str) -> Vec<String> {
+    IMAGE_SRC_RE
+        .captures_iter(html)
+        .filter_map(|c| c.get(1).or(c.get(2)))
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+/// The path of a local image relative to the output directory, resolved against the url of the page it is on.
+/// Remote images, protocol-relative images and data URIs are not local, so they return None.
+/// Like a browser, a .. at the root of the site stays at the root.
+/// src is an attribute value, so entities like &amp; are unescaped, and then it is percent-decoded,
+/// e.g. my%20photo.png is the file my photo.png.
+pub fn local_image_path(page_url: &Path, src: &str) -> Option<PathBuf> {
+    let src = quick_xml::escape::unescape(src).unwrap_or(src.into());
+    let src = src.split(['?', '#']).next().unwrap_or_default();
+    let src = percent_encoding::percent_decode_str(src)
+        .decode_utf8_lossy()
+        .into_owned();
+    if src.is_empty() || src.contains("://") || src.starts_with("//") || src.starts_with("data:") {
+        return None;
+    }
+    let path = if src.starts_with('/') {
+        PathBuf::from(src)
+    } else {
+        page_url.parent().unwrap_or(Path::new("/")).join(src)
+    };
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(c) => resolved.push(c),
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            _ => {}
+        }
     }
-    Ok(())
+    Some(resolved)
 }
@@ -1,34 +1,181 @@
+use crate::post::Post;
 use crate::render;
 use log::info;
-use notify::{Error, Event, RecursiveMode, Watcher};
-use std::{path::Path, sync::mpsc};
+use notify::{Error, Event, PollWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
 
-pub fn watch(input_dir: &Path, output_dir: &Path) -> Result<(), Error> {
+/// How long to wait for more changes after one, so that a burst of changes, like saving several files at once,
+/// is rendered once
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// What needs to be rendered after a set of changes
+#[derive(Debug, PartialEq, Eq)]
+pub enum RenderScope {
+    /// Something other than a post changed, e.g. a template, the config or a page, so the whole site is rendered
+    Full,
+    /// Only these posts changed. Their paths are relative to the input directory, like the input paths of posts.
+    /// They are rendered along with all of the pages, which can list them.
+    Posts(HashSet<PathBuf>),
+}
+
+/// Decide what to render for the paths that changed in input_dir.
+/// Only the changed posts are rendered when nothing else changed. Other posts aren't rewritten,
+/// so their related posts and series links can be out of date until the next full render.
+pub fn render_scope(input_dir: &Path, changed: &BTreeSet<PathBuf>) -> RenderScope {
+    let posts_dir = input_dir.join(Post::READ_DIRECTORY);
+    let posts = changed
+        .iter()
+        .map(|path| {
+            let is_post = path.starts_with(&posts_dir)
+                && path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| Post::VALID_EXTENSIONS.contains(&e));
+            if !is_post {
+                return None;
+            }
+            path.strip_prefix(input_dir).ok().map(Path::to_path_buf)
+        })
+        .collect::<Option<HashSet<_>>>();
+    match posts {
+        Some(posts) if !posts.is_empty() => RenderScope::Posts(posts),
+        _ => RenderScope::Full,
+    }
+}
+
+/// Re-render the site whenever something in input_dir changes.
+/// Changes are collected until none have arrived for a moment, and then rendered together. See render_scope.
+/// With a poll_interval, the filesystem is polled instead of using the platform's native events,
+/// for filesystems that don't send them, like network shares and some Docker volumes.
+/// Every render uses options, e.g. with a RenderOptions::template_dir, which is watched too.
+/// Each successful render is logged. Use watch_with_callback to do something else after it.
+pub fn watch(
+    input_dir: &Path,
+    output_dir: &Path,
+    poll_interval: Option<Duration>,
+    options: &render::RenderOptions,
+) -> Result<(), Error> {
+    watch_with_callback(input_dir, output_dir, poll_interval, options, |changed| {
+        info!("Rendered changes to {:?}", changed)
+    })
+}
+
+/// Like watch, but calls on_render with the paths that changed after each successful render,
+/// e.g. to reload a browser when the site is embedded in another program.
+/// It isn't called when a render fails, the error is logged instead.
+pub fn watch_with_callback(
+    input_dir: &Path,
+    output_dir: &Path,
+    poll_interval: Option<Duration>,
+    options: &render::RenderOptions,
+    on_render: impl FnMut(&[PathBuf]),
+) -> Result<(), Error> {
     let (tx, rx) = mpsc::channel::<Result<Event, notify::Error>>();
+    let input_dir = input_dir.canonicalize()?;
     let output_dir = output_dir.canonicalize()?;
 
-    let mut watcher =
-        notify::recommended_watcher(tx).unwrap_or_else(|e| panic!("notify error: ${e}"));
+    let mut watcher: Box<dyn Watcher> = match poll_interval {
+        Some(interval) => {
+            info!("Polling for changes every {}ms", interval.as_millis());
+            let config = notify::Config::default().with_poll_interval(interval);
+            Box::new(PollWatcher::new(tx, config)?)
+        }
+        None => Box::new(
+            notify::recommended_watcher(tx).unwrap_or_else(|e| panic!("notify error: ${e}")),
+        ),
+    };
 
-    watcher.watch(input_dir, RecursiveMode::Recursive)?;
-    for result in rx {
-        match result {
-            Ok(event) => {
-                // deal with case where the output directory is a subdirectory of the input directory
-                if event.paths.iter().all(|p| p.starts_with(&output_dir)) {
+    watcher.watch(&input_dir, RecursiveMode::Recursive)?;
+    if let Some(template_dir) = &options.template_dir {
+        let template_dir = input_dir.join(template_dir).canonicalize()?;
+        if !template_dir.starts_with(&input_dir) {
+            watcher.watch(&template_dir, RecursiveMode::Recursive)?;
+        }
+    }
+    render_on_changes(rx, &input_dir, &output_dir, options, on_render);
+    Ok(())
+}
+
+/// Collect the change events from rx until none have arrived for a moment, render them together,
+/// and call on_render with the paths that changed if the render succeeded. Changes in output_dir are ignored.
+/// Returns when rx is disconnected, e.g. when the watcher sending the events is dropped.
+pub fn render_on_changes(
+    rx: Receiver<Result<Event, notify::Error>>,
+    input_dir: &Path,
+    output_dir: &Path,
+    options: &render::RenderOptions,
+    mut on_render: impl FnMut(&[PathBuf]),
+) {
+    let mut changed = BTreeSet::new();
+    loop {
+        // wait as long as it takes for the first change, and then only until the changes stop
+        let result = if changed.is_empty() {
+            match rx.recv() {
+                Ok(result) => result,
+                Err(_) => break,
+            }
+        } else {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(result) => result,
+                Err(RecvTimeoutError::Timeout) => {
+                    if render_changes(input_dir, output_dir, &changed, options) {
+                        on_render(&changed.iter().cloned().collect::<Vec<_>>());
+                    }
+                    changed.clear();
                     continue;
                 }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        };
+        match result {
+            Ok(event) => {
                 info!("change event: {:?}", event);
-                info!(
-                    "Rendering posts and pages in {:?} to {:?}",
-                    input_dir, output_dir
+                // deal with case where the output directory is a subdirectory of the input directory
+                changed.extend(
+                    event
+                        .paths
+                        .into_iter()
+                        .filter(|p| !p.starts_with(output_dir)),
                 );
-                render::render_dir(input_dir, &output_dir).unwrap_or_else(|e| {
-                    info!("Error rendering posts and pages: {}", e);
-                });
             }
             Err(e) => info!("change event error: {:?}", e),
         }
     }
-    Ok(())
+}
+
+/// Render what changed, returning whether the render succeeded
+fn render_changes(
+    input_dir: &Path,
+    output_dir: &Path,
+    changed: &BTreeSet<PathBuf>,
+    options: &render::RenderOptions,
+) -> bool {
+    let options = match render_scope(input_dir, changed) {
+        RenderScope::Full => {
+            info!(
+                "Rendering posts and pages in {:?} to {:?}",
+                input_dir, output_dir
+            );
+            options.clone()
+        }
+        RenderScope::Posts(posts) => {
+            info!("Rendering {:?} and the pages to {:?}", posts, output_dir);
+            render::RenderOptions {
+                only_posts: Some(posts),
+                ..options.clone()
+            }
+        }
+    };
+    match render::render_dir_with_options(input_dir, output_dir, &options) {
+        Ok(_) => true,
+        Err(e) => {
+            info!("Error rendering posts and pages: {}", e);
+            false
+        }
+    }
 }
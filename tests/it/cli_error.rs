@@ -0,0 +1,51 @@
+//! Tests for the cli_error module
+use kalamos::{
+    cli_error::{CommandError, ErrorType},
+    render,
+};
+use std::{collections::HashSet, path::Path};
+
+#[test]
+fn test_exit_codes_are_distinct() {
+    let types = [
+        ErrorType::Usage,
+        ErrorType::Config,
+        ErrorType::Render,
+        ErrorType::Serve,
+        ErrorType::Watch,
+        ErrorType::Deploy,
+        ErrorType::Io,
+    ];
+    let codes = types.iter().map(|t| t.exit_code()).collect::<HashSet<_>>();
+    assert_eq!(codes.len(), types.len());
+    assert!(!codes.contains(&0) && !codes.contains(&1));
+}
+
+#[test]
+fn test_command_error_output() {
+    let error = CommandError::new(ErrorType::Usage, "--drafts-to is the output directory");
+    assert_eq!(
+        error.to_json(),
+        r#"{"error_type":"usage","message":"--drafts-to is the output directory"}"#
+    );
+    assert_eq!(
+        error.to_string(),
+        "usage error: --drafts-to is the output directory"
+    );
+}
+
+#[test]
+fn test_command_error_from_render_error() {
+    let error = CommandError::from(render::Error::MissingTheme(Path::new("themes/x").into()));
+    assert_eq!(error.error_type, ErrorType::Render);
+    assert_eq!(error.message, "theme not found: themes/x");
+
+    // a broken config.toml stops the render, but it's a config error
+    let error = render::render_dir(
+        Path::new("tests/it/testdata/bad_config_site"),
+        &std::env::temp_dir().join("kalamos_test_cli_error_output"),
+    )
+    .map_err(CommandError::from)
+    .expect_err("should fail");
+    assert_eq!(error.error_type, ErrorType::Config);
+}
@@ -0,0 +1,55 @@
+//! Tests for the content module
+use kalamos::{
+    content::Content,
+    page::{Page, PageFile},
+    post::{Post, PostFile},
+    render::Render,
+};
+use std::path::{Path, PathBuf};
+use tera::Tera;
+
+#[test]
+fn test_content_accessors() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let post = Post::from_content(
+        post_file,
+        "+++\ntitle = \"First\"\n+++\nbody",
+        &Tera::default(),
+    )
+    .expect("should parse");
+    let page_file = PageFile::try_from(PathBuf::from("pages/about.md")).expect("should parse");
+    let page = Page::from_content(
+        page_file,
+        "+++\ntitle = \"About\"\n+++\nbody",
+        &Tera::default(),
+    )
+    .expect("should parse");
+
+    let contents: Vec<Content> = vec![post.into(), page.into()];
+    let summary = contents
+        .iter()
+        .map(|c| (c.kind(), c.title(), c.url(), c.output_path()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                "post",
+                "First",
+                Path::new("/2024/12/first.html"),
+                Path::new("2024/12/first.html")
+            ),
+            (
+                "page",
+                "About",
+                Path::new("/about.html"),
+                Path::new("about.html")
+            ),
+        ]
+    );
+    assert_eq!(
+        contents[1].to_context().get("title"),
+        Some(&tera::Value::String("About".to_string()))
+    );
+}
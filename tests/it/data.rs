@@ -0,0 +1,36 @@
+//! Tests for the data module
+use kalamos::{data, render};
+use std::path::Path;
+
+#[test]
+fn test_load_data() {
+    let root_dir = Path::new("tests/it/testdata/simple_site");
+    let data = data::load_data(root_dir).expect("should load data");
+    assert_eq!(data["team"]["members"][0]["name"], "Scott");
+    assert_eq!(data["nav"][0]["url"], "/");
+    assert_eq!(data["social"]["github"], "spatten");
+}
+
+#[test]
+fn test_load_data_without_data_directory() {
+    let root_dir = Path::new("tests/it/testdata/simple_site/pages");
+    let data = data::load_data(root_dir).expect("should load data");
+    assert_eq!(data, tera::Value::Object(tera::Map::new()));
+}
+
+#[test]
+fn test_load_data_with_malformed_file() {
+    let root_dir = Path::new("tests/it/testdata/bad_data");
+    let err = data::load_data(root_dir).expect_err("should not load data");
+    assert!(matches!(err, render::Error::ParseData(ref path, _) if path.ends_with("broken.toml")));
+}
+
+#[test]
+fn test_load_data_with_duplicate_key() {
+    let root_dir = Path::new("tests/it/testdata/duplicate_data");
+    let err = data::load_data(root_dir).expect_err("should not load data");
+    assert_eq!(
+        err.to_string(),
+        "duplicate data key: tests/it/testdata/duplicate_data/data/nav.json and tests/it/testdata/duplicate_data/data/nav.yaml would both be data.nav"
+    );
+}
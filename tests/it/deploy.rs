@@ -0,0 +1,262 @@
+//! Tests for the deploy module
+use kalamos::{
+    config,
+    deploy::{self, DeployState, UploadAction},
+};
+use simple_test_case::test_case;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    time::Duration,
+};
+
+#[test]
+fn test_deploy_state_round_trip() {
+    let input_dir = env::temp_dir().join("kalamos_test_deploy_state");
+    let _ = fs::remove_dir_all(&input_dir);
+    fs::create_dir_all(&input_dir).expect("should create dir");
+
+    // no state file, so nothing is unchanged
+    let state = DeployState::load(&input_dir, "example.com");
+    assert_eq!(state.files, BTreeMap::new());
+
+    let hash = deploy::hash_object(b"<h1>Home</h1>", "text/html");
+    let state = DeployState {
+        bucket: "example.com".to_string(),
+        files: BTreeMap::from([("index.html".to_string(), hash.clone())]),
+    };
+    state.save(&input_dir).expect("should save");
+
+    let loaded = DeployState::load(&input_dir, "example.com");
+    assert_eq!(loaded, state);
+    assert!(loaded.is_unchanged("index.html", &hash));
+    assert!(!loaded.is_unchanged("index.html", &deploy::hash_object(b"changed", "text/html")));
+    assert!(!loaded.is_unchanged(
+        "index.html",
+        &deploy::hash_object(b"<h1>Home</h1>", "text/plain")
+    ));
+    assert!(!loaded.is_unchanged("about.html", &hash));
+
+    // the state is ignored when deploying to a different bucket
+    let other = DeployState::load(&input_dir, "other.example.com");
+    assert!(!other.is_unchanged("index.html", &hash));
+}
+
+#[test_case(Some("Throttling"), true; "throttling")]
+#[test_case(Some("TooManyInvalidationsInProgress"), true; "too many invalidations")]
+#[test_case(Some("AccessDenied"), false; "access denied")]
+#[test_case(None, false; "no code")]
+#[test]
+fn test_is_retryable_cloudfront_error(code: Option<&str>, expected: bool) {
+    assert_eq!(deploy::is_retryable_cloudfront_error(code), expected);
+}
+
+#[test_case(vec!["style.css"], 5, vec!["/style.css"]; "single file")]
+#[test_case(vec!["index.html", "blog/index.html"], 5, vec!["/", "/blog/", "/blog/index.html", "/index.html"]; "index files")]
+#[test_case(vec!["a.html", "b.html", "c.html"], 2, vec!["/*"]; "too many paths")]
+#[test_case(vec![], 5, vec![]; "nothing changed")]
+#[test]
+fn test_invalidation_paths(changed: Vec<&str>, max_paths: usize, expected: Vec<&str>) {
+    let changed = changed
+        .into_iter()
+        .map(String::from)
+        .collect::<BTreeSet<_>>();
+    assert_eq!(deploy::invalidation_paths(&changed, max_paths), expected);
+}
+
+#[test]
+fn test_caller_reference() {
+    let state = |hash: &str| DeployState {
+        bucket: "example.com".to_string(),
+        files: BTreeMap::from([("index.html".to_string(), hash.to_string())]),
+    };
+    let changed = BTreeSet::from(["index.html".to_string()]);
+    let first = deploy::caller_reference(&changed, &state("a"), &state("b"));
+    // a retry of the same deploy reuses the reference
+    assert_eq!(
+        first,
+        deploy::caller_reference(&changed, &state("a"), &state("b"))
+    );
+    // going back to the earlier content is a different deploy
+    assert_ne!(
+        first,
+        deploy::caller_reference(&changed, &state("b"), &state("a"))
+    );
+    assert!(
+        first.len() <= 128,
+        "CloudFront caller references are limited to 128 characters"
+    );
+}
+
+#[test_case("", None; "default credentials")]
+#[test_case("profile = \"client-a\"", Some("client-a"); "named profile")]
+#[test]
+fn test_deploy_config_profile(extra: &str, expected: Option<&str>) {
+    let config: config::DeployConfig = toml::from_str(&format!(
+        "strategy = \"s3_and_cloudfront\"\nbucket = \"example.com\"\n{}",
+        extra
+    ))
+    .expect("should parse");
+    let deploy_config = deploy::DeployConfig::from(config);
+    assert_eq!(deploy_config.profile.as_deref(), expected);
+}
+
+#[test]
+fn test_deploy_config_timeouts() {
+    let config: config::DeployConfig = toml::from_str(
+        "strategy = \"s3_and_cloudfront\"\nbucket = \"example.com\"\nmax_attempts = 5",
+    )
+    .expect("should parse");
+    let deploy_config = deploy::DeployConfig::from(config);
+    let timeouts = deploy_config.timeout_config();
+    assert_eq!(timeouts.connect_timeout(), Some(Duration::from_secs(10)));
+    assert_eq!(
+        timeouts.operation_attempt_timeout(),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(deploy_config.retry_config().max_attempts(), 5);
+}
+
+#[test_case(Some("\"5d41402abc4b2a76b9719d911017c592\""), true; "matching single part e_tag")]
+#[test_case(Some("\"7d793037a0760186574b0282f2f435e7\""), false; "different content")]
+#[test_case(Some("\"5d41402abc4b2a76b9719d911017c592-2\""), false; "multipart e_tag")]
+#[test_case(None, false; "no e_tag")]
+#[test]
+fn test_matches_e_tag(e_tag: Option<&str>, expected: bool) {
+    assert_eq!(deploy::matches_e_tag(e_tag, b"hello"), expected);
+}
+
+#[test]
+fn test_delete_errors_message() {
+    assert_eq!(deploy::delete_errors_message(&[]), None);
+    let errors = [
+        aws_sdk_s3::types::Error::builder()
+            .key("old.html")
+            .code("AccessDenied")
+            .message("Access Denied")
+            .build(),
+        aws_sdk_s3::types::Error::builder().key("gone.css").build(),
+    ];
+    assert_eq!(
+        deploy::delete_errors_message(&errors).as_deref(),
+        Some("failed to delete 2 files: old.html (AccessDenied: Access Denied), gone.css (unknown: )")
+    );
+}
+
+#[test_case(Some(("hello", "text/plain")), None, UploadAction::Upload; "new file")]
+#[test_case(Some(("hello", "text/plain")), Some("\"5d41402abc4b2a76b9719d911017c592\""), UploadAction::Skip; "unchanged")]
+#[test_case(None, Some("\"5d41402abc4b2a76b9719d911017c592\""), UploadAction::Invalidate; "uploaded by a failed deploy")]
+#[test_case(None, Some("\"7d793037a0760186574b0282f2f435e7\""), UploadAction::Upload; "changed without a state")]
+#[test_case(Some(("old", "text/plain")), Some("\"7d793037a0760186574b0282f2f435e7\""), UploadAction::Upload; "changed content")]
+#[test_case(Some(("hello", "text/html")), Some("\"5d41402abc4b2a76b9719d911017c592\""), UploadAction::Upload; "changed content type")]
+#[test]
+fn test_upload_action(previous: Option<(&str, &str)>, e_tag: Option<&str>, expected: UploadAction) {
+    let previous_state = DeployState {
+        bucket: "example.com".to_string(),
+        files: previous
+            .map(|(content, content_type)| {
+                let hash = deploy::hash_object(content.as_bytes(), content_type);
+                ("hello.txt".to_string(), hash)
+            })
+            .into_iter()
+            .collect(),
+    };
+    let hash = deploy::hash_object(b"hello", "text/plain");
+    let on_s3 = e_tag.map(Some);
+    assert_eq!(
+        deploy::upload_action(&previous_state, "hello.txt", &hash, on_s3, b"hello"),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn test_failed_invalidation_is_retried() {
+    let input_dir = env::temp_dir().join("kalamos_test_failed_invalidation");
+    let _ = fs::remove_dir_all(&input_dir);
+    fs::create_dir_all(&input_dir).expect("should create dir");
+    let state = |content: &[u8]| DeployState {
+        bucket: "example.com".to_string(),
+        files: BTreeMap::from([(
+            "index.html".to_string(),
+            deploy::hash_object(content, "text/html"),
+        )]),
+    };
+    let previous_state = state(b"old");
+    previous_state.save(&input_dir).expect("should save");
+
+    // the new index.html is uploaded, but the invalidation fails, so the state isn't saved
+    let new_state = state(b"new");
+    let result = deploy::save_after_invalidation(&input_dir, &new_state, async {
+        Err(deploy::Error::NoDistributionList)
+    })
+    .await;
+    assert!(result.is_err());
+    let loaded = DeployState::load(&input_dir, "example.com");
+    assert_eq!(loaded, previous_state);
+
+    // the next deploy finds index.html already uploaded, but still changed, so it is invalidated
+    let hash = &new_state.files["index.html"];
+    let e_tag = format!("\"{}\"", kalamos::util::etag(b"new"));
+    let action = deploy::upload_action(&loaded, "index.html", hash, Some(Some(&e_tag)), b"new");
+    assert_ne!(action, UploadAction::Skip);
+
+    // once the invalidation succeeds, the state is saved and index.html is unchanged
+    deploy::save_after_invalidation(&input_dir, &new_state, async { Ok(()) })
+        .await
+        .expect("should save");
+    let loaded = DeployState::load(&input_dir, "example.com");
+    assert_eq!(loaded, new_state);
+    let action = deploy::upload_action(&loaded, "index.html", hash, Some(Some(&e_tag)), b"new");
+    assert_eq!(action, UploadAction::Skip);
+}
+
+#[tokio::test]
+async fn test_retried_deploy_reuses_caller_reference() {
+    let input_dir = env::temp_dir().join("kalamos_test_retried_deploy");
+    let _ = fs::remove_dir_all(&input_dir);
+    fs::create_dir_all(&input_dir).expect("should create dir");
+    let previous_state = DeployState {
+        bucket: "example.com".to_string(),
+        files: BTreeMap::from([
+            (
+                "index.html".to_string(),
+                deploy::hash_object(b"old", "text/html"),
+            ),
+            (
+                "old.html".to_string(),
+                deploy::hash_object(b"gone", "text/html"),
+            ),
+        ]),
+    };
+    previous_state.save(&input_dir).expect("should save");
+    let hash = deploy::hash_object(b"new", "text/html");
+    let state = DeployState {
+        bucket: "example.com".to_string(),
+        files: BTreeMap::from([("index.html".to_string(), hash.clone())]),
+    };
+
+    // the first deploy uploads index.html and deletes old.html, then fails to invalidate them
+    let changed = BTreeSet::from(["index.html".to_string(), "old.html".to_string()]);
+    let first = deploy::caller_reference(&changed, &previous_state, &state);
+    let result = deploy::save_after_invalidation(&input_dir, &state, async {
+        Err(deploy::Error::NoDistributionList)
+    })
+    .await;
+    assert!(result.is_err());
+
+    // the retry finds index.html already uploaded and old.html already deleted, but both still changed
+    let loaded = DeployState::load(&input_dir, "example.com");
+    let e_tag = format!("\"{}\"", kalamos::util::etag(b"new"));
+    let action = deploy::upload_action(&loaded, "index.html", &hash, Some(Some(&e_tag)), b"new");
+    assert_ne!(action, UploadAction::Skip);
+    let mut retried = BTreeSet::from(["index.html".to_string()]);
+    retried.extend(
+        loaded
+            .files
+            .keys()
+            .filter(|key| !state.files.contains_key(*key))
+            .cloned(),
+    );
+    assert_eq!(retried, changed);
+    assert_eq!(deploy::caller_reference(&retried, &loaded, &state), first);
+}
@@ -0,0 +1,29 @@
+//! Tests for the doctor module
+use kalamos::doctor::{self, Severity};
+use simple_test_case::test_case;
+use std::path::Path;
+
+#[test_case("tests/it/testdata/simple_site", false; "complete site")]
+#[test_case("tests/it/testdata/missing", true; "missing input directory")]
+#[test_case("tests/it/testdata/bad_config_site", true; "invalid config")]
+#[test_case("tests/it/testdata/bad_data", true; "no layouts")]
+#[test]
+fn test_check_project(input_dir: &str, expected_critical: bool) {
+    let checks = doctor::check_project(Path::new(input_dir));
+    assert_eq!(
+        doctor::has_critical(&checks),
+        expected_critical,
+        "{:?}",
+        checks
+    );
+}
+
+#[test]
+fn test_check_project_without_config() {
+    let checks = doctor::check_project(Path::new("tests/it/testdata/simple_site"));
+    let config_check = checks
+        .iter()
+        .find(|c| c.name == "config.toml")
+        .expect("should check the config");
+    assert_eq!(config_check.severity, Severity::Warning);
+}
@@ -0,0 +1,104 @@
+//! Tests for the favicon module
+use image::{ImageFormat, Rgba, RgbaImage};
+use kalamos::{
+    config::FaviconConfig,
+    favicon::{self, FaviconIcon},
+};
+use simple_test_case::test_case;
+use std::{io::Cursor, path::PathBuf};
+
+/// The start of a PNG file: the signature and the IHDR chunk with the width and height
+fn png_header(width: u32, height: u32) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+    png.extend(width.to_be_bytes());
+    png.extend(height.to_be_bytes());
+    png.extend([8, 6, 0, 0, 0]);
+    png
+}
+
+#[test_case(png_header(512, 512), Some((512, 512)); "square")]
+#[test_case(png_header(32, 16), Some((32, 16)); "wide")]
+#[test_case(b"GIF89a".to_vec(), None; "not a png")]
+#[test_case(png_header(512, 512)[..20].to_vec(), None; "truncated")]
+#[test]
+fn test_png_size(png: Vec<u8>, expected: Option<(u32, u32)>) {
+    assert_eq!(favicon::png_size(&png), expected);
+}
+
+/// A PNG of the given size, in one color
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let image = RgbaImage::from_pixel(width, height, Rgba([200, 40, 40, 255]));
+    let mut png = Cursor::new(vec![]);
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .expect("should encode");
+    png.into_inner()
+}
+
+#[test_case(png(600, 600); "square")]
+#[test_case(png(48, 32); "not square")]
+#[test]
+fn test_favicon_files(icon: Vec<u8>) {
+    let config = FaviconConfig {
+        name: "My Site".to_string(),
+        ..FaviconConfig::default()
+    };
+    let (favicon, files) = favicon::favicon_files(&config, icon).expect("should be a png");
+    assert_eq!(favicon.icon, "/favicon-32x32.png");
+    assert_eq!(favicon.apple_touch_icon, "/apple-touch-icon.png");
+    assert_eq!(favicon.manifest, "/site.webmanifest");
+    let paths = files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("favicon-16x16.png"),
+            PathBuf::from("favicon-32x32.png"),
+            PathBuf::from("apple-touch-icon.png"),
+            PathBuf::from("android-chrome-192x192.png"),
+            PathBuf::from("android-chrome-512x512.png"),
+            PathBuf::from("site.webmanifest"),
+        ]
+    );
+    // each icon is resized, and cropped to a square
+    for (file, size) in files.iter().zip([16, 32, 180, 192, 512]) {
+        assert_eq!(favicon::png_size(&file.content), Some((size, size)));
+    }
+
+    let expected_icons = [
+        ("/favicon-16x16.png", "16x16"),
+        ("/favicon-32x32.png", "32x32"),
+        ("/apple-touch-icon.png", "180x180"),
+        ("/android-chrome-192x192.png", "192x192"),
+        ("/android-chrome-512x512.png", "512x512"),
+    ];
+    assert_eq!(
+        favicon.icons,
+        expected_icons
+            .iter()
+            .map(|(src, sizes)| FaviconIcon {
+                src: src.to_string(),
+                sizes: sizes.to_string(),
+            })
+            .collect::<Vec<_>>()
+    );
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&files[5].content).expect("should be json");
+    assert_eq!(manifest["name"], "My Site");
+    assert_eq!(manifest["short_name"], "My Site");
+    assert_eq!(
+        manifest["icons"],
+        serde_json::Value::Array(
+            expected_icons
+                .iter()
+                .map(|(src, sizes)| serde_json::json!({ "src": src, "sizes": sizes, "type": "image/png" }))
+                .collect()
+        )
+    );
+}
+
+#[test_case(b"GIF89a".to_vec(); "not a png")]
+#[test_case(png_header(512, 512); "only a png header")]
+#[test]
+fn test_favicon_files_not_a_png(icon: Vec<u8>) {
+    assert!(favicon::favicon_files(&FaviconConfig::default(), icon).is_none());
+}
@@ -0,0 +1,97 @@
+//! Tests for the git module
+use git2::{Oid, Repository, Signature, WorktreeAddOptions};
+use kalamos::git;
+use std::{env, fs, path::PathBuf};
+
+/// An empty repository in a temp dir of its own
+fn init(name: &str) -> (PathBuf, Repository) {
+    let root = env::temp_dir().join(format!("kalamos_test_git_{}", name));
+    let _ = fs::remove_dir_all(&root);
+    let repo = Repository::init(&root).expect("should init");
+    (root, repo)
+}
+
+/// Commit an empty tree to HEAD
+fn commit(repo: &Repository) -> Oid {
+    let signature = Signature::now("Test", "test@example.com").expect("should sign");
+    let tree_id = repo
+        .index()
+        .and_then(|mut index| index.write_tree())
+        .expect("should write tree");
+    let tree = repo.find_tree(tree_id).expect("should find tree");
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "commit",
+        &tree,
+        &parent.iter().collect::<Vec<_>>(),
+    )
+    .expect("should commit")
+}
+
+fn short(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+#[test]
+fn test_head_commit() {
+    let (root, repo) = init("branch");
+    commit(&repo);
+    let head = commit(&repo);
+    // the site can be in a subdirectory of the repository
+    let site = root.join("site");
+    fs::create_dir_all(&site).expect("should create dir");
+    assert_eq!(git::head_commit(&site), Some(short(head)));
+}
+
+#[test]
+fn test_head_commit_detached() {
+    let (root, repo) = init("detached");
+    let first = commit(&repo);
+    commit(&repo);
+    repo.set_head_detached(first).expect("should detach");
+    assert_eq!(git::head_commit(&root), Some(short(first)));
+}
+
+#[test]
+fn test_head_commit_packed_ref() {
+    let (root, repo) = init("packed");
+    let head = commit(&repo);
+    let branch = repo.head().expect("should have a head");
+    let name = branch.name().expect("should be named").to_string();
+    fs::write(
+        root.join(".git/packed-refs"),
+        format!(
+            "# pack-refs with: peeled fully-peeled sorted\n{} {}\n",
+            head, name
+        ),
+    )
+    .expect("should write");
+    fs::remove_file(root.join(".git").join(&name)).expect("should remove the loose ref");
+    assert_eq!(git::head_commit(&root), Some(short(head)));
+}
+
+#[test]
+fn test_head_commit_no_commits_yet() {
+    let (root, _repo) = init("empty");
+    assert_eq!(git::head_commit(&root), None);
+}
+
+#[test]
+fn test_head_commit_worktree() {
+    let (root, repo) = init("worktree");
+    let main = commit(&repo);
+    let worktree_dir = env::temp_dir().join("kalamos_test_git_worktree_feature");
+    let _ = fs::remove_dir_all(&worktree_dir);
+    let worktree = repo
+        .worktree("feature", &worktree_dir, Some(&WorktreeAddOptions::new()))
+        .expect("should add worktree");
+    let worktree_repo = Repository::open_from_worktree(&worktree).expect("should open");
+    let feature = commit(&worktree_repo);
+    // the worktree has its own HEAD, and .git there is a file pointing into the main repository
+    assert!(worktree_dir.join(".git").is_file());
+    assert_eq!(git::head_commit(&worktree_dir), Some(short(feature)));
+    assert_eq!(git::head_commit(&root), Some(short(main)));
+}
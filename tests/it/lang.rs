@@ -0,0 +1,19 @@
+//! Tests for the lang module
+use kalamos::lang;
+use simple_test_case::test_case;
+use std::path::Path;
+
+#[test_case("posts/es/2024-12-01-hola.md", "posts", Some("es"); "post in a language directory")]
+#[test_case("pages/en/blog/about.md", "pages", Some("en"); "nested page")]
+#[test_case("en/about.md", "pages", Some("en"); "flat root page")]
+#[test_case("posts/2024-12-01-hello.md", "posts", None; "no language directory")]
+#[test_case("pages/fr/about.md", "pages", None; "unknown language")]
+#[test_case("pages/en", "pages", None; "file named like a language")]
+#[test]
+fn test_language_of(input_path: &str, read_directory: &str, expected: Option<&str>) {
+    let languages = vec!["en".to_string(), "es".to_string()];
+    assert_eq!(
+        lang::language_of(Path::new(input_path), read_directory, &languages),
+        expected.map(String::from)
+    );
+}
@@ -0,0 +1,21 @@
+//! Tests for the logger module
+use chrono::{TimeZone, Utc};
+use kalamos::logger;
+use log::Level;
+use simple_test_case::test_case;
+
+#[test_case(Level::Info, "rendered 3 posts", r#"{"timestamp":"2025-01-02T03:04:05.000Z","level":"INFO","target":"kalamos::render","message":"rendered 3 posts"}"#; "info")]
+#[test_case(Level::Warn, "a \"quoted\"\nmessage", r#"{"timestamp":"2025-01-02T03:04:05.000Z","level":"WARN","target":"kalamos::render","message":"a \"quoted\"\nmessage"}"#; "escaped")]
+#[test]
+fn test_json_line(level: Level, message: &str, expected: &str) {
+    let timestamp = Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+    let line = logger::json_line(
+        &log::Record::builder()
+            .level(level)
+            .target("kalamos::render")
+            .args(format_args!("{}", message))
+            .build(),
+        timestamp,
+    );
+    assert_eq!(line, expected);
+}
@@ -1,5 +1,20 @@
+pub mod cli_error;
+pub mod content;
+pub mod data;
+pub mod deploy;
+pub mod doctor;
+pub mod favicon;
+pub mod git;
+pub mod lang;
+pub mod logger;
 pub mod page;
 pub mod parser;
 pub mod post;
 pub mod render;
+pub mod robots;
+pub mod serve;
+pub mod sitemap;
 pub mod support;
+pub mod url;
+pub mod util;
+pub mod watch;
@@ -1,9 +1,15 @@
-use kalamos::{page, page::PageFile, render::Render};
+use kalamos::{
+    config::TemplatesConfig,
+    page,
+    page::{Page, PageFile},
+    post::{Post, PostFile},
+    render::{ReadOptions, Render},
+};
 use simple_test_case::test_case;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
-use tera::Tera;
+use tera::{Context, Tera};
 
 macro_rules! page_file {
     ($slug:expr, $extension:expr, $filename:expr, $url:expr, $input_path:expr, $output_path:expr ) => {
@@ -73,12 +79,188 @@ fn test_page_from_content(layout: &str, input_path: &Path, content: &str, expect
     tera.add_raw_template("default.html", layout)
         .expect("should be able to add template");
     let page_file = page::PageFile::try_from(input_path.to_path_buf()).expect("should parse");
-    let page = page::Page::from_content(page_file, content).expect("should parse");
+    let page = page::Page::from_content(page_file, content, &tera).expect("should parse");
     let posts = vec![];
-    page.render(&tera, &output_dir, &posts)
+    page.render(&tera, &output_dir, &posts, &Context::new())
         .expect("should render");
     let output_path = output_dir.join("index.html");
     let rendered = fs::read_to_string(&output_path).expect("should read");
 
     assert_eq!(rendered, expected);
 }
+
+#[test]
+fn test_page_slug_override() {
+    let page_file =
+        page::PageFile::try_from(PathBuf::from("pages/blog/about.md")).expect("should parse");
+    let content = "+++\ntitle = \"About\"\nslug = \"About Me\"\n+++\nbody";
+    let page =
+        page::Page::from_content(page_file, content, &Tera::default()).expect("should parse");
+    assert_eq!(page.slug, "about-me");
+    assert_eq!(page.url, PathBuf::from("/blog/about-me.html"));
+    assert_eq!(page.output_path, PathBuf::from("blog/about-me.html"));
+}
+
+#[test_case("+++\ntitle = \"About\"\nnoindex = true\n+++\nbody", true; "noindex set")]
+#[test_case("+++\ntitle = \"About\"\n+++\nbody", false; "noindex unset")]
+#[test]
+fn test_page_noindex(content: &str, expected: bool) {
+    let page_file =
+        page::PageFile::try_from(PathBuf::from("pages/about.md")).expect("should parse");
+    let page =
+        page::Page::from_content(page_file, content, &Tera::default()).expect("should parse");
+    assert_eq!(page.noindex, expected);
+    assert_eq!(
+        page.to_context().get("noindex"),
+        Some(&tera::Value::Bool(expected))
+    );
+}
+
+#[test]
+fn test_page_default_template_from_config() {
+    let page_file =
+        page::PageFile::try_from(PathBuf::from("pages/about.md")).expect("should parse");
+    let options = ReadOptions {
+        templates: TemplatesConfig {
+            post: None,
+            page: Some("page".to_string()),
+            ..TemplatesConfig::default()
+        },
+        ..ReadOptions::default()
+    };
+    let page = page::Page::from_content_with_sidecar(
+        page_file,
+        "+++\ntitle = \"About\"\n+++\nbody",
+        None,
+        &toml::Table::new(),
+        &Tera::default(),
+        &options,
+    )
+    .expect("should parse");
+    assert_eq!(page.template, "page.html");
+}
+
+#[test_case("pages/index.md", true; "root index")]
+#[test_case("pages/blog/index.md", false; "nested index")]
+#[test_case("pages/about.md", false; "other page")]
+#[test]
+fn test_page_context_type(input_path: &str, is_home: bool) {
+    let page_file = page::PageFile::try_from(PathBuf::from(input_path)).expect("should parse");
+    let content = "+++\ntitle = \"Title\"\n+++\nbody";
+    let page =
+        page::Page::from_content(page_file, content, &Tera::default()).expect("should parse");
+    let context = page.to_context();
+    assert_eq!(context.get("is_home"), Some(&tera::Value::Bool(is_home)));
+    assert_eq!(context.get("is_page"), Some(&tera::Value::Bool(true)));
+    assert_eq!(context.get("is_post"), Some(&tera::Value::Bool(false)));
+}
+
+#[test_case("{{ post.title }}", true; "escaped title")]
+#[test_case("{{ post.title | safe }}", false; "unescaped title")]
+#[test]
+fn test_xml_page_is_validated(title: &str, valid: bool) {
+    let tera = Tera::default();
+    let output_dir = env::temp_dir().join(format!("kalamos_test_xml_page_{}", valid));
+    let post_file = PostFile::try_from(PathBuf::from("posts/2024-12-01-rust-and-you.md"))
+        .expect("should parse");
+    let post = Post::from_content(
+        post_file,
+        "+++\ntitle = \"Rust & \\\"You\\\" <3\"\n+++\nbody",
+        &tera,
+    )
+    .expect("should parse");
+    let content = format!(
+        "+++\ntitle = \"Feed\"\n+++\n<feed>{{% for post in posts %}}<entry><title>{}</title><link href=\"{{{{ post.url }}}}\"/></entry>{{% endfor %}}</feed>",
+        title
+    );
+    let page_file =
+        page::PageFile::try_from(PathBuf::from("pages/feed.xml")).expect("should parse");
+    let page = page::Page::from_content(page_file, &content, &tera).expect("should parse");
+
+    let res = page.render(&tera, &output_dir, &[post], &Context::new());
+    assert_eq!(res.is_ok(), valid, "{:?}", res);
+    if valid {
+        let rendered = fs::read_to_string(output_dir.join("feed.xml")).expect("should read");
+        assert_eq!(
+            rendered,
+            "<feed><entry><title>Rust &amp; &quot;You&quot; &lt;3</title><link href=\"/2024/12/rust-and-you.html\"/></entry></feed>"
+        );
+    }
+}
+
+#[test_case("<feed><entry></feed>"; "mismatched tags")]
+#[test_case("<feed>"; "unclosed tag")]
+#[test_case("<feed>Rust & You</feed>"; "unescaped ampersand")]
+#[test_case("<link href=\"/?a=1&b=2\"/>"; "unescaped ampersand in attribute")]
+#[test]
+fn test_validate_invalid_xml(xml: &str) {
+    assert!(kalamos::util::validate_xml(xml).is_err());
+}
+
+#[test_case("pages/about.md"; "markdown")]
+#[test_case("pages/about.html"; "html")]
+#[test]
+fn test_page_missing_title(input_path: &str) {
+    let page_file = page::PageFile::try_from(PathBuf::from(input_path)).expect("should parse");
+    let err = page::Page::from_content(
+        page_file,
+        "+++\nslug = \"about\"\n+++\nbody",
+        &Tera::default(),
+    )
+    .expect_err("should fail");
+    assert!(
+        matches!(err, kalamos::render::Error::MissingField(ref path, ref field) if path == Path::new(input_path) && field == "title"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn test_page_context_matches_post_context() {
+    let page_file = PageFile::try_from(PathBuf::from("pages/about.md")).expect("should parse");
+    let page = Page::from_content(
+        page_file,
+        "+++\ntitle = \"About\"\n+++\nbody",
+        &Tera::default(),
+    )
+    .expect("should parse");
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let post = Post::from_content(
+        post_file,
+        "+++\ntitle = \"First\"\n+++\nbody",
+        &Tera::default(),
+    )
+    .expect("should parse");
+    let page_context = page.to_context().into_json();
+    let post_context = post.to_context().into_json();
+    let post_keys = post_context.as_object().expect("should be an object");
+    for key in page_context
+        .as_object()
+        .expect("should be an object")
+        .keys()
+    {
+        assert!(post_keys.contains_key(key), "posts are missing {}", key);
+    }
+}
+
+#[test]
+fn test_page_navigation() {
+    let page = |name: &str, frontmatter: &str| {
+        let page_file =
+            PageFile::try_from(PathBuf::from(format!("pages/{}.md", name))).expect("should parse");
+        let content = format!("+++\ntitle = \"{}\"\n{}\n+++\nbody", name, frontmatter);
+        Page::from_content(page_file, &content, &Tera::default()).expect("should parse")
+    };
+    let pages = vec![
+        page("zebra", ""),
+        page("contact", "weight = 20"),
+        page("apple", ""),
+        page("home", "weight = -1"),
+        page("about", "weight = 20"),
+    ];
+    let titles = Page::navigation(&pages)
+        .into_iter()
+        .map(|p| p.title)
+        .collect::<Vec<_>>();
+    assert_eq!(titles, vec!["home", "about", "contact", "apple", "zebra"]);
+}
@@ -1,6 +1,10 @@
 //! Tests for the markdown module
-use kalamos::parser;
+use kalamos::{
+    config::{HighlightConfig, MarkdownConfig},
+    parser,
+};
 use simple_test_case::test_case;
+use tera::Tera;
 
 #[test_case("+++\ntitle = \"Hello, world!\"\n+++\n# Hello, world!", ("title = \"Hello, world!\"", "<h1>Hello, world!</h1>\n"); "simple frontmatter and post")]
 #[test_case(
@@ -37,6 +41,7 @@ use simple_test_case::test_case;
     "<h1>Hello, world!</h1>\n");
     "whitespace before frontmatter"
   )]
+#[test_case("+++\r\ntitle = \"Hello, world!\"\r\n+++\r\n# Hello, world!", ("title = \"Hello, world!\"", "<h1>Hello, world!</h1>\n"); "crlf line endings")]
 #[test_case("# Hello, world!", ("", "<h1>Hello, world!</h1>\n"); "no frontmatter")]
 #[test_case("+++\ntitle = \"Hello, world!\"\n+++\n# Hello, world!\n+++\n\ncontinuing", ("title = \"Hello, world!\"", "<h1>Hello, world!</h1>\n<p>+++</p>\n<p>continuing</p>\n"); "multiple plus-plus-plus lines")]
 #[test]
@@ -45,7 +50,7 @@ fn test_parse_with_valid_frontmatter(markdown: &str, expected: (&str, &str)) {
         frontmatter,
         body,
         excerpt,
-    } = parser::parse(markdown).expect("should parse");
+    } = parser::parse(markdown, &Tera::default()).expect("should parse");
     assert_eq!(
         frontmatter,
         toml::from_str(expected.0).expect("should parse frontmatter")
@@ -60,6 +65,211 @@ fn test_parse_with_valid_frontmatter(markdown: &str, expected: (&str, &str)) {
 #[test_case("before the frontmatter\n+++\ntitle = \"Hello, world!\"\n+++\n# Hello, world!\n+++\ncontinuing"; "content before frontmatter")]
 #[test]
 fn test_parse_with_invalid_frontmatter(markdown: &str) {
-    let res = parser::parse(markdown);
+    let res = parser::parse(markdown, &Tera::default());
     assert!(res.is_err());
 }
+
+fn shortcode_templates() -> Tera {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        (
+            "shortcodes/callout.html",
+            r#"<div class="callout {{ type }}">{{ inner | trim }}</div>"#,
+        ),
+        ("shortcodes/signup.html", r#"<form class="signup"></form>"#),
+    ])
+    .expect("should add templates");
+    tera
+}
+
+#[test_case(
+    "Before\n\n{{< callout type=\"warning\" >}}\nBe careful!\n{{< /callout >}}\n\nAfter",
+    "Before\n\n<div class=\"callout warning\">Be careful!</div>\n\nAfter";
+    "shortcode with inner content")]
+#[test_case("{{<signup>}}\n\n{{< signup >}}", "<form class=\"signup\"></form>\n\n<form class=\"signup\"></form>"; "shortcode without closing tag")]
+#[test_case("No shortcodes {{ here }}", "No shortcodes {{ here }}"; "no shortcodes")]
+#[test_case(
+    "Use it like this:\n\n```\n{{< callout type=\"warning\" >}}\nBe careful!\n{{< /callout >}}\n```\n\n{{< signup >}}",
+    "Use it like this:\n\n```\n{{< callout type=\"warning\" >}}\nBe careful!\n{{< /callout >}}\n```\n\n<form class=\"signup\"></form>";
+    "shortcode in a code block")]
+#[test_case("Write `{{< missing >}}` for it", "Write `{{< missing >}}` for it"; "shortcode in inline code")]
+#[test]
+fn test_expand_shortcodes(body: &str, expected: &str) {
+    let expanded =
+        parser::expand_shortcodes(body, &shortcode_templates()).expect("should expand shortcodes");
+    assert_eq!(expanded, expected);
+}
+
+#[test_case(
+    "Before\n\n{{< callout type=\"warning\" >}}\nBe careful!\n{{< /callout >}}\n\nAfter",
+    "Before\n\n\n++++\n<div class=\"callout warning\">Be careful!</div>\n++++\n\n\nAfter";
+    "block shortcode")]
+#[test_case(
+    "Sign up: {{< signup >}} today",
+    "Sign up: pass:[<form class=\"signup\"></form>] today";
+    "inline shortcode")]
+#[test]
+fn test_expand_asciidoc_shortcodes(body: &str, expected: &str) {
+    let expanded = parser::expand_asciidoc_shortcodes(body, &shortcode_templates())
+        .expect("should expand shortcodes");
+    assert_eq!(expanded, expected);
+}
+
+#[test]
+fn test_parse_asciidoc() {
+    // asciidoctor isn't a dependency of kalamos, so this only runs where it is installed
+    if std::process::Command::new("asciidoctor")
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        return;
+    }
+    let content = "+++\ntitle = \"Hello\"\n+++\nBefore\n\n{{< callout type=\"warning\" >}}\nBe careful!\n{{< /callout >}}\n\nSign up: {{< signup >}} today\n";
+    let parsed = parser::parse_file(
+        "adoc",
+        content,
+        &shortcode_templates(),
+        &parser::ParseOptions::default(),
+    )
+    .expect("should parse");
+    assert_eq!(parsed.frontmatter["title"].as_str(), Some("Hello"));
+    assert!(parsed.body.contains("<p>Before</p>"));
+    assert!(parsed
+        .body
+        .contains("<div class=\"callout warning\">Be careful!</div>"));
+    assert!(parsed
+        .body
+        .contains("Sign up: <form class=\"signup\"></form> today"));
+    assert_eq!(parsed.excerpt, None);
+}
+
+#[test_case("{{< missing >}}", parser::Error::UnknownShortcode("missing".to_string()); "unknown shortcode")]
+#[test_case("{{< /callout >}}", parser::Error::UnexpectedClosingShortcode("callout".to_string()); "closing shortcode without opening")]
+#[test]
+fn test_expand_shortcodes_errors(body: &str, expected: parser::Error) {
+    let res = parser::expand_shortcodes(body, &shortcode_templates());
+    assert_eq!(res, Err(expected));
+}
+
+#[test]
+fn test_parse_with_class_highlighting() {
+    let options = parser::ParseOptions {
+        highlight: HighlightConfig {
+            classes: true,
+            class_prefix: "code-".to_string(),
+        },
+        ..parser::ParseOptions::default()
+    };
+    let markdown = "```rust\nfn main() {}\n```\n";
+    let parsed =
+        parser::parse_with_options(markdown, &Tera::default(), &options).expect("should parse");
+    assert!(parsed.body.starts_with("<pre class=\"code-code\"><code>"));
+    assert!(parsed
+        .body
+        .contains("class=\"code-storage code-type code-function code-rust\""));
+    assert!(!parsed.body.contains("style="));
+
+    let css = parser::highlight_css("code-").expect("should generate css");
+    assert!(css.contains(".code-code {"));
+}
+
+#[test]
+fn test_parse_excerpt_with_code_block() {
+    let markdown = "Intro\n\n```rust\nfn main() {}\n```\n<!--more-->\nMore text\n";
+    let parsed = parser::parse(markdown, &Tera::default()).expect("should parse");
+    let excerpt = parsed.excerpt.expect("should have an excerpt");
+    assert!(excerpt.contains("<pre style="));
+    assert!(parsed.body.starts_with(&excerpt));
+    assert!(!excerpt.contains("More text"));
+}
+
+#[test_case("Intro\n<!--more-->\nMore\n", "<p>Intro</p>\n<p>More</p>\n", Some("<p>Intro</p>\n"); "marker")]
+#[test_case("Intro\n<!-- more -->\nMore\n", "<p>Intro</p>\n<p>More</p>\n", Some("<p>Intro</p>\n"); "marker with spaces")]
+#[test_case("Intro <!--more--> More\n", "<p>Intro  More</p>\n", Some("<p>Intro</p>\n"); "inline marker")]
+#[test_case("Intro\n<!-- prettier-ignore -->\nMore\n", "<p>Intro</p>\n<!-- prettier-ignore -->\n<p>More</p>\n", None; "other comments are kept")]
+#[test_case("<!-- keep -->\n\nIntro\n<!--more-->\nMore\n", "<!-- keep -->\n<p>Intro</p>\n<p>More</p>\n", Some("<!-- keep -->\n<p>Intro</p>\n"); "comment and marker")]
+#[test]
+fn test_parse_html_comments(markdown: &str, expected_body: &str, expected_excerpt: Option<&str>) {
+    let parsed = parser::parse(markdown, &Tera::default()).expect("should parse");
+    assert_eq!(parsed.body, expected_body);
+    assert_eq!(parsed.excerpt.as_deref(), expected_excerpt);
+}
+
+#[test_case(true, "<p>“hello” – wait… <code>\"literal\" -- ...</code></p>\n"; "smart punctuation")]
+#[test_case(false, "<p>\"hello\" -- wait... <code>\"literal\" -- ...</code></p>\n"; "straight punctuation")]
+#[test]
+fn test_parse_smart_punctuation(smart_punctuation: bool, expected: &str) {
+    let options = parser::ParseOptions {
+        markdown: MarkdownConfig {
+            smart_punctuation,
+            ..MarkdownConfig::default()
+        },
+        ..parser::ParseOptions::default()
+    };
+    let markdown = "\"hello\" -- wait... `\"literal\" -- ...`\n";
+    let parsed =
+        parser::parse_with_options(markdown, &Tera::default(), &options).expect("should parse");
+    assert_eq!(parsed.body, expected);
+}
+
+#[test_case(true, "$a_1 < b$", "<p><span class=\"math inline\">\\(a_1 &lt; b\\)</span></p>\n"; "inline math")]
+#[test_case(true, "$$x^2 *y* z$$", "<p><span class=\"math display\">\\[x^2 *y* z\\]</span></p>\n"; "display math")]
+#[test_case(false, "$a_1$ and $b_1$", "<p>$a_1$ and $b_1$</p>\n"; "math off")]
+#[test]
+fn test_parse_math(math: bool, markdown: &str, expected: &str) {
+    let options = parser::ParseOptions {
+        markdown: MarkdownConfig {
+            math,
+            ..MarkdownConfig::default()
+        },
+        ..parser::ParseOptions::default()
+    };
+    let parsed =
+        parser::parse_with_options(markdown, &Tera::default(), &options).expect("should parse");
+    assert_eq!(parsed.body, expected);
+}
+
+#[test_case(None, "Intro\n\n<!--more-->\n\nRest\n", "<p>Intro</p>\n<p>Rest</p>\n", Some("<p>Intro</p>\n"); "default marker")]
+#[test_case(Some("---cut---"), "Intro\n\n---cut---\n\nRest\n", "<p>Intro</p>\n<p>Rest</p>\n", Some("<p>Intro</p>\n"); "custom separator")]
+#[test_case(Some("---cut---"), "Intro\n\n<!--more-->\n\nRest\n", "<p>Intro</p>\n<p>Rest</p>\n", None; "custom separator ignores the default marker")]
+#[test]
+fn test_parse_excerpt_separator(
+    excerpt_separator: Option<&str>,
+    markdown: &str,
+    expected_body: &str,
+    expected_excerpt: Option<&str>,
+) {
+    let options = parser::ParseOptions {
+        markdown: MarkdownConfig {
+            excerpt_separator: excerpt_separator.map(String::from),
+            ..MarkdownConfig::default()
+        },
+        ..parser::ParseOptions::default()
+    };
+    let parsed =
+        parser::parse_with_options(markdown, &Tera::default(), &options).expect("should parse");
+    assert_eq!(parsed.body, expected_body);
+    assert_eq!(parsed.excerpt.as_deref(), expected_excerpt);
+}
+
+#[test_case("md", Some(&["md", "markdown"][..]); "markdown")]
+#[test_case("adoc", Some(&["adoc", "asciidoc"][..]); "asciidoc")]
+#[test_case("html", None; "template")]
+#[test]
+fn test_parser_for_extension(extension: &str, expected: Option<&[&str]>) {
+    let parser = parser::parser_for_extension(extension);
+    assert_eq!(parser.map(|p| p.extensions()), expected);
+}
+
+#[test]
+fn test_parse_file_as_markdown() {
+    let parsed = parser::parse_file(
+        "md",
+        "+++\ntitle = \"Hello\"\n+++\n# Hello",
+        &Tera::default(),
+        &parser::ParseOptions::default(),
+    )
+    .expect("should parse");
+    assert_eq!(parsed.body, "<h1>Hello</h1>\n");
+}
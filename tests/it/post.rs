@@ -1,13 +1,16 @@
 use chrono::NaiveDate;
+use insta::assert_yaml_snapshot;
 use kalamos::{
+    config::{FieldType, FrontmatterSchema, SchemaConfig, TemplatesConfig},
     post::{Post, PostFile},
-    render::Render,
+    render::{Error as RenderError, ReadOptions, Render},
 };
 use simple_test_case::test_case;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
-use tera::Tera;
+use tera::{Context, Tera};
 
 macro_rules! post_file {
     ($date:expr, $slug:expr, $extension:expr, $filename:expr, $url:expr, $input_path:expr, $output_path:expr ) => {
@@ -59,12 +62,508 @@ fn test_post_from_content(layout: &str, input_path: &Path, content: &str, expect
     tera.add_raw_template("post.html", layout)
         .expect("should be able to add template");
     let page_file = PostFile::try_from(input_path.to_path_buf()).expect("should parse");
-    let page = Post::from_content(page_file, content).expect("should parse");
+    let page = Post::from_content(page_file, content, &tera).expect("should parse");
     let posts = vec![];
-    page.render(&tera, &output_dir, &posts)
+    page.render(&tera, &output_dir, &posts, &Context::new())
         .expect("should render");
     let output_path = output_dir.join("2024/12/first.html");
     let rendered = fs::read_to_string(&output_path).expect("should read");
 
     assert_eq!(rendered, expected);
 }
+
+#[test_case("+++\ntitle = \"First Post\"\n+++\nbody", "2024-12-01T00:00:00+00:00", "2024-12-01"; "date from file name")]
+#[test_case("+++\ntitle = \"First Post\"\ndate = 2024-12-02\n+++\nbody", "2024-12-02T00:00:00+00:00", "2024-12-02"; "date in frontmatter")]
+#[test_case("+++\ntitle = \"First Post\"\ndate = 2024-12-01T09:30:00\n+++\nbody", "2024-12-01T09:30:00+00:00", "2024-12-01"; "local datetime in frontmatter")]
+#[test_case("+++\ntitle = \"First Post\"\ndate = 2024-12-01T21:00:00-05:00\n+++\nbody", "2024-12-01T21:00:00-05:00", "2024-12-01"; "datetime with offset in frontmatter")]
+#[test]
+fn test_post_datetime(content: &str, expected_datetime: &str, expected_date_str: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let post = Post::from_content(post_file, content, &Tera::default()).expect("should parse");
+    assert_eq!(post.datetime.to_rfc3339(), expected_datetime);
+    assert_eq!(post.date_str, expected_date_str);
+}
+
+#[test_case("", "/2023/12/first.html"; "date from file name")]
+#[test_case("date = 2024-01-01", "/2024/01/first.html"; "date in frontmatter")]
+#[test_case("date = 2024-01-01\nslug = \"renamed\"", "/2024/01/renamed.html"; "date and slug in frontmatter")]
+#[test]
+fn test_post_url_uses_frontmatter_date(frontmatter: &str, expected_url: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2023-12-31-first.md")).expect("should parse");
+    let content = format!("+++\ntitle = \"First Post\"\n{}\n+++\nbody", frontmatter);
+    let post = Post::from_content(post_file, &content, &Tera::default()).expect("should parse");
+    assert_eq!(post.url, PathBuf::from(expected_url));
+    assert_eq!(
+        post.output_path,
+        PathBuf::from(expected_url.trim_start_matches('/'))
+    );
+    assert_eq!(post.date.format("%Y").to_string(), &expected_url[1..5]);
+}
+
+#[test_case(vec![("posts/2024-12-01-a.md", ""), ("posts/2024-12-02-b.md", "")], vec!["b", "a"]; "dates from file names")]
+#[test_case(vec![("posts/2024-12-01-a.md", "date = 2025-06-01"), ("posts/2024-12-02-b.md", "")], vec!["a", "b"]; "frontmatter date is newer than the file name")]
+#[test_case(vec![("posts/2024-12-01-a.md", "date = 2024-12-01T09:00:00-05:00"), ("posts/2024-12-01-b.md", "date = 2024-12-01T12:00:00Z")], vec!["a", "b"]; "times with offsets")]
+#[test_case(vec![("posts/2024-12-01-a.md", ""), ("posts/2024-12-01-b.md", "")], vec!["b", "a"]; "same date")]
+#[test]
+fn test_sort_newest_first(posts: Vec<(&str, &str)>, expected: Vec<&str>) {
+    let mut posts = posts
+        .into_iter()
+        .map(|(input_path, frontmatter)| {
+            let post_file = PostFile::try_from(PathBuf::from(input_path)).expect("should parse");
+            let content = format!("+++\ntitle = \"Post\"\n{}\n+++\nbody", frontmatter);
+            Post::from_content(post_file, &content, &Tera::default()).expect("should parse")
+        })
+        .collect::<Vec<_>>();
+    Post::sort_newest_first(&mut posts);
+    let slugs = posts.iter().map(|p| p.slug.as_str()).collect::<Vec<_>>();
+    assert_eq!(slugs, expected);
+}
+
+fn post_with_tags(input_path: &str, tags: &str) -> Post {
+    let post_file = PostFile::try_from(PathBuf::from(input_path)).expect("should parse");
+    let content = format!(
+        "+++\ntitle = \"{}\"\ntags = {}\n+++\nbody",
+        input_path, tags
+    );
+    Post::from_content(post_file, &content, &Tera::default()).expect("should parse")
+}
+
+#[test]
+fn test_set_related_posts() {
+    let mut posts = vec![
+        post_with_tags("posts/2024-12-04-rust.md", r#"["rust", "web"]"#),
+        post_with_tags("posts/2024-12-03-cooking.md", r#"["food"]"#),
+        post_with_tags(
+            "posts/2024-12-02-tera.md",
+            r#"["rust", "web", "templates"]"#,
+        ),
+        post_with_tags("posts/2024-12-01-cargo.md", r#"["rust"]"#),
+        post_with_tags("posts/2024-11-30-clippy.md", r#"["rust"]"#),
+    ];
+    Post::set_related_posts(&mut posts, 2);
+
+    let related_slugs = |post: &Post| {
+        post.related
+            .iter()
+            .map(|p| p.slug.clone())
+            .collect::<Vec<_>>()
+    };
+    // tera shares two tags, and cargo is more recent than clippy
+    assert_eq!(related_slugs(&posts[0]), vec!["tera", "cargo"]);
+    assert!(related_slugs(&posts[1]).is_empty());
+    assert_eq!(related_slugs(&posts[3]), vec!["rust", "tera"]);
+}
+
+fn post_in_series(input_path: &str, series: &str) -> Post {
+    let post_file = PostFile::try_from(PathBuf::from(input_path)).expect("should parse");
+    let content = format!("+++\ntitle = \"{}\"\n{}\n+++\nbody", input_path, series);
+    Post::from_content(post_file, &content, &Tera::default()).expect("should parse")
+}
+
+#[test]
+fn test_set_series() {
+    let mut posts = vec![
+        post_in_series(
+            "posts/2024-12-04-intro.md",
+            "series = \"rust\"\nseries_order = 1",
+        ),
+        post_in_series("posts/2024-12-03-cooking.md", ""),
+        post_in_series("posts/2024-12-02-appendix.md", "series = \"rust\""),
+        post_in_series(
+            "posts/2024-12-01-setup.md",
+            "series = \"rust\"\nseries_order = 2",
+        ),
+        post_in_series("posts/2024-11-30-other.md", "series = \"other\""),
+    ];
+    Post::set_series(&mut posts);
+
+    let position = |i: usize| {
+        let position = posts[i]
+            .series_position
+            .clone()
+            .expect("should be in a series");
+        (
+            position.index,
+            position.total,
+            position.prev.map(|p| p.slug),
+            position.next.map(|p| p.slug),
+        )
+    };
+    // series_order comes first, then posts without one by date
+    assert_eq!(position(0), (1, 3, None, Some("setup".to_string())));
+    assert_eq!(
+        position(3),
+        (
+            2,
+            3,
+            Some("intro".to_string()),
+            Some("appendix".to_string())
+        )
+    );
+    assert_eq!(position(2), (3, 3, Some("setup".to_string()), None));
+    assert_eq!(position(4), (1, 1, None, None));
+    assert_eq!(posts[1].series_position, None);
+
+    let context = posts[3].to_context();
+    assert_eq!(context.get("series"), Some(&tera::Value::from("rust")));
+    assert_eq!(context.get("series_index"), Some(&tera::Value::from(2)));
+    assert_eq!(context.get("series_total"), Some(&tera::Value::from(3)));
+    assert_eq!(
+        context.get("series_next").and_then(|n| n.get("slug")),
+        Some(&tera::Value::from("appendix"))
+    );
+}
+
+#[test_case("Hello World", "/2024/12/hello-world.html"; "spaces")]
+#[test_case("Ça va?", "/2024/12/a-va.html"; "non-ascii characters")]
+#[test_case("already-a_slug", "/2024/12/already-a_slug.html"; "already a slug")]
+#[test]
+fn test_post_slug_override(slug: &str, expected_url: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = format!(
+        "+++\ntitle = \"First Post\"\nslug = \"{}\"\n+++\nbody",
+        slug
+    );
+    let post = Post::from_content(post_file, &content, &Tera::default()).expect("should parse");
+    assert_eq!(post.url, PathBuf::from(expected_url));
+    assert_eq!(
+        post.output_path,
+        PathBuf::from(expected_url.trim_start_matches('/'))
+    );
+}
+
+#[test]
+fn test_post_invalid_slug_override() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = "+++\ntitle = \"First Post\"\nslug = \"???\"\n+++\nbody";
+    let res = Post::from_content(post_file, content, &Tera::default());
+    assert!(matches!(res, Err(RenderError::InvalidSlug(_))));
+}
+
+#[test]
+fn test_post_unknown_shortcode() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = "+++\ntitle = \"First Post\"\n+++\n{{< missing >}}";
+    let res = Post::from_content(post_file, content, &Tera::default());
+    assert!(
+        matches!(res, Err(RenderError::Markdown(path, _)) if path == Path::new("posts/2024-12-01-first.md"))
+    );
+}
+
+#[test_case("permalink = \"/about-us.html\"", "/about-us.html", "about-us.html"; "permalink")]
+#[test_case("path = \"company/about/\"", "/company/about/index.html", "company/about/index.html"; "path ending in a slash")]
+#[test]
+fn test_post_permalink_override(frontmatter: &str, expected_url: &str, expected_output_path: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = format!("+++\ntitle = \"First Post\"\n{}\n+++\nbody", frontmatter);
+    let post = Post::from_content(post_file, &content, &Tera::default()).expect("should parse");
+    assert_eq!(post.url, PathBuf::from(expected_url));
+    assert_eq!(post.output_path, PathBuf::from(expected_output_path));
+    // the slug still comes from the file name
+    assert_eq!(post.slug, "first");
+}
+
+#[test_case("/../outside.html"; "parent directory")]
+#[test_case(""; "empty")]
+#[test]
+fn test_post_invalid_permalink_override(permalink: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = format!(
+        "+++\ntitle = \"First Post\"\npermalink = \"{}\"\n+++\nbody",
+        permalink
+    );
+    let res = Post::from_content(post_file, &content, &Tera::default());
+    assert!(matches!(res, Err(RenderError::InvalidPermalink(_))));
+}
+
+#[test]
+fn test_post_from_content_with_sidecar() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = "+++\ntitle = \"Inline Title\"\ntags = [\"inline\"]\n+++\nbody";
+    let sidecar = r#"{ "title": "Sidecar Title", "date": "2024-12-01T09:00:00-05:00" }"#;
+    let post = Post::from_content_with_sidecar(
+        post_file,
+        content,
+        Some(sidecar),
+        &toml::Table::new(),
+        &Tera::default(),
+        &ReadOptions::default(),
+    )
+    .expect("should parse");
+    // keys in the sidecar take precedence, and keys only in the file are kept
+    assert_eq!(post.title, "Sidecar Title");
+    assert_eq!(post.tags, vec!["inline"]);
+    assert_eq!(post.datetime.to_rfc3339(), "2024-12-01T09:00:00-05:00");
+}
+
+#[test_case("", None, "post.html"; "default")]
+#[test_case("", Some("article"), "article.html"; "from config")]
+#[test_case("template = \"custom\"\n", Some("article"), "custom.html"; "from frontmatter")]
+#[test]
+fn test_post_default_template(frontmatter: &str, configured: Option<&str>, expected: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-post.md")).expect("should parse");
+    let content = format!("+++\ntitle = \"Post\"\n{}+++\nbody", frontmatter);
+    let options = ReadOptions {
+        templates: TemplatesConfig {
+            post: configured.map(|t| t.to_string()),
+            page: None,
+            ..TemplatesConfig::default()
+        },
+        ..ReadOptions::default()
+    };
+    let post = Post::from_content_with_sidecar(
+        post_file,
+        &content,
+        None,
+        &toml::Table::new(),
+        &Tera::default(),
+        &options,
+    )
+    .expect("should parse");
+    assert_eq!(post.template, expected);
+}
+
+#[test]
+fn test_post_context_type() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-index.md")).expect("should parse");
+    let content = "+++\ntitle = \"Index\"\n+++\nbody";
+    let post = Post::from_content(post_file, content, &Tera::default()).expect("should parse");
+    let context = post.to_context();
+    assert_eq!(context.get("is_post"), Some(&tera::Value::Bool(true)));
+    assert_eq!(context.get("is_page"), Some(&tera::Value::Bool(false)));
+    assert_eq!(context.get("is_home"), Some(&tera::Value::Bool(false)));
+}
+
+#[test]
+fn test_post_read_from_directory_with_sidecar() {
+    let root_dir = Path::new("tests/it/testdata/sidecar_site");
+    let mut posts = Post::read_from_directory(root_dir, &Tera::default()).expect("should read");
+    posts.sort();
+    let titles = posts
+        .iter()
+        .map(|p| (p.title.as_str(), p.slug.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        titles,
+        vec![("Sidecar Title", "from-sidecar"), ("No Sidecar", "second")]
+    );
+}
+
+#[test]
+fn test_post_invalid_sidecar() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = "+++\ntitle = \"Inline Title\"\n+++\nbody";
+    let res = Post::from_content_with_sidecar(
+        post_file,
+        content,
+        Some("[1, 2]"),
+        &toml::Table::new(),
+        &Tera::default(),
+        &ReadOptions::default(),
+    );
+    assert!(matches!(res, Err(RenderError::Markdown(_, _))));
+}
+
+#[test]
+fn test_post_read_from_directory_with_defaults() {
+    let root_dir = Path::new("tests/it/testdata/defaults_site");
+    let mut posts = Post::read_from_directory(root_dir, &Tera::default()).expect("should read");
+    posts.sort();
+    let templates = posts
+        .iter()
+        .map(|p| (p.slug.as_str(), p.template.as_str(), p.tags.clone()))
+        .collect::<Vec<_>>();
+    // defaults cascade from posts/ to posts/tutorials/, and the frontmatter takes precedence
+    assert_eq!(
+        templates,
+        vec![
+            ("news", "post.html", vec!["blog".to_string()]),
+            ("intro", "tutorial.html", vec!["blog".to_string()]),
+            ("custom", "custom.html", vec!["blog".to_string()]),
+        ]
+    );
+}
+
+#[test_case("posts/2024-12-01-trip/index.md", Ok(("trip", "2024/12/trip.html")); "bundle")]
+#[test_case("posts/2024-12-01-trip.md", Ok(("trip", "2024/12/trip.html")); "single file")]
+#[test_case("posts/trips/index.md", Err(()); "index without a date")]
+#[test]
+fn test_post_file_from_bundle(path: &str, expected: Result<(&str, &str), ()>) {
+    let post_file = PostFile::try_from(PathBuf::from(path));
+    let post_file = post_file
+        .as_ref()
+        .map(|f| (f.slug.as_str(), f.output_path.to_str().unwrap_or_default()))
+        .map_err(|_| ());
+    assert_eq!(post_file, expected);
+}
+
+#[test_case("+++\ndate = \"2024-12-01\"\n+++\nbody"; "frontmatter without title")]
+#[test_case("body"; "no frontmatter")]
+#[test]
+fn test_post_missing_title(content: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-foo.md")).expect("should parse");
+    let err = Post::from_content(post_file, content, &Tera::default()).expect_err("should fail");
+    assert_eq!(
+        err.to_string(),
+        "missing required field `title` in posts/2024-12-01-foo.md"
+    );
+}
+
+#[test]
+fn test_post_context() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = "+++\ntitle = \"First Post\"\ntags = [\"rust\"]\nseries = \"intro\"\n+++\nexcerpt\n<!--more-->\nbody";
+    let mut posts =
+        vec![Post::from_content(post_file, content, &Tera::default()).expect("should parse")];
+    Post::set_series(&mut posts);
+    assert_yaml_snapshot!(posts[0].to_context().into_json());
+}
+
+#[test]
+fn test_post_anchor() {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-first.md")).expect("should parse");
+    let content = "+++\ntitle = \"First\"\nslug = \"2024: A Year\"\n+++\nbody";
+    let post = Post::from_content(post_file, content, &Tera::default()).expect("should parse");
+    assert_eq!(post.slug, "2024-a-year");
+    assert_eq!(post.anchor, "post-2024-a-year");
+}
+
+#[test_case("author = \"Ann\"\nrating = 5\n", false, None; "valid")]
+#[test_case("rating = 5\n", false, None; "violations are warnings")]
+#[test_case("rating = 5\n", true, Some("frontmatter of posts/2024-12-01-post.md doesn't match the schema: `author` is required"); "missing field")]
+#[test_case("author = \"Ann\"\nrating = \"five\"\n", true, Some("frontmatter of posts/2024-12-01-post.md doesn't match the schema: `rating` should be of type integer, not string"); "wrong type")]
+#[test]
+fn test_post_schema(frontmatter: &str, strict: bool, expected: Option<&str>) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-post.md")).expect("should parse");
+    let content = format!("+++\ntitle = \"Post\"\n{}+++\nbody", frontmatter);
+    let schema = FrontmatterSchema {
+        required: vec!["author".to_string()],
+        types: BTreeMap::from([
+            ("author".to_string(), FieldType::String),
+            ("rating".to_string(), FieldType::Integer),
+        ]),
+    };
+    let options = ReadOptions {
+        schema: SchemaConfig {
+            post: schema,
+            ..SchemaConfig::default()
+        },
+        strict,
+        ..ReadOptions::default()
+    };
+    let result = Post::from_content_with_sidecar(
+        post_file,
+        &content,
+        None,
+        &toml::Table::new(),
+        &Tera::default(),
+        &options,
+    );
+    assert_eq!(result.err().map(|e| e.to_string()).as_deref(), expected);
+}
+
+#[test_case("", "<p>Intro</p>\n"; "from the marker")]
+#[test_case("excerpt = \"A *short* summary\"\n", "<p>A <em>short</em> summary</p>\n"; "from the frontmatter")]
+#[test]
+fn test_post_excerpt(frontmatter: &str, expected: &str) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-post.md")).expect("should parse");
+    let content = format!(
+        "+++\ntitle = \"Post\"\n{}+++\nIntro\n\n<!--more-->\n\nRest\n",
+        frontmatter
+    );
+    let post = Post::from_content(post_file, &content, &Tera::default()).expect("should parse");
+    assert_eq!(post.excerpt, expected);
+    assert_eq!(post.content, "<p>Intro</p>\n<p>Rest</p>\n");
+}
+
+#[test]
+fn test_featured() {
+    let post = |file: &str, frontmatter: &str| {
+        let post_file = PostFile::try_from(PathBuf::from(file)).expect("should parse");
+        let content = format!("+++\ntitle = \"{}\"\n{}+++\nbody", file, frontmatter);
+        Post::from_content(post_file, &content, &Tera::default()).expect("should parse")
+    };
+    let mut posts = vec![
+        post("posts/2024-01-01-a.md", "featured = true\n"),
+        post("posts/2024-02-01-b.md", ""),
+        post("posts/2024-03-01-c.md", "featured = true\n"),
+        post(
+            "posts/2024-04-01-d.md",
+            "featured = true\nfeatured_order = 2\n",
+        ),
+        post(
+            "posts/2024-05-01-e.md",
+            "featured = true\nfeatured_order = 1\n",
+        ),
+    ];
+    Post::sort_newest_first(&mut posts);
+    let featured = Post::featured(&posts)
+        .iter()
+        .map(|p| p.slug.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(featured, vec!["e", "d", "c", "a"]);
+    // b isn't featured
+    assert!(Post::featured(&posts[3..4]).is_empty());
+}
+
+#[test]
+fn test_by_year() {
+    let post = |file: &str| {
+        let post_file = PostFile::try_from(PathBuf::from(file)).expect("should parse");
+        Post::from_content(post_file, "+++\ntitle = \"t\"\n+++\nbody", &Tera::default())
+            .expect("should parse")
+    };
+    let mut posts = vec![
+        post("posts/2023-05-01-a.md"),
+        post("posts/2024-01-01-b.md"),
+        post("posts/2023-11-01-c.md"),
+        post("posts/2024-03-01-d.md"),
+    ];
+    Post::sort_newest_first(&mut posts);
+    let by_year = Post::by_year(&posts)
+        .iter()
+        .map(|y| {
+            (
+                y.year,
+                y.posts.iter().map(|p| p.slug.as_str()).collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        by_year,
+        vec![(2024, vec!["d", "b"]), (2023, vec!["c", "a"])]
+    );
+}
+
+#[test_case("", "Intro\n\n<!--more-->\n\nRest\n", true; "marker")]
+#[test_case("", "Intro\n\nRest\n", false; "no marker")]
+#[test_case("", "Intro\n\n<!--more-->\n", false; "marker at the end")]
+#[test_case("excerpt = \"A summary\"\n", "Intro\n\nRest\n", true; "frontmatter excerpt")]
+#[test]
+fn test_post_has_more(frontmatter: &str, body: &str, expected: bool) {
+    let post_file =
+        PostFile::try_from(PathBuf::from("posts/2024-12-01-post.md")).expect("should parse");
+    let content = format!("+++\ntitle = \"Post\"\n{}+++\n{}", frontmatter, body);
+    let post = Post::from_content(post_file, &content, &Tera::default()).expect("should parse");
+    assert_eq!(post.has_more, expected);
+    assert_eq!(
+        post.to_context().get("has_more"),
+        Some(&tera::Value::Bool(expected))
+    );
+}
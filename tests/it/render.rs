@@ -1,15 +1,931 @@
 use crate::support;
+use chrono::NaiveDate;
 use insta::assert_yaml_snapshot;
-use kalamos::render;
-use std::{env, fs, path::Path};
+use kalamos::{
+    config::TemplatesConfig,
+    page::Page,
+    post::Post,
+    render::{self, Render},
+    util,
+};
+use simple_test_case::test_case;
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 #[test]
 fn test_render_dir() {
     let temp_dir = env::temp_dir();
     let root_dir = Path::new("tests/it/testdata/simple_site");
     let output_dir = temp_dir.join("kalamos_test_output");
+    let options = render::RenderOptions {
+        clean: true,
+        ..render::RenderOptions::default()
+    };
+    render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    let output_content = support::dir_to_yaml(&output_dir, &[]).expect("should generate yaml");
+    assert_yaml_snapshot!(output_content);
+}
+
+#[test]
+fn test_clean_output_dir() {
+    let root_dir = Path::new("tests/it/testdata/simple_site");
+    let output_dir = env::temp_dir().join("kalamos_test_clean_output");
     let _ = fs::remove_dir_all(&output_dir);
+    fs::create_dir_all(output_dir.join("2023/01")).expect("should create dir");
+    fs::write(output_dir.join("2023/01/deleted.html"), "stale").expect("should write");
+
+    let written = render::render_dir(root_dir, &output_dir).expect("should render");
+    let removed = render::clean_output_dir(root_dir, &output_dir, &written).expect("should clean");
+
+    assert_eq!(removed, vec![Path::new("2023/01/deleted.html")]);
+    assert!(!output_dir.join("2023").exists());
+    assert!(output_dir.join("index.html").exists());
+}
+
+#[test]
+fn test_clean_output_dir_refuses_to_clean_input_dir() {
+    let root_dir = Path::new("tests/it/testdata/simple_site");
+    let res = render::clean_output_dir(root_dir, root_dir, &[]);
+    assert!(matches!(res, Err(render::Error::Clean(_, _))));
+    assert!(root_dir.join("pages/index.md").exists());
+}
+
+#[test]
+fn test_dir_to_yaml_ignore_patterns() {
+    let root_dir = Path::new("tests/it/testdata/simple_site");
+    let output_content =
+        support::dir_to_yaml(root_dir, &[r"^posts/", r"\.css$"]).expect("should generate yaml");
+    let paths = output_content.keys().collect::<Vec<_>>();
+    assert!(paths.contains(&&Path::new("pages/index.md").to_path_buf()));
+    assert!(!paths.iter().any(|p| p.starts_with("posts")));
+    assert!(!paths.iter().any(|p| p.extension() == Some("css".as_ref())));
+}
+
+#[test]
+fn test_render_dir_creates_output_dir() {
+    let root_dir = Path::new("tests/it/testdata/simple_site");
+    let output_dir = env::temp_dir().join("kalamos_test_missing_output/nested");
+    let _ = fs::remove_dir_all(output_dir.parent().expect("should have parent"));
     render::render_dir(root_dir, &output_dir).expect("should render");
-    let output_content = support::dir_to_yaml(&output_dir).expect("should generate yaml");
-    assert_yaml_snapshot!(output_content);
+    assert!(output_dir.join("index.html").exists());
+}
+
+#[test]
+fn test_render_dir_with_flat_root_pages() {
+    let root_dir = Path::new("tests/it/testdata/flat_site");
+    let output_dir = env::temp_dir().join("kalamos_test_flat_root_output");
+    let options = render::RenderOptions {
+        clean: true,
+        ..render::RenderOptions::default()
+    };
+    let mut written = render::render_dir_with_options(root_dir, &output_dir, &options)
+        .expect("should render")
+        .written;
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            Path::new("blog/about.html"),
+            Path::new("index.html"),
+            Path::new("style.css")
+        ]
+    );
+    let index = fs::read_to_string(output_dir.join("index.html")).expect("should read");
+    assert_eq!(
+        index,
+        "<h1>Home Page</h1>\n<p>This is my home page.</p>\n\n"
+    );
+}
+
+#[test]
+fn test_read_from_missing_directories() {
+    // This site doesn't have posts or pages directories
+    let root_dir = Path::new("tests/it/testdata/flat_site");
+    let templates = render::load_templates(root_dir, &TemplatesConfig::default())
+        .expect("should load templates");
+    let posts = Post::read_from_directory(root_dir, &templates).expect("should read posts");
+    assert!(posts.is_empty());
+    let pages = Page::read_from_directory(root_dir, &templates).expect("should read pages");
+    assert!(pages.is_empty());
+}
+
+#[test]
+fn test_render_dir_without_posts_or_static_directories() {
+    let root_dir = Path::new("tests/it/testdata/minimal_site");
+    let output_dir = env::temp_dir().join("kalamos_test_minimal_output");
+    let written = render::render_dir(root_dir, &output_dir).expect("should render");
+    assert_eq!(written, vec![Path::new("index.html")]);
+}
+
+#[test]
+fn test_render_dir_reports_missing_images() {
+    let root_dir = Path::new("tests/it/testdata/missing_image_site");
+    let output_dir = env::temp_dir().join("kalamos_test_missing_image_output");
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &render::RenderOptions::default())
+            .expect("should render");
+    assert_eq!(
+        report.warnings,
+        vec!["missing image: \"pages/blog/index.html\" uses \"missing.png\""]
+    );
+
+    let options = render::RenderOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let err = render::render_dir_with_options(root_dir, &output_dir, &options)
+        .expect_err("should not render");
+    match err {
+        render::Error::MissingImages(missing) => assert_eq!(
+            missing,
+            vec![(
+                Path::new("pages/blog/index.html").to_path_buf(),
+                "missing.png".to_string()
+            )]
+        ),
+        e => panic!("unexpected error: {e:?}"),
+    }
+}
+
+#[test]
+fn test_render_dir_with_options_report() {
+    let root_dir = Path::new("tests/it/testdata/flat_site");
+    let output_dir = env::temp_dir().join("kalamos_test_report_output");
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &render::RenderOptions::default())
+            .expect("should render");
+    assert_eq!(report.version, render::RENDER_REPORT_VERSION);
+    assert_eq!(report.stats.posts, 0);
+    assert_eq!(report.stats.pages, 2);
+    assert_eq!(report.stats.files_written, report.written.len());
+    let timings = &report.stats.timings;
+    assert!(
+        timings.templates_ms
+            + timings.posts_ms
+            + timings.pages_ms
+            + timings.render_ms
+            + timings.copy_ms
+            <= report.stats.duration_ms
+    );
+
+    let json = serde_json::to_value(&report).expect("should serialize");
+    let mut rendered = json["rendered"]
+        .as_array()
+        .expect("should be an array")
+        .iter()
+        .map(|r| {
+            (
+                r["kind"].as_str(),
+                r["input_path"].as_str(),
+                r["output_path"].as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    rendered.sort();
+    assert_eq!(
+        rendered,
+        vec![
+            (Some("page"), Some("blog/about.md"), Some("blog/about.html")),
+            (Some("page"), Some("index.md"), Some("index.html")),
+        ]
+    );
+    assert_eq!(json["warnings"], serde_json::json!([]));
+}
+
+#[test]
+fn test_render_dir_multilingual() {
+    let root_dir = Path::new("tests/it/testdata/multilingual_site");
+    let output_dir = env::temp_dir().join("kalamos_test_multilingual_output");
+    let mut written = render::render_dir(root_dir, &output_dir).expect("should render");
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            Path::new("en/2024/12/hello.html"),
+            Path::new("en/about.html"),
+            Path::new("es/2024/12/hello.html"),
+            Path::new("es/about.html"),
+            Path::new("es/index.html"),
+            Path::new("index.html"),
+        ]
+    );
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(
+        read("es/2024/12/hello.html").trim(),
+        "es: Hola [en /en/2024/12/hello.html]"
+    );
+    assert_eq!(
+        read("en/about.html").trim(),
+        "en: About [es /es/about.html]"
+    );
+    assert_eq!(read("index.html").trim(), ": Home (home)");
+    // the index of a language directory is the home page of that language
+    assert_eq!(read("es/index.html").trim(), "es: Inicio (home)");
+}
+
+#[test]
+fn test_render_dir_with_and_without_drafts() {
+    let root_dir = Path::new("tests/it/testdata/drafts_site");
+    let output_dir = env::temp_dir().join("kalamos_test_drafts_output");
+    let preview_dir = env::temp_dir().join("kalamos_test_drafts_preview");
+    let _ = fs::remove_dir_all(&output_dir);
+    let _ = fs::remove_dir_all(&preview_dir);
+
+    let mut written = render::render_dir(root_dir, &output_dir).expect("should render");
+    written.sort();
+    assert_eq!(
+        written,
+        vec![Path::new("2024/12/published.html"), Path::new("index.html")]
+    );
+    let index = fs::read_to_string(output_dir.join("index.html")).expect("should read");
+    assert_eq!(index.trim(), "Published;");
+
+    let options = render::RenderOptions {
+        drafts: true,
+        ..render::RenderOptions::default()
+    };
+    let mut written = render::render_dir_with_options(root_dir, &preview_dir, &options)
+        .expect("should render")
+        .written;
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            Path::new("2024/12/published.html"),
+            Path::new("2024/12/upcoming.html"),
+            Path::new("index.html"),
+            Path::new("secret.html"),
+        ]
+    );
+    let index = fs::read_to_string(preview_dir.join("index.html")).expect("should read");
+    assert_eq!(index.trim(), "PREVIEW:Upcoming;Published;");
+    assert!(!output_dir.join("2024/12/upcoming.html").exists());
+}
+
+#[test]
+fn test_render_dir_duplicate_slugs() {
+    let root_dir = Path::new("tests/it/testdata/duplicate_slug_site");
+    let output_dir = env::temp_dir().join("kalamos_test_duplicate_slug_output");
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &render::RenderOptions::default())
+            .expect("should render with a warning");
+    assert_eq!(
+        report.warnings,
+        vec![
+            r#"duplicate slug: "hello" is used by both "posts/2024-02-01-hello.md" and "posts/2024-01-01-hello.md""#
+        ]
+    );
+
+    let options = render::RenderOptions {
+        strict: true,
+        ..render::RenderOptions::default()
+    };
+    match render::render_dir_with_options(root_dir, &output_dir, &options) {
+        Err(render::Error::DuplicateSlug(slug, _, _)) => assert_eq!(slug, "hello"),
+        r => panic!("unexpected result: {r:?}"),
+    }
+}
+
+#[test]
+fn test_load_templates_from_configured_dir() {
+    let root_dir = Path::new("tests/it/testdata/custom_templates_site");
+    let config = TemplatesConfig {
+        dir: "themes/mytheme/templates".to_string(),
+        extensions: vec!["html".to_string(), "tera".to_string()],
+        ..TemplatesConfig::default()
+    };
+    let templates = render::load_templates(root_dir, &config).expect("should load templates");
+    let mut names = templates.get_template_names().collect::<Vec<_>>();
+    names.sort();
+    // post.tera is also available as post.html, but page.tera isn't, as there is a page.html
+    assert_eq!(
+        names,
+        vec![
+            "base.html",
+            "base.tera",
+            "page.html",
+            "page.tera",
+            "post.html",
+            "post.tera"
+        ]
+    );
+    let mut context = tera::Context::new();
+    context.insert("title", "Title");
+    assert_eq!(
+        templates
+            .render("post.html", &context)
+            .expect("should render"),
+        "<main>post: Title</main>\n"
+    );
+    assert_eq!(
+        templates
+            .render("page.html", &context)
+            .expect("should render"),
+        "html page: Title\n"
+    );
+}
+
+#[test]
+fn test_render_dir_with_theme() {
+    let root_dir = Path::new("tests/it/testdata/theme_site");
+    let output_dir = env::temp_dir().join("kalamos_test_theme_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    let mut written = render::render_dir(root_dir, &output_dir).expect("should render");
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            Path::new("index.html"),
+            Path::new("logo.txt"),
+            Path::new("style.css")
+        ]
+    );
+    // the theme's default.html extends the site's base.html, which overrides the theme's
+    let index = fs::read_to_string(output_dir.join("index.html")).expect("should read");
+    assert_eq!(index, "<site>theme: Home</site>\n");
+    // the site's static files replace the theme's, and the rest of the theme's are copied
+    let style = fs::read_to_string(output_dir.join("style.css")).expect("should read");
+    assert_eq!(style, "site style\n");
+    let logo = fs::read_to_string(output_dir.join("logo.txt")).expect("should read");
+    assert_eq!(logo, "theme logo\n");
+}
+
+#[test]
+fn test_render_dir_dry_run() {
+    let root_dir = Path::new("tests/it/testdata/simple_site");
+    let output_dir = env::temp_dir().join("kalamos_test_dry_run_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        dry_run: true,
+        ..render::RenderOptions::default()
+    };
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    assert!(!output_dir.exists());
+
+    // the planned files are the ones a real render writes, with their sizes
+    let mut written = render::render_dir(root_dir, &output_dir).expect("should render");
+    written.sort();
+    let planned = report
+        .planned
+        .iter()
+        .map(|f| f.path.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(planned, written);
+    for file in &report.planned {
+        let size = fs::metadata(output_dir.join(&file.path))
+            .expect("should exist")
+            .len();
+        assert_eq!(file.bytes, size, "{:?}", file.path);
+    }
+}
+
+#[test]
+fn test_render_dir_with_build_date() {
+    let root_dir = Path::new("tests/it/testdata/build_date_site");
+    let output_dir = env::temp_dir().join("kalamos_test_build_date_output");
+    let options = render::RenderOptions {
+        build_date: Some(render::parse_build_date("2024-12-01T09:30:00Z").expect("should parse")),
+        ..render::RenderOptions::default()
+    };
+    render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    let index = fs::read_to_string(output_dir.join("index.html")).expect("should read");
+    assert_eq!(index, "2024-12-01T09:30:00\n");
+}
+
+#[test_case("2024-12-01T09:30:00Z", Some("2024-12-01T09:30:00+00:00"); "utc")]
+#[test_case("2024-12-01T09:30:00-05:00", Some("2024-12-01T14:30:00+00:00"); "offset")]
+#[test_case("2024-12-01", Some("2024-12-01T00:00:00+00:00"); "date")]
+#[test_case("yesterday", None; "invalid")]
+#[test]
+fn test_parse_build_date(date: &str, expected: Option<&str>) {
+    let parsed = render::parse_build_date(date).ok().map(|d| d.to_rfc3339());
+    assert_eq!(parsed.as_deref(), expected);
+}
+
+#[test_case("1733045400", Some("2024-12-01T09:30:00+00:00"); "seconds")]
+#[test_case(" 0\n", Some("1970-01-01T00:00:00+00:00"); "whitespace")]
+#[test_case("2024-12-01", None; "not seconds")]
+#[test]
+fn test_parse_source_date_epoch(epoch: &str, expected: Option<&str>) {
+    let parsed = render::parse_source_date_epoch(epoch)
+        .ok()
+        .map(|d| d.to_rfc3339());
+    assert_eq!(parsed.as_deref(), expected);
+}
+
+#[test]
+fn test_render_dir_with_variants() {
+    let root_dir = Path::new("tests/it/testdata/variants_site");
+    let output_dir = env::temp_dir().join("kalamos_test_variants_output");
+    let mut written = render::render_dir(root_dir, &output_dir).expect("should render");
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            Path::new("2024/12/hello.html"),
+            Path::new("amp/2024/12/hello.html")
+        ]
+    );
+    let amp = fs::read_to_string(output_dir.join("amp/2024/12/hello.html")).expect("should read");
+    // the variant has the same context as the post, including its url
+    assert_eq!(amp, "amp: Hello /2024/12/hello.html\n");
+}
+
+#[test]
+fn test_render_dir_variant_collision() {
+    let root_dir = Path::new("tests/it/testdata/variant_collision_site");
+    let output_dir = env::temp_dir().join("kalamos_test_variant_collision_output");
+    let err = render::render_dir(root_dir, &output_dir).expect_err("should not render");
+    assert!(matches!(err, render::Error::OutputPathCollision(_, _, _)));
+}
+
+#[test_case("static/logo.svg", Ok("<div><svg><path d=\"M0 0\"/></svg></div>"); "file")]
+#[test_case("static/missing.svg", Err("static/missing.svg"); "missing file")]
+#[test_case("../simple_site/layouts/post.html", Err("outside of the site"); "outside of the site")]
+#[test]
+fn test_include_file(path: &str, expected: Result<&str, &str>) {
+    let root_dir = Path::new("tests/it/testdata/include_site");
+    let mut templates = render::load_templates(root_dir, &TemplatesConfig::default())
+        .expect("should load templates");
+    let template = format!("<div>{{{{ include_file(path=\"{}\") }}}}</div>", path);
+    let result = templates.render_str(&template, &tera::Context::new());
+    match (result, expected) {
+        (Ok(html), Ok(expected)) => assert_eq!(html, expected),
+        (Err(e), Err(expected)) => {
+            let message = format!("{:?}", e);
+            assert!(message.contains(expected), "{}", message);
+        }
+        (result, _) => panic!("unexpected result: {result:?}"),
+    }
+}
+
+#[test]
+fn test_render_dir_with_post_bundle() {
+    let root_dir = Path::new("tests/it/testdata/bundle_site");
+    let output_dir = env::temp_dir().join("kalamos_test_bundle_output");
+    let mut written = render::render_dir(root_dir, &output_dir).expect("should render");
+    written.sort();
+    // the bundle's assets are copied next to the post, so the relative image links work
+    assert_eq!(
+        written,
+        vec![
+            Path::new("2024/12/cat.png"),
+            Path::new("2024/12/images/dog.png"),
+            Path::new("2024/12/plain.html"),
+            Path::new("2024/12/trip.html"),
+        ]
+    );
+    let cat = fs::read_to_string(output_dir.join("2024/12/cat.png")).expect("should read");
+    assert_eq!(cat, "cat");
+}
+
+#[test]
+fn test_render_dir_with_aliases() {
+    let root_dir = Path::new("tests/it/testdata/aliases_site");
+    let output_dir = env::temp_dir().join("kalamos_test_aliases_output");
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &render::RenderOptions::default())
+            .expect("should render");
+    let mut written = report.written.clone();
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            Path::new("2024/12/new.html"),
+            Path::new("2024/12/old-slug/index.html"),
+            Path::new("about.html"),
+            Path::new("old.html"),
+        ]
+    );
+    let redirect = fs::read_to_string(output_dir.join("old.html")).expect("should read");
+    assert!(redirect.contains(
+        r#"<meta http-equiv="refresh" content="0; url=&#x2F;2024&#x2F;12&#x2F;new.html">"#
+    ));
+    // the page's alias would overwrite the post, so it is skipped
+    assert_eq!(
+        report.warnings,
+        vec![
+            r#"alias "/2024/12/new.html" of "pages/about.md" would overwrite "posts/2024-12-01-new.md", skipping it"#
+        ]
+    );
+    let post = fs::read_to_string(output_dir.join("2024/12/new.html")).expect("should read");
+    assert_eq!(post, "New\n");
+}
+
+#[test]
+fn test_render_dir_with_templates_hook() {
+    let root_dir = Path::new("tests/it/testdata/filters_site");
+    let output_dir = env::temp_dir().join("kalamos_test_filters_output");
+    let options = render::RenderOptions {
+        templates_hook: Some(|tera| {
+            tera.register_filter("currency", |value: &tera::Value, _: &_| {
+                let value = value.as_f64().unwrap_or_default();
+                Ok(tera::Value::String(format!("${:.2}", value)))
+            });
+            // a hook can replace the filters that come with kalamos
+            tera.register_filter("date_fmt", |_: &tera::Value, _: &_| {
+                Ok(tera::Value::String("replaced".to_string()))
+            });
+        }),
+        ..render::RenderOptions::default()
+    };
+    render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    let index = fs::read_to_string(output_dir.join("index.html")).expect("should read");
+    assert_eq!(index, "$1234.50 replaced\n");
+}
+
+#[test]
+fn test_render_dir_with_tag_feeds() {
+    let root_dir = Path::new("tests/it/testdata/tag_feeds_site");
+    let output_dir = env::temp_dir().join("kalamos_test_tag_feeds_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir_with_options(root_dir, &output_dir, &render::RenderOptions::default())
+        .expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(
+        read("atom.xml"),
+        "<feed><entry>Second</entry><entry>First</entry></feed>\n"
+    );
+    assert_eq!(
+        read("tags/rust/atom.xml"),
+        "<feed><title>rust</title><entry>Second</entry><entry>First</entry></feed>\n"
+    );
+    assert_eq!(
+        read("tags/web/atom.xml"),
+        "<feed><title>web</title><entry>First</entry></feed>\n"
+    );
+}
+
+#[test_case(false; "warnings")]
+#[test_case(true; "strict")]
+#[test]
+fn test_render_dir_tag_feed_slugs(strict: bool) {
+    let root_dir = Path::new("tests/it/testdata/tag_slugs_site");
+    let output_dir = env::temp_dir().join(format!("kalamos_test_tag_slugs_output_{}", strict));
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        strict,
+        ..Default::default()
+    };
+    let result = render::render_dir_with_options(root_dir, &output_dir, &options);
+    if strict {
+        assert!(result.is_err());
+        return;
+    }
+    let report = result.expect("should render");
+    assert_eq!(
+        report.warnings,
+        vec![
+            "tag slug collision: \"c\" is the slug of both \"C++\" and \"C\", so they share a feed",
+            "tag \"日本語\" has no slug, so it has no feed",
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(output_dir.join("tags/c/atom.xml")).expect("should read"),
+        "<feed><title>C++</title><entry>Second</entry><entry>First</entry></feed>\r\n"
+    );
+}
+
+#[test_case(false; "warnings")]
+#[test_case(true; "strict")]
+#[test]
+fn test_render_dir_schema_warnings(strict: bool) {
+    let root_dir = Path::new("tests/it/testdata/schema_site");
+    let output_dir = env::temp_dir().join(format!("kalamos_test_schema_output_{}", strict));
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        strict,
+        ..Default::default()
+    };
+    let result = render::render_dir_with_options(root_dir, &output_dir, &options);
+    if strict {
+        assert!(result.is_err());
+        return;
+    }
+    let report = result.expect("should render");
+    assert_eq!(
+        report.warnings,
+        vec![
+            "frontmatter of \"posts/2024-12-01-first.md\": `author` is required",
+            "frontmatter of \"pages/index.html\": `subtitle` should be of type string, not integer",
+        ]
+    );
+}
+
+#[test]
+fn test_render_dir_feed_limit() {
+    let root_dir = Path::new("tests/it/testdata/feed_limit_site");
+    let output_dir = env::temp_dir().join("kalamos_test_feed_limit_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(read("atom.xml"), "<feed><entry>Second</entry></feed>\n");
+    assert_eq!(read("rss.xml"), "<rss><item>Second</item></rss>\n");
+    assert_eq!(
+        read("tags/web/atom.xml"),
+        "<feed><title>web</title><entry>First</entry></feed>\n"
+    );
+    // pages that aren't feeds still get every post
+    assert_eq!(read("index.html"), "Second First \n");
+}
+
+#[test]
+fn test_render_dir_feed_limit_uses_frontmatter_dates() {
+    // first.md is dated before second.md by its file name, but after it by its frontmatter
+    let root_dir = Path::new("tests/it/testdata/feed_limit_date_site");
+    let output_dir = env::temp_dir().join("kalamos_test_feed_limit_date_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(read("atom.xml"), "<feed><entry>First</entry></feed>\n");
+    assert_eq!(read("rss.xml"), "<rss><item>First</item></rss>\n");
+    assert_eq!(read("index.html"), "First Second \n");
+}
+
+#[test]
+fn test_render_dir_since() {
+    let root_dir = Path::new("tests/it/testdata/since_site");
+    let output_dir = env::temp_dir().join("kalamos_test_since_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    fs::create_dir_all(output_dir.join("2023/06")).expect("should create dir");
+    fs::write(output_dir.join("2023/06/old.html"), "from an earlier build").expect("should write");
+    let options = render::RenderOptions {
+        clean: true,
+        since: Some(NaiveDate::from_ymd_opt(2024, 1, 1).expect("should be a date")),
+        ..render::RenderOptions::default()
+    };
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    assert_eq!(report.stats.posts, 1);
+    assert!(report.removed.is_empty());
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(read("2024/02/new.html"), "New\n");
+    assert_eq!(read("2023/06/old.html"), "from an earlier build");
+    assert_eq!(read("index.html"), "New Old \n");
+}
+
+#[test_case(false; "eager")]
+#[test_case(true; "lazy")]
+#[test]
+fn test_render_dir_lazy_bodies(lazy_bodies: bool) {
+    let root_dir = Path::new("tests/it/testdata/lazy_bodies_site");
+    let output_dir = env::temp_dir().join(format!("kalamos_test_lazy_bodies_{}", lazy_bodies));
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        lazy_bodies,
+        ..render::RenderOptions::default()
+    };
+    render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    insta::assert_snapshot!(
+        format!("lazy_bodies_{}", lazy_bodies),
+        [
+            read("2024/01/first.html"),
+            read("2024/02/second.html"),
+            read("index.html")
+        ]
+        .join("\n---\n")
+    );
+}
+
+#[test]
+fn test_render_dir_only_posts() {
+    let root_dir = Path::new("tests/it/testdata/since_site");
+    let output_dir = env::temp_dir().join("kalamos_test_only_posts_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        clean: true,
+        only_posts: Some(HashSet::from([PathBuf::from("posts/2023-06-01-old.md")])),
+        ..render::RenderOptions::default()
+    };
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    assert_eq!(report.stats.posts, 1);
+    assert!(output_dir.join("2023/06/old.html").is_file());
+    assert!(!output_dir.join("2024/02/new.html").exists());
+    assert_eq!(
+        fs::read_to_string(output_dir.join("index.html")).expect("should read"),
+        "New Old \n"
+    );
+}
+
+#[test]
+fn test_render_dir_base_template() {
+    let root_dir = Path::new("tests/it/testdata/base_template_site");
+    let output_dir = env::temp_dir().join("kalamos_test_base_template_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(
+        read("about.html"),
+        "<title>About</title><main><p>Hello <em>there</em></p>\n</main>\n"
+    );
+    assert_eq!(read("custom.html"), "custom: <p>Mine</p>\n");
+}
+
+#[test]
+fn test_render_dir_html_extension() {
+    let root_dir = Path::new("tests/it/testdata/htm_site");
+    let output_dir = env::temp_dir().join("kalamos_test_htm_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(read("2024/02/hello.htm"), "Hello\n");
+    assert_eq!(read("about.htm"), "About: <p>Hi</p>\n");
+    assert_eq!(read("index.htm"), "/2024/02/hello.htm \n");
+    assert!(!output_dir.join("2024/02/hello.html").exists());
+}
+
+#[test]
+fn test_render_dir_config_context() {
+    let root_dir = Path::new("tests/it/testdata/context_site");
+    let output_dir = env::temp_dir().join("kalamos_test_context_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(read("2024/02/hello.html"), "Hello G-12345\n");
+    assert_eq!(read("index.html"), "banner G-12345\n");
+}
+
+#[test]
+fn test_render_dir_line_endings() {
+    let root_dir = Path::new("tests/it/testdata/crlf_site");
+    let output_dir = env::temp_dir().join("kalamos_test_crlf_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    assert_eq!(
+        fs::read_to_string(output_dir.join("2024/02/hello.html")).expect("should read"),
+        "<h1>Hello</h1>\r\n<p>first</p>\r\n<p>second</p>\r\n"
+    );
+}
+
+#[test]
+fn test_render_dir_template_dir() {
+    let root_dir = Path::new("tests/it/testdata/since_site");
+    let output_dir = env::temp_dir().join("kalamos_test_template_dir_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        template_dir: Some(PathBuf::from("alt_layouts")),
+        ..render::RenderOptions::default()
+    };
+    render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    assert_eq!(
+        fs::read_to_string(output_dir.join("2024/02/new.html")).expect("should read"),
+        "alt: New\n"
+    );
+}
+
+#[test]
+fn test_render_dir_asset_integrity() {
+    let root_dir = Path::new("tests/it/testdata/sri_site");
+    let output_dir = env::temp_dir().join("kalamos_test_sri_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    assert_eq!(
+        fs::read_to_string(output_dir.join("index.html")).expect("should read"),
+        "<script src=\"/js/site.js\" integrity=\"sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO\"></script>\n"
+    );
+}
+
+#[test_case("../config.toml"; "parent directory")]
+#[test_case("js/missing.js"; "missing file")]
+#[test]
+fn test_asset_integrity_errors(path: &str) {
+    let function =
+        render::AssetIntegrity::new(vec![PathBuf::from("tests/it/testdata/sri_site/static")]);
+    let args = std::collections::HashMap::from([("path".to_string(), tera::Value::from(path))]);
+    assert!(tera::Function::call(&function, &args).is_err());
+}
+
+#[test]
+fn test_render_dir_fail_on_empty() {
+    let root_dir = env::temp_dir().join("kalamos_test_empty_site");
+    let output_dir = env::temp_dir().join("kalamos_test_empty_output");
+    let _ = fs::remove_dir_all(&root_dir);
+    fs::create_dir_all(&root_dir).expect("should create dir");
+    let options = render::RenderOptions {
+        fail_on_empty: true,
+        ..render::RenderOptions::default()
+    };
+    let result = render::render_dir_with_options(&root_dir, &output_dir, &options);
+    assert!(matches!(result, Err(render::Error::EmptyOutput(_))));
+    // an empty site is fine without the option
+    render::render_dir(&root_dir, &output_dir).expect("should render");
+    // and a site with a page is fine with it
+    render::render_dir_with_options(
+        Path::new("tests/it/testdata/minimal_site"),
+        &output_dir,
+        &options,
+    )
+    .expect("should render");
+}
+
+#[test]
+fn test_render_dir_date_format() {
+    let root_dir = Path::new("tests/it/testdata/date_format_site");
+    let output_dir = env::temp_dir().join("kalamos_test_date_format_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    assert_eq!(
+        fs::read_to_string(output_dir.join("2024/12/hello.html")).expect("should read"),
+        "December 1, 2024 | 01.12.24 | 09:30\n"
+    );
+}
+
+#[test_case(tera::Value::from("2024-12-01"), "%B %-d, %Y", Ok("December 1, 2024"); "date")]
+#[test_case(tera::Value::from("2024-12-01T09:30:00+01:00"), "%H:%M %z", Ok("09:30 +0100"); "datetime")]
+#[test_case(tera::Value::from("2024-12-01"), "%Q", Err(()); "invalid format")]
+#[test_case(tera::Value::from("yesterday"), "%Y", Err(()); "not a date")]
+#[test_case(tera::Value::from(2024), "%Y", Err(()); "not a string")]
+#[test]
+fn test_date_fmt(value: tera::Value, format: &str, expected: Result<&str, ()>) {
+    let args = std::collections::HashMap::from([("format".to_string(), tera::Value::from(format))]);
+    let formatted = render::date_fmt(&value, &args);
+    match expected {
+        Ok(expected) => assert_eq!(formatted.expect("should format"), expected),
+        Err(()) => assert!(formatted.is_err()),
+    }
+}
+
+#[test]
+fn test_render_dir_authors() {
+    let root_dir = Path::new("tests/it/testdata/authors_site");
+    let output_dir = env::temp_dir().join("kalamos_test_authors_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    render::render_dir(root_dir, &output_dir).expect("should render");
+    let read = |path: &str| fs::read_to_string(output_dir.join(path)).expect("should read");
+    assert_eq!(read("2024/12/second.html"), "Second by Jane Doe, Sam\n");
+    assert_eq!(
+        read("authors/jane-doe/index.html"),
+        "Jane Doe (Writes about Rust.): Second;First;\n"
+    );
+    assert_eq!(read("authors/sam/index.html"), "Sam (): Second;\n");
+    assert!(!output_dir.join("authors/third").exists());
+}
+
+#[test_case("pages/_site"; "in the pages directory")]
+#[test_case("posts/_site"; "in the posts directory")]
+#[test_case("static/_site"; "in the static directory")]
+#[test]
+fn test_render_dir_output_inside_input(output: &str) {
+    let root_dir = env::temp_dir().join(format!(
+        "kalamos_test_nested_output_{}",
+        output.replace('/', "_")
+    ));
+    let _ = fs::remove_dir_all(&root_dir);
+    util::copy_dir(Path::new("tests/it/testdata/since_site"), &root_dir).expect("should copy");
+    fs::create_dir_all(root_dir.join("static")).expect("should create dir");
+    fs::write(root_dir.join("static/style.css"), "body {}").expect("should write");
+    let output_dir = root_dir.join(output);
+    let mut first = render::render_dir(&root_dir, &output_dir).expect("should render");
+    first.sort();
+    // the second build must not read the files written by the first one
+    let mut second = render::render_dir(&root_dir, &output_dir).expect("should render");
+    second.sort();
+    assert_eq!(first, second);
+    assert!(!output_dir.join("_site").exists());
+}
+
+#[test]
+fn test_render_dir_checksums() {
+    let root_dir = Path::new("tests/it/testdata/since_site");
+    let output_dir = env::temp_dir().join("kalamos_test_checksums_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    let options = render::RenderOptions {
+        checksums: true,
+        ..render::RenderOptions::default()
+    };
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &options).expect("should render");
+    assert_eq!(report.checksums.len(), report.written.len());
+    // the post renders to "New\n"
+    assert_eq!(
+        report
+            .checksums
+            .get(Path::new("2024/02/new.html"))
+            .map(String::as_str),
+        Some("b3db44e0b62512e27505251390a09877")
+    );
+}
+
+#[test]
+fn test_render_dir_sections() {
+    let root_dir = Path::new("tests/it/testdata/sections_site");
+    let output_dir = env::temp_dir().join("kalamos_test_sections_output");
+    let _ = fs::remove_dir_all(&output_dir);
+    let report =
+        render::render_dir_with_options(root_dir, &output_dir, &render::RenderOptions::default())
+            .expect("should render");
+    // the _index.md isn't a post
+    assert_eq!(report.stats.posts, 3);
+    assert_eq!(
+        fs::read_to_string(output_dir.join("tutorials/index.html")).expect("should read"),
+        "Tutorials: Step by step\n<p>Learn things.</p>\nRust Intro \n3 posts\n"
+    );
+    // notes has no _index.md
+    assert!(!output_dir.join("notes/index.html").exists());
 }
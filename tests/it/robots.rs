@@ -0,0 +1,50 @@
+//! Tests for the robots module
+use kalamos::{
+    config::{Config, RobotsConfig},
+    robots,
+};
+use simple_test_case::test_case;
+use std::path::PathBuf;
+
+#[test]
+fn test_robots_txt_not_configured() {
+    let config = Config::default();
+    assert_eq!(robots::robots_txt(&config, &[]), None);
+}
+
+#[test_case(None, vec![], vec![], "User-agent: *\nDisallow:\n"; "empty disallow list")]
+#[test_case(None, vec!["/drafts/", "/private/"], vec![], "User-agent: *\nDisallow: /drafts/\nDisallow: /private/\n"; "disallow list")]
+#[test_case(Some("https://example.com/"), vec![], vec!["sitemap.xml"], "User-agent: *\nDisallow:\n\nSitemap: https://example.com/sitemap.xml\n"; "with sitemap")]
+#[test_case(Some("https://example.com"), vec![], vec!["index.html"], "User-agent: *\nDisallow:\n"; "base url without sitemap")]
+#[test]
+fn test_robots_txt(
+    base_url: Option<&str>,
+    disallow: Vec<&str>,
+    written: Vec<&str>,
+    expected: &str,
+) {
+    let config = Config {
+        base_url: base_url.map(|s| s.to_string()),
+        robots: Some(RobotsConfig {
+            disallow: disallow.iter().map(|s| s.to_string()).collect(),
+        }),
+        ..Config::default()
+    };
+    let written = written.iter().map(PathBuf::from).collect::<Vec<_>>();
+    assert_eq!(
+        robots::robots_txt(&config, &written),
+        Some(expected.to_string())
+    );
+}
+
+#[test]
+fn test_robots_txt_keeps_existing_file() {
+    let config = Config {
+        base_url: Some("https://example.com".to_string()),
+        ..Config::default()
+    };
+    assert_eq!(
+        robots::robots_txt(&config, &[PathBuf::from("robots.txt")]),
+        None
+    );
+}
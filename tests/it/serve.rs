@@ -0,0 +1,252 @@
+//! Tests for the serve module
+use kalamos::{
+    cli_error::CommandError,
+    config::{Config, ServeConfig},
+    serve,
+};
+use simple_test_case::test_case;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// A temp dir for one case of a test, so that the cases, which run in parallel, don't share files
+fn case_dir(test: &str, case: &str) -> PathBuf {
+    let case = case.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    env::temp_dir().join(format!("kalamos_test_{}_{}", test, case))
+}
+
+#[test_case("feed", "atom.xml"; "route")]
+#[test_case("feed/", "atom.xml"; "route with trailing slash")]
+#[test_case("sitemap", "sitemap.xml"; "route with leading slashes in config")]
+#[test_case("about.html", "about.html"; "no route")]
+#[test]
+fn test_resolve_route(path: &str, expected: &str) {
+    let serve_config = ServeConfig {
+        routes: HashMap::from([
+            ("feed".to_string(), "atom.xml".to_string()),
+            ("/sitemap".to_string(), "/sitemap.xml".to_string()),
+        ]),
+        ..ServeConfig::default()
+    };
+    assert_eq!(serve::resolve_route(path, &serve_config), expected);
+}
+
+#[test]
+fn test_duplicate_routes_are_rejected() {
+    // "/docs" and "docs/" both match /docs, so neither can win
+    let error = Config::load(Path::new("tests/it/testdata/duplicate_route_site"))
+        .map_err(CommandError::from)
+        .expect_err("should fail");
+    assert_eq!(
+        error.to_string(),
+        "config error: config.toml: [serve] routes \"/docs\" and \"docs/\" are the same route"
+    );
+}
+
+#[test_case(vec!["index.html"], "", Some("index.html"); "default index")]
+#[test_case(vec!["index.html"], "docs", None; "no matching index")]
+#[test_case(vec!["index.html", "index.htm"], "docs", Some("docs/index.htm"); "alternate index")]
+#[test_case(vec!["index.es.html", "index.html"], "", Some("index.es.html"); "checked in order")]
+#[test_case(vec!["index.html"], "docs/index.htm", Some("docs/index.htm"); "file")]
+#[test_case(vec!["index.html"], "missing", None; "missing")]
+#[test]
+fn test_resolve_file(index_files: Vec<&str>, path: &str, expected: Option<&str>) {
+    let root = case_dir("resolve_file", &format!("{:?}_{}", index_files, path));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("docs")).expect("should create dir");
+    fs::write(root.join("index.html"), "home").expect("should write");
+    fs::write(root.join("index.es.html"), "inicio").expect("should write");
+    fs::write(root.join("docs/index.htm"), "docs").expect("should write");
+
+    let serve_config = ServeConfig {
+        index_files: index_files.into_iter().map(String::from).collect(),
+        ..ServeConfig::default()
+    };
+    assert_eq!(
+        serve::resolve_file(&root, path, &serve_config),
+        expected.map(|e| root.join(PathBuf::from(e)))
+    );
+}
+
+#[test_case(404, vec![], Some("404.html"); "default not found page")]
+#[test_case(404, vec![("404", "40x.html")], Some("40x.html"); "configured not found page")]
+#[test_case(500, vec![("500", "/50x.html")], Some("50x.html"); "configured page with leading slash")]
+#[test_case(500, vec![], None; "no page")]
+#[test_case(500, vec![("500", "missing.html")], None; "missing page")]
+#[test]
+fn test_resolve_error_page(status: u16, error_pages: Vec<(&str, &str)>, expected: Option<&str>) {
+    let root = case_dir(
+        "resolve_error_page",
+        &format!("{}_{:?}", status, error_pages),
+    );
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).expect("should create dir");
+    for page in ["404.html", "40x.html", "50x.html"] {
+        fs::write(root.join(page), page).expect("should write");
+    }
+
+    let serve_config = ServeConfig {
+        error_pages: error_pages
+            .into_iter()
+            .map(|(status, page)| (status.to_string(), page.to_string()))
+            .collect(),
+        ..ServeConfig::default()
+    };
+    assert_eq!(
+        serve::resolve_error_page(&root, status, &serve_config),
+        expected.map(|e| root.join(e))
+    );
+}
+
+#[test_case("style.css", Some("output/style.css"); "first root wins")]
+#[test_case("draft.css", Some("input/draft.css"); "falls through")]
+#[test_case("missing.css", None; "missing from every root")]
+#[test]
+fn test_resolve_file_in_roots(path: &str, expected: Option<&str>) {
+    let root = case_dir("resolve_file_in_roots", path);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("output")).expect("should create dir");
+    fs::create_dir_all(root.join("input")).expect("should create dir");
+    fs::write(root.join("output/style.css"), "built").expect("should write");
+    fs::write(root.join("input/style.css"), "source").expect("should write");
+    fs::write(root.join("input/draft.css"), "source").expect("should write");
+
+    let roots = vec![root.join("output"), root.join("input")];
+    assert_eq!(
+        serve::resolve_file_in_roots(&roots, path, &ServeConfig::default()),
+        expected.map(|e| root.join(e))
+    );
+}
+
+#[test_case(false, vec![("Cache-Control", "no-store"), ("Pragma", "no-cache")]; "no caching")]
+#[test_case(true, vec![]; "caching")]
+#[test]
+fn test_cache_headers(cache: bool, expected: Vec<(&str, &str)>) {
+    assert_eq!(serve::cache_headers(cache), expected);
+}
+
+#[test]
+fn test_access_log_line() {
+    assert_eq!(
+        serve::access_log_line("GET", "/missing.html", 404, 27, Duration::from_millis(3)),
+        "GET /missing.html 404 27 bytes 3ms"
+    );
+}
+
+#[test_case("docs", Some("output/docs"); "directory")]
+#[test_case("/docs/", Some("output/docs"); "slashes")]
+#[test_case("drafts", Some("input/drafts"); "falls through")]
+#[test_case("../output", None; "outside the root")]
+#[test_case("style.css", None; "file")]
+#[test]
+fn test_resolve_dir_in_roots(path: &str, expected: Option<&str>) {
+    let root = case_dir("resolve_dir_in_roots", path);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("output/docs")).expect("should create dir");
+    fs::create_dir_all(root.join("input/drafts")).expect("should create dir");
+    fs::write(root.join("output/style.css"), "built").expect("should write");
+
+    let roots = vec![root.join("output"), root.join("input")];
+    assert_eq!(
+        serve::resolve_dir_in_roots(&roots, path),
+        expected.map(|e| root.join(e))
+    );
+}
+
+#[test]
+fn test_directory_listing() {
+    let root = env::temp_dir().join("kalamos_test_directory_listing");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("docs/images")).expect("should create dir");
+    fs::write(root.join("docs/a<b>.txt"), "").expect("should write");
+    fs::write(root.join("docs/about.html"), "").expect("should write");
+
+    let listing =
+        serve::directory_listing(&root.join("docs"), "docs").expect("should list directory");
+    assert_eq!(
+        listing,
+        "<!DOCTYPE html>\n<html>\n<head><title>Index of &#x2F;docs&#x2F;</title></head>\n<body>\n\
+         <h1>Index of &#x2F;docs&#x2F;</h1>\n<ul>\n\
+         <li><a href=\"&#x2F;docs&#x2F;images&#x2F;\">images&#x2F;</a></li>\n\
+         <li><a href=\"&#x2F;docs&#x2F;a&lt;b&gt;.txt\">a&lt;b&gt;.txt</a></li>\n\
+         <li><a href=\"&#x2F;docs&#x2F;about.html\">about.html</a></li>\n\
+         </ul>\n</body>\n</html>\n"
+    );
+}
+
+#[test_case("gzip, deflate, br", "br", true; "listed")]
+#[test_case("gzip, deflate", "br", false; "not listed")]
+#[test_case("br;q=0, gzip", "br", false; "refused")]
+#[test_case("BR;q=0.5", "br", true; "case and weight")]
+#[test_case("*", "gzip", true; "wildcard")]
+#[test_case("*, gzip;q=0", "gzip", false; "wildcard with refusal")]
+#[test_case("", "gzip", false; "empty")]
+#[test]
+fn test_accepts_encoding(accept_encoding: &str, encoding: &str, expected: bool) {
+    assert_eq!(serve::accepts_encoding(accept_encoding, encoding), expected);
+}
+
+#[test_case("page.html", "gzip, br", Some(("page.html.br", "br")); "brotli preferred")]
+#[test_case("page.html", "gzip", Some(("page.html.gz", "gzip")); "gzip")]
+#[test_case("page.html", "deflate", None; "not accepted")]
+#[test_case("other.html", "br", None; "no variant")]
+#[test]
+fn test_precompressed_variant(path: &str, accept_encoding: &str, expected: Option<(&str, &str)>) {
+    let root = case_dir(
+        "precompressed_variant",
+        &format!("{}_{}", path, accept_encoding),
+    );
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).expect("should create dir");
+    for file in ["page.html", "page.html.br", "page.html.gz", "other.html"] {
+        fs::write(root.join(file), file).expect("should write");
+    }
+
+    assert_eq!(
+        serve::precompressed_variant(&root.join(path), accept_encoding),
+        expected.map(|(file, encoding)| (root.join(file), encoding))
+    );
+}
+
+#[test_case("html", "", Some("index.html"), Some("404.html"); "html")]
+#[test_case("htm", "", Some("index.htm"), Some("404.htm"); "htm")]
+#[test_case("", "", Some("index"), Some("404"); "no extension")]
+#[test_case("htm", "docs", Some("docs/index.htm"), Some("404.htm"); "subdirectory")]
+#[test]
+fn test_serve_config_with_html_extension(
+    html_extension: &str,
+    path: &str,
+    expected_index: Option<&str>,
+    expected_not_found: Option<&str>,
+) {
+    let root = case_dir(
+        "serve_html_extension",
+        &format!("{}_{}", html_extension, path),
+    );
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("docs")).expect("should create dir");
+    let index = |dir: &str| match html_extension {
+        "" => format!("{}index", dir),
+        extension => format!("{}index.{}", dir, extension),
+    };
+    fs::write(root.join(index("")), "home").expect("should write");
+    fs::write(root.join(index("docs/")), "docs").expect("should write");
+    let not_found = match html_extension {
+        "" => "404".to_string(),
+        extension => format!("404.{}", extension),
+    };
+    fs::write(root.join(&not_found), "not found").expect("should write");
+
+    let serve_config = ServeConfig::default().with_html_extension(html_extension);
+    assert_eq!(
+        serve::resolve_file(&root, path, &serve_config),
+        expected_index.map(|e| root.join(e))
+    );
+    assert_eq!(
+        serve::resolve_error_page(&root, 404, &serve_config),
+        expected_not_found.map(|e| root.join(e))
+    );
+}
@@ -0,0 +1,66 @@
+//! Tests for the sitemap module
+use kalamos::{
+    config::SitemapConfig,
+    page::{Page, PageFile},
+    post::{Post, PostFile},
+    render::{Error as RenderError, Render},
+    sitemap,
+};
+use simple_test_case::test_case;
+use std::path::PathBuf;
+use tera::Tera;
+
+fn post(file: &str, frontmatter: &str) -> Post {
+    let post_file = PostFile::try_from(PathBuf::from(file)).expect("should parse");
+    let content = format!("+++\ntitle = \"t\"\n{}+++\nbody", frontmatter);
+    Post::from_content(post_file, &content, &Tera::default()).expect("should parse")
+}
+
+fn page(file: &str, frontmatter: &str) -> Page {
+    let page_file = PageFile::try_from(PathBuf::from(file)).expect("should parse");
+    let content = format!("+++\ntitle = \"t\"\n{}+++\nbody", frontmatter);
+    Page::from_content(page_file, &content, &Tera::default()).expect("should parse")
+}
+
+#[test_case(vec![], vec!["/2024/01/a.html", "/about.html", "/tags/rust.html"]; "default")]
+#[test_case(vec!["tags/**"], vec!["/2024/01/a.html", "/about.html"]; "excluded glob")]
+#[test_case(vec!["/2024/**"], vec!["/about.html", "/tags/rust.html"]; "leading slash")]
+#[test]
+fn test_entries(exclude: Vec<&str>, expected: Vec<&str>) {
+    let posts = vec![
+        post("posts/2024-01-01-a.md", ""),
+        post("posts/2024-01-02-b.md", "noindex = true\n"),
+        post("posts/2024-01-03-c.md", "sitemap = false\n"),
+    ];
+    let pages = vec![
+        page("pages/about.md", ""),
+        page("pages/tags/rust.md", ""),
+        page("pages/404.md", ""),
+        page("pages/atom.xml", ""),
+        page("pages/secret.md", "sitemap = false\n"),
+    ];
+    let config = SitemapConfig {
+        exclude: exclude.iter().map(|s| s.to_string()).collect(),
+    };
+    let entries = sitemap::entries(&posts, &pages, &config).expect("should list entries");
+    let urls = entries
+        .iter()
+        .map(|e| e.url.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(urls, expected);
+    // only posts have a lastmod
+    assert!(entries
+        .iter()
+        .all(|e| e.lastmod.is_some() == e.url.starts_with("/2024")));
+}
+
+#[test]
+fn test_entries_invalid_glob() {
+    let config = SitemapConfig {
+        exclude: vec!["tags/[".to_string()],
+    };
+    assert!(matches!(
+        sitemap::entries(&[], &[], &config),
+        Err(RenderError::InvalidGlob(_, _))
+    ));
+}
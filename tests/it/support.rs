@@ -1,3 +1,4 @@
+use regex::Regex;
 use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
 use std::fs;
@@ -9,7 +10,16 @@ use walkdir::WalkDir;
 /// Create a YAML representation of a directory.
 /// binary files are represented as a hash of their contents.
 /// text files are represented by their contents.
-pub fn dir_to_yaml(root_dir: &Path) -> Result<BTreeMap<PathBuf, String>, Error> {
+/// Files whose path relative to root_dir matches any of the ignore_patterns regexes are left out.
+/// This is useful for files that change on every build, like a feed with a build timestamp.
+pub fn dir_to_yaml(
+    root_dir: &Path,
+    ignore_patterns: &[&str],
+) -> Result<BTreeMap<PathBuf, String>, Error> {
+    let ignore_patterns = ignore_patterns
+        .iter()
+        .map(|p| Regex::new(p))
+        .collect::<Result<Vec<_>, _>>()?;
     let files = WalkDir::new(root_dir)
         .into_iter()
         .filter_map(|e| e.ok())
@@ -17,7 +27,14 @@ pub fn dir_to_yaml(root_dir: &Path) -> Result<BTreeMap<PathBuf, String>, Error>
         .map(|e| e.path().to_path_buf())
         .map(|p| yaml_for_file(&p, root_dir))
         .collect::<Result<Vec<_>, _>>()?;
-    let hashmap = files.into_iter().collect::<BTreeMap<_, _>>();
+    let hashmap = files
+        .into_iter()
+        .filter(|(path, _)| {
+            !ignore_patterns
+                .iter()
+                .any(|re| re.is_match(&path.to_string_lossy()))
+        })
+        .collect::<BTreeMap<_, _>>();
     Ok(hashmap)
 }
 
@@ -29,6 +46,8 @@ pub enum Error {
     Serde(#[from] serde_yaml::Error),
     #[error("strip prefix error")]
     StripPrefix(#[from] std::path::StripPrefixError),
+    #[error("regex error")]
+    Regex(#[from] regex::Error),
 }
 
 fn yaml_for_file(path: &Path, root_dir: &Path) -> Result<(PathBuf, String), Error> {
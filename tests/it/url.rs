@@ -0,0 +1,33 @@
+//! Tests for the url module
+use chrono::NaiveDate;
+use kalamos::url;
+use simple_test_case::test_case;
+use std::path::{Path, PathBuf};
+
+#[test_case(url::DEFAULT_POST_PATTERN, "/2024/03/my-post.html"; "default pattern")]
+#[test_case("/:year/:month/:day/:slug/", "/2024/03/09/my-post/index.html"; "pretty url")]
+#[test_case("/blog/:slug.html", "/blog/my-post.html"; "no date")]
+#[test]
+fn test_post_url(pattern: &str, expected: &str) {
+    let date = NaiveDate::from_ymd_opt(2024, 3, 9).expect("should be a date");
+    let url = url::post_url(date, "my-post", pattern).expect("should build url");
+    assert_eq!(url, PathBuf::from(expected));
+}
+
+#[test_case("about.md", "html", "/about.html"; "markdown page")]
+#[test_case("blog/atom.xml", "xml", "/blog/atom.xml"; "nested page")]
+#[test]
+fn test_page_url(relative_path: &str, extension: &str, expected: &str) {
+    let url = url::page_url(Path::new(relative_path), extension);
+    assert_eq!(url, PathBuf::from(expected));
+}
+
+#[test_case("/about-us.html", Some("about-us.html"); "file")]
+#[test_case("/blog/", Some("blog/index.html"); "directory")]
+#[test_case("/../outside.html", None; "outside the output directory")]
+#[test_case("", None; "empty")]
+#[test]
+fn test_output_path_from_permalink(permalink: &str, expected: Option<&str>) {
+    let output_path = url::output_path_from_permalink(permalink).ok();
+    assert_eq!(output_path, expected.map(PathBuf::from));
+}
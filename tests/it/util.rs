@@ -0,0 +1,184 @@
+//! Tests for the util module
+use kalamos::{
+    config::{LineEndings, MimeConfig},
+    render, util,
+};
+use simple_test_case::test_case;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[test_case("site.webmanifest", "application/manifest+json"; "overridden extension")]
+#[test_case("SITE.WEBMANIFEST", "application/manifest+json"; "override is case insensitive")]
+#[test_case("index.html", "text/html"; "guessed")]
+#[test_case("style.css", "text/css"; "invalid override falls back to guess")]
+#[test_case("README", "text/plain"; "no extension")]
+#[test]
+fn test_mime_type(path: &str, expected: &str) {
+    let mime_config = MimeConfig {
+        overrides: HashMap::from([
+            (
+                "webmanifest".to_string(),
+                "application/manifest+json".to_string(),
+            ),
+            ("css".to_string(), "not a mime type".to_string()),
+        ]),
+    };
+    let mime_type = util::mime_type(Path::new(path), &mime_config);
+    assert_eq!(mime_type.essence_str(), expected);
+}
+
+#[test_case("my-post", "my-post"; "slug")]
+#[test_case("C++ & Rust!", "c-rust"; "unusual characters")]
+#[test_case("2024-in-review", "post-2024-in-review"; "leading digit")]
+#[test_case("_draft", "post-_draft"; "leading underscore")]
+#[test_case("!!!", "post"; "nothing left")]
+#[test]
+fn test_html_id(text: &str, expected: &str) {
+    assert_eq!(util::html_id(text), expected);
+}
+
+#[test_case("a\nb\n", LineEndings::Lf, "a\nb\n"; "already lf")]
+#[test_case("a\r\nb\rc\n", LineEndings::Lf, "a\nb\nc\n"; "mixed to lf")]
+#[test_case("a\r\nb\n", LineEndings::Crlf, "a\r\nb\r\n"; "mixed to crlf")]
+#[test_case("\u{feff}<p>hi</p>\n", LineEndings::Lf, "<p>hi</p>\n"; "byte order mark")]
+#[test]
+fn test_normalize_output(content: &str, line_endings: LineEndings, expected: &str) {
+    assert_eq!(util::normalize_output(content, line_endings), expected);
+}
+
+#[test]
+fn test_sri_hash() {
+    assert_eq!(
+        util::sri_hash(b"alert('Hello, world.');"),
+        "sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO"
+    );
+}
+
+#[test_case("Hello World", "hello-world"; "spaces")]
+#[test_case("  --Trim me--  ", "trim-me"; "leading and trailing separators")]
+#[test]
+fn test_slugify(slug: &str, expected: &str) {
+    assert_eq!(util::slugify(slug).expect("should slugify"), expected);
+}
+
+#[test_case("tests/it/testdata", ""; "existing")]
+#[test_case("tests/it/testdata/../testdata/uncreated_dir/sub", "uncreated_dir/sub"; "missing")]
+#[test]
+fn test_canonicalize_uncreated(path: &str, rest: &str) {
+    let testdata = Path::new("tests/it/testdata")
+        .canonicalize()
+        .expect("should canonicalize");
+    let canonical = util::canonicalize_uncreated(Path::new(path)).expect("should canonicalize");
+    assert_eq!(canonical, testdata.join(rest));
+    // nothing is created
+    assert!(!Path::new("tests/it/testdata/uncreated_dir").exists());
+}
+
+#[test]
+fn test_image_sources() {
+    let html =
+        r#"<p><img src="/a.png" alt="a"><IMG alt='b' SRC='b.png'><image-gallery src="c.png"></p>"#;
+    assert_eq!(util::image_sources(html), vec!["/a.png", "b.png"]);
+}
+
+#[test_case("/blog/post.html", "/images/a.png", Some("images/a.png"); "absolute")]
+#[test_case("/blog/post.html", "a.png", Some("blog/a.png"); "relative")]
+#[test_case("/blog/post.html", "../../a.png#top", Some("a.png"); "parent dirs stop at the root")]
+#[test_case("/blog/post.html", "https://example.com/a.png", None; "remote")]
+#[test_case("/blog/post.html", "//example.com/a.png", None; "protocol relative")]
+#[test_case("/blog/post.html", "data:image/png;base64,abc", None; "data uri")]
+#[test_case("/blog/post.html", "my%20photo.png", Some("blog/my photo.png"); "percent encoded")]
+#[test_case("/blog/post.html", "a&amp;b.png?v=1&amp;w=2", Some("blog/a&b.png"); "html entities")]
+#[test_case("/blog/post.html", "a%3Fb.png", Some("blog/a?b.png"); "encoded question mark")]
+#[test]
+fn test_local_image_path(page_url: &str, src: &str, expected: Option<&str>) {
+    assert_eq!(
+        util::local_image_path(Path::new(page_url), src),
+        expected.map(PathBuf::from)
+    );
+}
+
+#[test_case("assets/logo.png", "static/logo.png"; "mapped")]
+#[test_case("assets/icons/rss.png", "icons/rss.png"; "longest prefix wins")]
+#[test_case("assetsfile.png", "assetsfile.png"; "only whole directories match")]
+#[test_case("css/site.css", "css/site.css"; "unmapped")]
+#[test]
+fn test_map_destination(path: &str, expected: &str) {
+    let map = HashMap::from([
+        ("assets".to_string(), "static".to_string()),
+        ("assets/icons/".to_string(), "/icons".to_string()),
+    ]);
+    let destination = util::map_destination(Path::new(path), &map).expect("should map");
+    assert_eq!(destination, PathBuf::from(expected));
+}
+
+#[test]
+fn test_map_destination_rejects_parent_dirs() {
+    let map = HashMap::from([("assets".to_string(), "../outside".to_string())]);
+    let res = util::map_destination(Path::new("assets/logo.png"), &map);
+    assert!(matches!(res, Err(render::Error::InvalidCopyMap(_, _))));
+}
+
+#[test]
+fn test_copy_dir_with_map_conflict() {
+    let src = env::temp_dir().join("kalamos_test_copy_conflict_src");
+    let dst = env::temp_dir().join("kalamos_test_copy_conflict_dst");
+    let _ = fs::remove_dir_all(&src);
+    let _ = fs::remove_dir_all(&dst);
+    fs::create_dir_all(src.join("assets")).expect("should create dir");
+    fs::create_dir_all(src.join("static")).expect("should create dir");
+    fs::write(src.join("assets/logo.png"), "a").expect("should write");
+    fs::write(src.join("static/logo.png"), "b").expect("should write");
+
+    let map = HashMap::from([("assets".to_string(), "static".to_string())]);
+    let res = util::copy_dir_with_map(&src, &dst, &map, false);
+    assert!(
+        matches!(res, Err(render::Error::CopyConflict(ref destination, _, _)) if destination == Path::new("static/logo.png"))
+    );
+    assert!(!dst.join("static/logo.png").exists());
+}
+
+#[test]
+fn test_write_if_changed() {
+    let dir = env::temp_dir().join("kalamos_test_write_if_changed");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("should create dir");
+    let path = dir.join("atom.xml");
+
+    assert!(util::write_if_changed(&path, b"<feed/>").expect("should write"));
+    let modified = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .expect("mtime");
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert!(!util::write_if_changed(&path, b"<feed/>").expect("should skip"));
+    let unchanged = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .expect("mtime");
+    assert_eq!(modified, unchanged);
+
+    assert!(util::write_if_changed(&path, b"<feed></feed>").expect("should write"));
+    assert_eq!(fs::read(&path).expect("should read"), b"<feed></feed>");
+}
+
+#[test]
+fn test_copy_if_changed() {
+    let dir = env::temp_dir().join("kalamos_test_copy_if_changed");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("should create dir");
+    let src = dir.join("logo.svg");
+    let dst = dir.join("copy.svg");
+    fs::write(&src, "<svg/>").expect("should write");
+
+    assert!(util::copy_if_changed(&src, &dst, false).expect("should copy"));
+    assert!(!util::copy_if_changed(&src, &dst, false).expect("should skip"));
+    assert!(util::copy_if_changed(&src, &dst, true).expect("should force the copy"));
+
+    // the same size, but changed after the copy was made
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fs::write(&src, "<SVG/>").expect("should write");
+    assert!(util::copy_if_changed(&src, &dst, false).expect("should copy"));
+    assert_eq!(fs::read_to_string(&dst).expect("should read"), "<SVG/>");
+}
@@ -0,0 +1,83 @@
+//! Tests for the watch module
+use kalamos::{
+    render::RenderOptions,
+    watch::{self, RenderScope},
+};
+use notify::{Event, EventKind};
+use simple_test_case::test_case;
+use std::{
+    collections::{BTreeSet, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+#[test_case(vec!["posts/2024-12-01-a.md"], Some(vec!["posts/2024-12-01-a.md"]); "one post")]
+#[test_case(vec!["posts/2024-12-01-a.md", "posts/en/2024-12-02-b.markdown"], Some(vec!["posts/2024-12-01-a.md", "posts/en/2024-12-02-b.markdown"]); "several posts")]
+#[test_case(vec!["posts/2024-12-01-a.md", "layouts/post.html"], None; "post and template")]
+#[test_case(vec!["config.toml"], None; "config")]
+#[test_case(vec!["pages/about.md"], None; "page")]
+#[test_case(vec!["posts/2024-12-01-a.md.json"], None; "sidecar")]
+#[test_case(vec!["posts/2024-12-01-a/photo.png"], None; "bundle asset")]
+#[test]
+fn test_render_scope(changed: Vec<&str>, expected: Option<Vec<&str>>) {
+    let input_dir = Path::new("/site");
+    let changed = changed
+        .into_iter()
+        .map(|p| input_dir.join(p))
+        .collect::<BTreeSet<_>>();
+    let expected = match expected {
+        Some(posts) => {
+            RenderScope::Posts(posts.into_iter().map(PathBuf::from).collect::<HashSet<_>>())
+        }
+        None => RenderScope::Full,
+    };
+    assert_eq!(watch::render_scope(input_dir, &changed), expected);
+}
+
+#[test_case("simple_site", true; "render succeeds")]
+#[test_case("bad_config_site", false; "render fails")]
+#[test]
+fn test_render_on_changes(site: &str, succeeds: bool) {
+    let input_dir = Path::new("tests/it/testdata").join(site);
+    let output_dir = env::temp_dir().join(format!("kalamos_test_watch_output_{}", site));
+    let _ = fs::remove_dir_all(&output_dir);
+    fs::create_dir_all(&output_dir).expect("should create dir");
+    let changes = vec![
+        input_dir.join("config.toml"),
+        input_dir.join("pages/index.html"),
+        // written by the previous render, so it must not trigger another one
+        output_dir.join("index.html"),
+    ];
+
+    let (tx, rx) = mpsc::channel();
+    let sender = thread::spawn(move || {
+        for path in changes {
+            tx.send(Ok(Event::new(EventKind::Any).add_path(path)))
+                .expect("should send");
+        }
+        // wait out the debounce, so the changes are rendered before the channel is closed
+        thread::sleep(Duration::from_millis(500));
+    });
+    let mut renders = vec![];
+    watch::render_on_changes(
+        rx,
+        &input_dir,
+        &output_dir,
+        &RenderOptions::default(),
+        |changed| renders.push(changed.to_vec()),
+    );
+    sender.join().expect("should join");
+
+    let expected = if succeeds {
+        vec![vec![
+            input_dir.join("config.toml"),
+            input_dir.join("pages/index.html"),
+        ]]
+    } else {
+        vec![]
+    };
+    assert_eq!(renders, expected);
+}